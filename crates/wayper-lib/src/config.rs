@@ -1,11 +1,18 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     path::{Path, PathBuf},
+    time::Duration,
 };
 
 use color_eyre::{Result, eyre::eyre};
 use serde::Deserialize;
 
+use crate::{
+    easing::Easing,
+    fill::{Background, Fill},
+    palette::Rgb,
+};
+
 #[derive(Clone, Debug, Default)]
 pub struct Config {
     pub default_profile: String,
@@ -14,6 +21,13 @@ pub struct Config {
     pub path: Option<PathBuf>,
     pub transition: Option<TransitionConfig>,
     pub transitions_enabled: Option<bool>,
+    pub fallback: Option<Fallback>,
+    pub notifications: NotificationsConfig,
+    pub hooks: HooksConfig,
+    /// Output whose wallpaper drives the templates, every output if unset
+    pub primary_output: Option<String>,
+    pub templates: Vec<TemplateConfig>,
+    pub animation: AnimationConfig,
 }
 
 impl Config {
@@ -93,16 +107,50 @@ impl Profiles {
     }
 }
 
+/// A file rendered from the palette of the wallpaper, see `[[template]]`
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct TemplateConfig {
+    /// The template to read
+    pub input: PathBuf,
+    /// Where the rendered file is written
+    pub output: PathBuf,
+    /// Command ran after the file is written, to reload the themed program
+    pub post_command: Option<String>,
+}
+
 /// Serializable output config
 #[derive(Deserialize, Clone, Debug, PartialEq, Default)]
 pub struct OutputConfig {
     pub duration: Option<u64>,
+    /// A directory, an image or a playlist. Not needed with a `source` or a `content`.
+    #[serde(default)]
     pub path: PathBuf,
+    /// Where images come from instead of `path`
+    pub source: Option<SourceConfig>,
+    /// What is drawn instead of the images, which are handed to it if there are any
+    pub content: Option<ContentConfig>,
     #[serde(default)]
     pub fit: FitMode,
+    /// Shown around images that don't cover the output: a color, a gradient, `auto` or `blur`
+    pub background: Option<Background>,
+    #[serde(default)]
+    pub align: Align,
+    /// Scale on top of the fit, above 1 crops the image
+    pub zoom: Option<f32>,
+    /// Slow movement of the images while they are shown
+    pub motion: Option<MotionConfig>,
+    /// Order images from a directory are shown in
+    #[serde(default)]
+    pub order: ImageOrder,
+    /// Deprecated: use `hooks.on_wallpaper_change` instead
     pub run_command: Option<String>,
     pub transition: Option<TransitionConfig>,
     pub transitions_enabled: Option<bool>,
+    /// Shown when there is no image that can be shown
+    pub fallback: Option<Fallback>,
+    /// Per output hooks, these take precedence over the global hooks
+    #[serde(default)]
+    pub hooks: HooksConfig,
 }
 
 impl OutputConfig {
@@ -125,11 +173,71 @@ impl OutputConfig {
             .or(global_config.transition.as_ref())
     }
 
+    pub fn exec_source(&self) -> Option<&ExecSourceConfig> {
+        match self.source.as_ref()? {
+            SourceConfig::Exec(exec) => Some(exec),
+            SourceConfig::Http(_) => None,
+        }
+    }
+
+    pub fn http_source(&self) -> Option<&HttpSourceConfig> {
+        match self.source.as_ref()? {
+            SourceConfig::Http(http) => Some(http),
+            SourceConfig::Exec(_) => None,
+        }
+    }
+
+    pub fn shader_content(&self) -> Option<&ShaderContentConfig> {
+        match self.content.as_ref()? {
+            ContentConfig::Shader(shader) => Some(shader),
+            _ => None,
+        }
+    }
+
+    /// The color or gradient shown instead of images
+    pub fn fill_content(&self) -> Option<Fill> {
+        match self.content.as_ref()? {
+            ContentConfig::Color { color } => Some(Fill::Color(*color)),
+            ContentConfig::Gradient { gradient } => Some(gradient.clone()),
+            ContentConfig::Shader(_) => None,
+        }
+    }
+
+    /// The order images are shown in, playlists keep the order of their entries
+    pub fn image_order(&self) -> ImageOrder {
+        match crate::playlist::is_playlist(&self.path) {
+            true => ImageOrder::Playlist,
+            false => self.order,
+        }
+    }
+
     pub fn is_transitions_enabled(&self, global_config: &Config) -> bool {
         self.transitions_enabled
             .or(global_config.transitions_enabled)
             .unwrap_or(true)
     }
+
+    /// What to show when there is no image that can be shown. The output's fallback takes
+    /// precedence over the global one.
+    pub fn get_fallback(&self, global_config: &Config) -> Fallback {
+        self.fallback
+            .clone()
+            .or_else(|| global_config.fallback.clone())
+            .unwrap_or_default()
+    }
+
+    /// Get the hook for an event. Output hooks take precedence over global hooks. The old
+    /// `run_command` counts as the output's wallpaper change hook.
+    pub fn get_hook(&self, event: HookEvent, global_config: &Config) -> Option<HookConfig> {
+        self.hooks
+            .get(event)
+            .cloned()
+            .or_else(|| match event {
+                HookEvent::WallpaperChange => self.run_command.clone().map(HookConfig::from),
+                _ => None,
+            })
+            .or_else(|| global_config.hooks.get(event).cloned())
+    }
 }
 
 fn default_profile() -> String {
@@ -143,6 +251,16 @@ struct ConfigReader {
     default_profile: String,
     pub transition: Option<TransitionConfig>,
     pub transitions_enabled: Option<bool>,
+    pub fallback: Option<Fallback>,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    pub primary_output: Option<String>,
+    #[serde(default, rename = "template")]
+    pub templates: Vec<TemplateConfig>,
+    #[serde(default)]
+    pub animation: AnimationConfig,
     #[serde(flatten)]
     pub outputs: HashMap<String, ProfileReader>,
 }
@@ -162,7 +280,11 @@ impl ConfigReader {
                         .for_each(|e| profiles.insert(Some(iden), e.0, e.1.clone()));
                 }
                 ProfileReader::Default(output_config) => {
-                    profiles.insert(Some(&self.default_profile), iden, output_config.clone());
+                    profiles.insert(
+                        Some(&self.default_profile),
+                        iden,
+                        output_config.as_ref().clone(),
+                    );
                 }
             }
         }
@@ -171,19 +293,374 @@ impl ConfigReader {
         config.default_profile = self.default_profile;
         config.transition = self.transition;
         config.transitions_enabled = self.transitions_enabled;
+        config.fallback = self.fallback;
+        config.notifications = self.notifications;
+        config.hooks = self.hooks;
+        config.primary_output = self.primary_output;
+        config.templates = self.templates;
+        config.animation = self.animation;
     }
 }
 
 /// Serializable struct to support 2 different config forms
-#[derive(Deserialize, Clone, Debug, PartialEq)]
-#[serde(untagged)]
+#[derive(Clone, Debug, PartialEq)]
 enum ProfileReader {
     Profile(HashMap<String, OutputConfig>),
-    Default(OutputConfig),
+    Default(Box<OutputConfig>),
+}
+
+impl<'de> Deserialize<'de> for ProfileReader {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // every field of an output is optional but one of these, so a profile can't be told
+        // apart from an output by its shape alone
+        let table = toml::Table::deserialize(deserializer)?;
+        let output = ["path", "source", "content"]
+            .iter()
+            .any(|key| table.contains_key(*key));
+        let value = toml::Value::Table(table);
+        match output {
+            true => value
+                .try_into()
+                .map(|output| Self::Default(Box::new(output))),
+            false => value.try_into().map(Self::Profile),
+        }
+        .map_err(serde::de::Error::custom)
+    }
 }
 impl Default for ProfileReader {
     fn default() -> Self {
-        Self::Default(OutputConfig::default())
+        Self::Default(Box::default())
+    }
+}
+
+/// What an output draws instead of showing its images
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum ContentConfig {
+    Shader(ShaderContentConfig),
+    /// A flat color, `#rrggbb`
+    Color {
+        color: Rgb,
+    },
+    /// A CSS style gradient, see [`Fill`]
+    Gradient {
+        gradient: Fill,
+    },
+}
+
+/// A WGSL file with a fullscreen `fs_main` fragment shader, reloaded when it changes
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct ShaderContentConfig {
+    #[serde(deserialize_with = "deserialize_home_path")]
+    pub shader: PathBuf,
+    /// Frames drawn per second
+    #[serde(default = "default_shader_fps")]
+    pub fps: u16,
+}
+
+fn default_shader_fps() -> u16 {
+    30
+}
+
+/// A slow movement of an image while it is shown, `"kenburns"` or a table with its settings
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(from = "MotionSetting")]
+pub struct MotionConfig {
+    pub motion_type: MotionType,
+    /// How far the image is zoomed in, in fractions of the output
+    pub amplitude: f32,
+    /// Frames drawn per second, few are needed for a slow movement
+    pub fps: u16,
+    pub easing: Easing,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MotionType {
+    /// Pan and zoom between two views of the image
+    KenBurns,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MotionSetting {
+    Type(MotionType),
+    Config {
+        #[serde(rename = "type")]
+        motion_type: MotionType,
+        #[serde(default = "default_motion_amplitude")]
+        amplitude: f32,
+        #[serde(default = "default_motion_fps")]
+        fps: u16,
+        #[serde(default = "default_motion_easing")]
+        easing: Easing,
+    },
+}
+
+impl From<MotionSetting> for MotionConfig {
+    fn from(setting: MotionSetting) -> Self {
+        match setting {
+            MotionSetting::Type(motion_type) => Self {
+                motion_type,
+                amplitude: default_motion_amplitude(),
+                fps: default_motion_fps(),
+                easing: default_motion_easing(),
+            },
+            MotionSetting::Config {
+                motion_type,
+                amplitude,
+                fps,
+                easing,
+            } => Self {
+                motion_type,
+                amplitude,
+                fps,
+                easing,
+            },
+        }
+    }
+}
+
+fn default_motion_amplitude() -> f32 {
+    0.15
+}
+
+fn default_motion_fps() -> u16 {
+    10
+}
+
+/// A steady movement, easing it would look like it stops between images
+fn default_motion_easing() -> Easing {
+    Easing::Linear
+}
+
+/// A path where a leading `~` stands for the home directory
+fn deserialize_home_path<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<PathBuf, D::Error> {
+    let path = PathBuf::deserialize(deserializer)?;
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => Ok(PathBuf::from(home).join(rest)),
+        _ => Ok(path),
+    }
+}
+
+/// Where the images of an output come from, instead of its `path`
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum SourceConfig {
+    Exec(ExecSourceConfig),
+    Http(HttpSourceConfig),
+}
+
+/// A command that picks the images. It prints a path, or a JSON line with a `path` and the
+/// settings of a playlist entry. The command may use `{output}`, `{profile}` and `{image}`.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct ExecSourceConfig {
+    pub exec: String,
+    /// Seconds before the command is killed
+    #[serde(default = "default_exec_timeout")]
+    pub timeout: u64,
+    /// Keep the command running and show every line it prints, instead of running it for every
+    /// image
+    #[serde(default)]
+    pub stream: bool,
+}
+
+fn default_exec_timeout() -> u64 {
+    10
+}
+
+/// Images downloaded from a URL returning an image, possibly through a redirect, or a JSON list
+/// of image URLs. Downloads are cached so the images stay available offline.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct HttpSourceConfig {
+    pub http: String,
+    /// How often the URL is fetched again, like "30m" or "1h"
+    #[serde(default = "default_http_refresh", with = "humantime_serde")]
+    pub refresh: Duration,
+    /// Seconds before a request is given up
+    #[serde(default = "default_http_timeout")]
+    pub timeout: u64,
+    /// Size of the download cache in megabytes, the least recently used images go first
+    #[serde(default = "default_cache_size_mb")]
+    pub cache_size_mb: u64,
+}
+
+fn default_http_refresh() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+fn default_http_timeout() -> u64 {
+    30
+}
+
+fn default_cache_size_mb() -> u64 {
+    512
+}
+
+/// Events that hooks can be attached to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum HookEvent {
+    WallpaperChange,
+    ProfileChange,
+    OutputAdded,
+    OutputRemoved,
+    TransitionEnd,
+}
+
+/// Commands to run on daemon events
+#[derive(Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub on_wallpaper_change: Option<HookConfig>,
+    pub on_profile_change: Option<HookConfig>,
+    pub on_output_added: Option<HookConfig>,
+    pub on_output_removed: Option<HookConfig>,
+    pub on_transition_end: Option<HookConfig>,
+}
+
+impl HooksConfig {
+    pub fn get(&self, event: HookEvent) -> Option<&HookConfig> {
+        match event {
+            HookEvent::WallpaperChange => self.on_wallpaper_change.as_ref(),
+            HookEvent::ProfileChange => self.on_profile_change.as_ref(),
+            HookEvent::OutputAdded => self.on_output_added.as_ref(),
+            HookEvent::OutputRemoved => self.on_output_removed.as_ref(),
+            HookEvent::TransitionEnd => self.on_transition_end.as_ref(),
+        }
+    }
+}
+
+/// A single hook. Can be written as just the command string, or as a table.
+///
+/// The command may use the placeholders `{image}`, `{output}`, `{profile}`, `{index}` and
+/// `{prev_image}`. The same values are exported as `WAYPER_*` environment variables.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(from = "HookReader")]
+pub struct HookConfig {
+    pub command: String,
+    /// Seconds before the command is killed
+    pub timeout: u64,
+    /// Wait this long for the event to settle before running, events in between replace the
+    /// pending one
+    pub debounce_ms: u64,
+    pub concurrency: HookConcurrency,
+}
+
+impl From<String> for HookConfig {
+    fn from(command: String) -> Self {
+        Self {
+            command,
+            timeout: default_hook_timeout(),
+            debounce_ms: 0,
+            concurrency: HookConcurrency::default(),
+        }
+    }
+}
+
+/// What to do when a hook fires while the previous run is still going
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HookConcurrency {
+    /// Kill the running command and start over
+    #[default]
+    Replace,
+    /// Run after the current one finishes
+    Queue,
+    /// Drop the new event
+    Skip,
+}
+
+/// Serializable struct to support both hook forms
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HookReader {
+    Command(String),
+    Full {
+        command: String,
+        #[serde(default = "default_hook_timeout")]
+        timeout: u64,
+        #[serde(default)]
+        debounce_ms: u64,
+        #[serde(default)]
+        concurrency: HookConcurrency,
+    },
+}
+
+impl From<HookReader> for HookConfig {
+    fn from(value: HookReader) -> Self {
+        match value {
+            HookReader::Command(command) => command.into(),
+            HookReader::Full {
+                command,
+                timeout,
+                debounce_ms,
+                concurrency,
+            } => Self {
+                command,
+                timeout,
+                debounce_ms,
+                concurrency,
+            },
+        }
+    }
+}
+
+fn default_hook_timeout() -> u64 {
+    30
+}
+
+/// Desktop notification settings. Nothing is sent unless `enabled` is set.
+/// Memory limits of animated images. Frames past either limit are dropped, the animation loops
+/// over the ones that fit.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct AnimationConfig {
+    pub max_frames: usize,
+    /// Megabytes of decoded frames, 4 bytes per pixel
+    pub max_mb: u64,
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        Self {
+            max_frames: 500,
+            max_mb: 512,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    pub enabled: bool,
+    /// Notify when the active profile changes
+    pub on_profile_change: bool,
+    /// Notify when an output shows a new image, with the image as the icon
+    pub on_image_change: bool,
+    /// Notify on errors, like an empty directory or an image that fails to decode
+    pub on_error: bool,
+    /// Minimum seconds between two image change notifications of the same output
+    pub image_change_interval: u64,
+    /// How long a notification stays up, in milliseconds. The server decides if unset.
+    pub timeout_ms: Option<u32>,
+    /// Add "Next" and "Favorite" buttons to image change notifications
+    pub actions: bool,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            on_profile_change: true,
+            on_image_change: true,
+            on_error: true,
+            image_change_interval: 300,
+            timeout_ms: None,
+            actions: true,
+        }
     }
 }
 
@@ -200,18 +677,45 @@ pub struct TransitionConfig {
 
     #[serde(default)]
     pub slide: SlideConfig,
+
+    #[serde(default)]
+    pub wipe: WipeConfig,
+
+    #[serde(default)]
+    pub circle: CircleConfig,
+
+    #[serde(default)]
+    pub dissolve: DissolveConfig,
+
+    #[serde(default)]
+    pub zoom: ZoomConfig,
+
+    #[serde(default)]
+    pub blur: BlurConfig,
+
+    #[serde(default)]
+    pub pixelate: PixelateConfig,
+
+    /// Values handed to a custom transition shader as the fields of `params`
+    #[serde(default)]
+    pub params: BTreeMap<String, f32>,
+
+    /// How the transition speeds up and slows down
+    #[serde(default)]
+    pub easing: Easing,
 }
 
 impl TransitionConfig {
     pub fn pick_random_type(&self) -> TransitionTypeEnum {
         use rand::seq::SliceRandom;
         match &self.transition_type {
-            TransitionSelection::Single(t) => *t,
+            TransitionSelection::Single(t) => t.clone(),
             TransitionSelection::Random(types) => {
                 let mut rng = rand::thread_rng();
-                *types
+                types
                     .choose(&mut rng)
-                    .unwrap_or(&TransitionTypeEnum::Crossfade)
+                    .cloned()
+                    .unwrap_or(TransitionTypeEnum::Crossfade)
             }
         }
     }
@@ -224,11 +728,20 @@ pub enum TransitionSelection {
     Random(Vec<TransitionTypeEnum>),
 }
 
-#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum TransitionTypeEnum {
     Crossfade,
     Slide,
+    Wipe,
+    #[serde(alias = "iris")]
+    Circle,
+    Dissolve,
+    Zoom,
+    Blur,
+    Pixelate,
+    /// A WGSL file with a `transition` function, `{ shader = "path.wgsl" }`
+    Shader(#[serde(deserialize_with = "deserialize_home_path")] PathBuf),
 }
 
 impl TransitionTypeEnum {
@@ -236,24 +749,136 @@ impl TransitionTypeEnum {
         match self {
             TransitionTypeEnum::Crossfade => 0,
             TransitionTypeEnum::Slide => 1,
+            TransitionTypeEnum::Wipe => 2,
+            TransitionTypeEnum::Circle => 3,
+            TransitionTypeEnum::Dissolve => 4,
+            TransitionTypeEnum::Zoom => 5,
+            TransitionTypeEnum::Blur => 6,
+            TransitionTypeEnum::Pixelate => 7,
+            TransitionTypeEnum::Shader(_) => 8,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransitionTypeEnum::Crossfade => "crossfade",
+            TransitionTypeEnum::Slide => "slide",
+            TransitionTypeEnum::Wipe => "wipe",
+            TransitionTypeEnum::Circle => "circle",
+            TransitionTypeEnum::Dissolve => "dissolve",
+            TransitionTypeEnum::Zoom => "zoom",
+            TransitionTypeEnum::Blur => "blur",
+            TransitionTypeEnum::Pixelate => "pixelate",
+            TransitionTypeEnum::Shader(_) => "shader",
         }
     }
 }
 
-#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[derive(Deserialize, Clone, Debug, PartialEq, Default)]
 pub struct SlideConfig {
     #[serde(default)]
     pub direction: Direction,
 }
 
-impl Default for SlideConfig {
+/// A straight edge moving across the output, the new image behind it
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct WipeConfig {
+    pub direction: Direction,
+    /// Width of the edge, in fractions of the distance it travels
+    pub softness: f32,
+}
+
+impl Default for WipeConfig {
     fn default() -> Self {
         Self {
             direction: Direction::default(),
+            softness: 0.1,
+        }
+    }
+}
+
+/// A circle growing from `center` until the new image fills the output
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct CircleConfig {
+    /// In fractions of the output, a random point when unset
+    pub center: Option<[f32; 2]>,
+    /// Width of the edge, in fractions of the output diagonal
+    pub softness: f32,
+}
+
+impl Default for CircleConfig {
+    fn default() -> Self {
+        Self {
+            center: None,
+            softness: 0.02,
+        }
+    }
+}
+
+/// The new image showing through noise, wherever it is below the progress
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct DissolveConfig {
+    /// The same seed dissolves the same way, a random one when unset
+    pub seed: Option<u32>,
+    /// Size of the noise in pixels, 1 for static
+    pub scale: f32,
+    pub softness: f32,
+}
+
+impl Default for DissolveConfig {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            scale: 24.0,
+            softness: 0.1,
         }
     }
 }
 
+/// The old image zooming in as it fades out, the new one settling from the same zoom
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ZoomConfig {
+    pub scale: f32,
+}
+
+impl Default for ZoomConfig {
+    fn default() -> Self {
+        Self { scale: 1.5 }
+    }
+}
+
+/// The old image blurring out, and the new one coming into focus
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct BlurConfig {
+    /// Blur radius halfway through, in pixels
+    pub radius: f32,
+}
+
+impl Default for BlurConfig {
+    fn default() -> Self {
+        Self { radius: 48.0 }
+    }
+}
+
+/// Pixels growing on the old image and shrinking on the new one
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct PixelateConfig {
+    /// Pixel size halfway through
+    pub size: f32,
+}
+
+impl Default for PixelateConfig {
+    fn default() -> Self {
+        Self { size: 64.0 }
+    }
+}
+
 #[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
 #[serde(rename_all = "kebab-case")]
 pub enum Direction {
@@ -294,6 +919,95 @@ pub enum FitMode {
     Tile,
 }
 
+/// Where an image sits in the space it leaves free, or which part of it is kept when it is
+/// cropped
+#[derive(Debug, Copy, Clone, Default, PartialEq, Deserialize)]
+pub struct Align {
+    #[serde(default)]
+    pub horizontal: HorizontalAlign,
+    #[serde(default)]
+    pub vertical: VerticalAlign,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HorizontalAlign {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerticalAlign {
+    Top,
+    #[default]
+    Center,
+    Bottom,
+}
+
+impl Align {
+    /// Fractions of the free or cropped space left of and above the image
+    pub fn fractions(&self) -> [f32; 2] {
+        let x = match self.horizontal {
+            HorizontalAlign::Left => 0.0,
+            HorizontalAlign::Center => 0.5,
+            HorizontalAlign::Right => 1.0,
+        };
+        let y = match self.vertical {
+            VerticalAlign::Top => 0.0,
+            VerticalAlign::Center => 0.5,
+            VerticalAlign::Bottom => 1.0,
+        };
+        [x, y]
+    }
+}
+
+/// What an output shows when it has no image that can be shown, either a `#rrggbb` color or
+/// the path to an image
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fallback {
+    Color(Rgb),
+    Image(PathBuf),
+}
+
+impl Default for Fallback {
+    fn default() -> Self {
+        Self::Color(Rgb::new(0, 0, 0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Fallback {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        if value.starts_with('#') {
+            Rgb::from_hex(&value)
+                .map(Self::Color)
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid color \"{value}\"")))
+        } else {
+            Ok(Self::Image(value.into()))
+        }
+    }
+}
+
+/// Order images of a directory are shown in
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageOrder {
+    #[default]
+    Shuffle,
+    /// By path
+    Name,
+    /// Most recently modified first
+    Newest,
+    /// Least recently modified first
+    Oldest,
+    /// The order of the entries of a playlist
+    #[serde(skip)]
+    Playlist,
+}
+
 impl FitMode {
     pub fn as_shader_u32(&self) -> u32 {
         match self {
@@ -306,10 +1020,10 @@ impl FitMode {
     }
 }
 
-fn default_duration() -> u32 {
+pub(crate) fn default_duration() -> u32 {
     2000
 }
-fn default_fps() -> u16 {
+pub(crate) fn default_fps() -> u16 {
     30
 }
 
@@ -352,9 +1066,18 @@ mod tests {
                 duration: Some(10),
                 path: "/home/user/wallpapers/personal".into(),
                 fit: FitMode::default(),
+                background: None,
+                align: Align::default(),
+                zoom: None,
+                motion: None,
+                order: ImageOrder::default(),
                 run_command: Some(String::from("matugen image {image}")),
                 transition: None,
                 transitions_enabled: None,
+                fallback: None,
+                hooks: HooksConfig::default(),
+                source: None,
+                content: None,
             },
         );
         assert_eq!(
@@ -363,9 +1086,18 @@ mod tests {
                 duration: Some(10),
                 path: "/home/user/wallpapers/work".into(),
                 fit: FitMode::default(),
+                background: None,
+                align: Align::default(),
+                zoom: None,
+                motion: None,
+                order: ImageOrder::default(),
                 run_command: None,
                 transition: None,
                 transitions_enabled: None,
+                fallback: None,
+                hooks: HooksConfig::default(),
+                source: None,
+                content: None,
             }
         );
 
@@ -373,6 +1105,103 @@ mod tests {
         dbg!(value);
     }
 
+    #[test]
+    fn test_deserialize_source_config() {
+        let config = Config::new(
+            r#"
+            [eDP-1]
+            source = { exec = "my-picker --output {output}" }
+
+            [work.eDP-1]
+            source = { exec = "slideshow", stream = true, timeout = 2 }
+
+            [lobby.eDP-1]
+            source = { http = "https://images.example.com/list.json", refresh = "30m" }
+            "#,
+        )
+        .unwrap();
+
+        let output_config = config.get_output_config(None, "eDP-1").unwrap();
+        assert_eq!(output_config.path, PathBuf::new());
+        assert_eq!(
+            output_config.exec_source(),
+            Some(&ExecSourceConfig {
+                exec: "my-picker --output {output}".to_string(),
+                timeout: 10,
+                stream: false,
+            })
+        );
+        let output_config = config.get_output_config("work", "eDP-1").unwrap();
+        assert_eq!(
+            output_config.exec_source(),
+            Some(&ExecSourceConfig {
+                exec: "slideshow".to_string(),
+                timeout: 2,
+                stream: true,
+            })
+        );
+        let output_config = config.get_output_config("lobby", "eDP-1").unwrap();
+        assert_eq!(output_config.exec_source(), None);
+        assert_eq!(output_config.shader_content(), None);
+        assert_eq!(
+            output_config.http_source(),
+            Some(&HttpSourceConfig {
+                http: "https://images.example.com/list.json".to_string(),
+                refresh: Duration::from_secs(30 * 60),
+                timeout: 30,
+                cache_size_mb: 512,
+            })
+        );
+    }
+
+    #[test]
+    fn test_deserialize_content_config() {
+        let config = Config::new(
+            r##"
+            [eDP-1]
+            path = "/home/user/wallpapers"
+            content = { shader = "/home/user/waves.wgsl", fps = 60 }
+
+            [HDMI-A-1]
+            content = { shader = "~/.config/wayper/shaders/waves.wgsl" }
+
+            [work.eDP-1]
+            content = { color = "#1e1e2e" }
+
+            [work.HDMI-A-1]
+            content = { gradient = "linear-gradient(135deg, #1e1e2e, #89b4fa)" }
+            "##,
+        )
+        .unwrap();
+
+        let output_config = config.get_output_config(None, "eDP-1").unwrap();
+        assert_eq!(
+            output_config.shader_content(),
+            Some(&ShaderContentConfig {
+                shader: PathBuf::from("/home/user/waves.wgsl"),
+                fps: 60,
+            })
+        );
+        let output_config = config.get_output_config(None, "HDMI-A-1").unwrap();
+        let shader = output_config.shader_content().unwrap();
+        assert!(shader.shader.ends_with(".config/wayper/shaders/waves.wgsl"));
+        assert!(!shader.shader.starts_with("~"));
+        assert_eq!(shader.fps, 30);
+        assert_eq!(output_config.fill_content(), None);
+
+        let output_config = config.get_output_config("work", "eDP-1").unwrap();
+        assert_eq!(output_config.shader_content(), None);
+        assert_eq!(
+            output_config.fill_content(),
+            Some(Fill::Color(Rgb::new(0x1e, 0x1e, 0x2e)))
+        );
+        let output_config = config.get_output_config("work", "HDMI-A-1").unwrap();
+        assert!(matches!(
+            output_config.fill_content(),
+            Some(Fill::Gradient(_))
+        ));
+    }
+
     #[test]
     fn test_deserialize_transition_config() {
         let conf_str = r#"
@@ -439,4 +1268,315 @@ mod tests {
             Direction::TopLeftToBottomRight
         ));
     }
+
+    #[test]
+    fn test_deserialize_shader_transitions() {
+        let config = Config::new(
+            r#"
+            [transition]
+            type = ["wipe", "iris", "dissolve", "zoom", "blur", "pixelate"]
+            easing = "cubic-bezier(0.68, -0.6, 0.32, 1.6)"
+
+            [transition.wipe]
+            direction = "top-to-bottom"
+            softness = 0.2
+
+            [transition.circle]
+            center = [0.25, 0.75]
+
+            [transition.dissolve]
+            seed = 42
+        "#,
+        )
+        .unwrap();
+        let transition = config.transition.unwrap();
+        assert_eq!(
+            transition.easing,
+            Easing::CubicBezier([0.68, -0.6, 0.32, 1.6])
+        );
+        assert_eq!(
+            transition.transition_type,
+            TransitionSelection::Random(vec![
+                TransitionTypeEnum::Wipe,
+                TransitionTypeEnum::Circle,
+                TransitionTypeEnum::Dissolve,
+                TransitionTypeEnum::Zoom,
+                TransitionTypeEnum::Blur,
+                TransitionTypeEnum::Pixelate,
+            ])
+        );
+        assert_eq!(
+            transition.wipe,
+            WipeConfig {
+                direction: Direction::TopToBottom,
+                softness: 0.2,
+            }
+        );
+        assert_eq!(transition.circle.center, Some([0.25, 0.75]));
+        assert_eq!(transition.circle.softness, 0.02);
+        assert_eq!(transition.dissolve.seed, Some(42));
+        assert_eq!(transition.zoom, ZoomConfig::default());
+        assert_eq!(transition.blur, BlurConfig::default());
+        assert_eq!(transition.pixelate, PixelateConfig::default());
+
+        let config = Config::new(
+            r#"
+            [transition]
+            type = { shader = "~/.config/wayper/transitions/ripple.wgsl" }
+            params = { amplitude = 0.1, waves = 12 }
+        "#,
+        )
+        .unwrap();
+        let transition = config.transition.unwrap();
+        let TransitionSelection::Single(TransitionTypeEnum::Shader(shader)) =
+            &transition.transition_type
+        else {
+            panic!("expected a shader transition");
+        };
+        assert!(shader.ends_with(".config/wayper/transitions/ripple.wgsl"));
+        assert!(!shader.starts_with("~"));
+        assert_eq!(
+            transition.params,
+            BTreeMap::from([("amplitude".to_string(), 0.1), ("waves".to_string(), 12.0)])
+        );
+    }
+
+    #[test]
+    fn test_deserialize_notifications_config() {
+        let config = Config::new(
+            r#"
+            [eDP-1]
+            path = "/home/user/wallpapers"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.notifications, NotificationsConfig::default());
+        assert!(!config.notifications.enabled);
+
+        let config = Config::new(
+            r#"
+            [notifications]
+            enabled = true
+            on_image_change = false
+            timeout_ms = 3000
+
+            [eDP-1]
+            path = "/home/user/wallpapers"
+        "#,
+        )
+        .unwrap();
+        assert!(config.notifications.enabled);
+        assert!(!config.notifications.on_image_change);
+        assert!(config.notifications.on_profile_change);
+        assert_eq!(config.notifications.timeout_ms, Some(3000));
+        // the section must not be mistaken for an output
+        assert!(
+            !config
+                .profiles
+                .profiles()
+                .contains(&"notifications".to_string())
+        );
+        assert!(config.get_output_config("default", "eDP-1").is_ok());
+    }
+
+    #[test]
+    fn test_deserialize_fallback_config() {
+        let config = Config::new(
+            r##"
+            fallback = "#1e1e2e"
+
+            [eDP-1]
+            path = "/home/user/wallpapers"
+
+            [HDMI-A-1]
+            path = "/home/user/wallpapers"
+            fallback = "/home/user/fallback.png"
+        "##,
+        )
+        .unwrap();
+
+        let edp = config.get_output_config("default", "eDP-1").unwrap();
+        assert_eq!(
+            edp.get_fallback(&config),
+            Fallback::Color(Rgb::new(0x1e, 0x1e, 0x2e))
+        );
+        let hdmi = config.get_output_config("default", "HDMI-A-1").unwrap();
+        assert_eq!(
+            hdmi.get_fallback(&config),
+            Fallback::Image(PathBuf::from("/home/user/fallback.png"))
+        );
+        assert_eq!(edp.get_fallback(&Config::default()), Fallback::default());
+
+        assert!(Config::new("fallback = \"#nope\"").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_background_config() {
+        let config = Config::new(
+            r##"
+            [eDP-1]
+            path = "/home/user/wallpapers"
+            fit = "contain"
+            background = "blur"
+
+            [HDMI-A-1]
+            path = "/home/user/wallpapers"
+            background = "#1e1e2e"
+        "##,
+        )
+        .unwrap();
+
+        let edp = config.get_output_config("default", "eDP-1").unwrap();
+        assert_eq!(edp.background, Some(Background::Blur));
+        let hdmi = config.get_output_config("default", "HDMI-A-1").unwrap();
+        assert_eq!(
+            hdmi.background,
+            Some(Background::Fill(Fill::Color(Rgb::new(0x1e, 0x1e, 0x2e))))
+        );
+
+        assert!(Config::new("[eDP-1]\npath = \"/tmp\"\nbackground = \"blurred\"").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_align_config() {
+        let config = Config::new(
+            r##"
+            [eDP-1]
+            path = "/home/user/portraits"
+            align = { vertical = "top" }
+            zoom = 1.2
+
+            [HDMI-A-1]
+            path = "/home/user/wallpapers"
+        "##,
+        )
+        .unwrap();
+
+        let edp = config.get_output_config("default", "eDP-1").unwrap();
+        assert_eq!(edp.align.fractions(), [0.5, 0.0]);
+        assert_eq!(edp.zoom, Some(1.2));
+        let hdmi = config.get_output_config("default", "HDMI-A-1").unwrap();
+        assert_eq!(hdmi.align, Align::default());
+        assert_eq!(hdmi.align.fractions(), [0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_deserialize_motion_config() {
+        let config = Config::new(
+            r##"
+            [eDP-1]
+            path = "/home/user/wallpapers"
+            motion = "kenburns"
+
+            [HDMI-A-1]
+            path = "/home/user/wallpapers"
+            motion = { type = "kenburns", amplitude = 0.3, fps = 24, easing = "ease-in-out-quad" }
+        "##,
+        )
+        .unwrap();
+
+        let edp = config.get_output_config("default", "eDP-1").unwrap();
+        assert_eq!(
+            edp.motion,
+            Some(MotionConfig {
+                motion_type: MotionType::KenBurns,
+                amplitude: 0.15,
+                fps: 10,
+                easing: Easing::Linear,
+            })
+        );
+        let hdmi = config.get_output_config("default", "HDMI-A-1").unwrap();
+        assert_eq!(
+            hdmi.motion,
+            Some(MotionConfig {
+                motion_type: MotionType::KenBurns,
+                amplitude: 0.3,
+                fps: 24,
+                easing: Easing::InOut(crate::easing::Curve::Quad),
+            })
+        );
+
+        assert!(Config::new("[eDP-1]\npath = \"/tmp\"\nmotion = \"wobble\"").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_hooks_config() {
+        let config = Config::new(
+            r#"
+            [hooks]
+            on_profile_change = "notify-send {profile}"
+
+            [hooks.on_wallpaper_change]
+            command = "wal -i {image}"
+            timeout = 5
+            debounce_ms = 500
+            concurrency = "skip"
+
+            [eDP-1]
+            path = "/home/user/wallpapers"
+
+            [HDMI-A-1]
+            path = "/home/user/wallpapers"
+            run_command = "matugen image {image}"
+
+            [HDMI-A-1.hooks]
+            on_transition_end = "echo {output}"
+        "#,
+        )
+        .unwrap();
+
+        let profile_hook = config.hooks.on_profile_change.as_ref().unwrap();
+        assert_eq!(profile_hook.command, "notify-send {profile}");
+        assert_eq!(profile_hook.concurrency, HookConcurrency::Replace);
+        assert_eq!(profile_hook.timeout, 30);
+
+        let edp = config.get_output_config("default", "eDP-1").unwrap();
+        let hook = edp.get_hook(HookEvent::WallpaperChange, &config).unwrap();
+        assert_eq!(hook.command, "wal -i {image}");
+        assert_eq!(hook.timeout, 5);
+        assert_eq!(hook.debounce_ms, 500);
+        assert_eq!(hook.concurrency, HookConcurrency::Skip);
+        assert!(edp.get_hook(HookEvent::TransitionEnd, &config).is_none());
+
+        // the deprecated run_command is still an output level setting
+        let hdmi = config.get_output_config("default", "HDMI-A-1").unwrap();
+        let hook = hdmi.get_hook(HookEvent::WallpaperChange, &config).unwrap();
+        assert_eq!(hook.command, "matugen image {image}");
+        let hook = hdmi.get_hook(HookEvent::TransitionEnd, &config).unwrap();
+        assert_eq!(hook.command, "echo {output}");
+    }
+
+    #[test]
+    fn test_deserialize_templates_config() {
+        let config = Config::new(
+            r#"
+            primary_output = "eDP-1"
+
+            [[template]]
+            input = "/home/user/.config/wayper/templates/foot.ini"
+            output = "/home/user/.config/foot/colors.ini"
+
+            [[template]]
+            input = "/home/user/.config/wayper/templates/waybar.css"
+            output = "/home/user/.config/waybar/colors.css"
+            post_command = "pkill -SIGUSR2 waybar"
+
+            [eDP-1]
+            path = "/home/user/wallpapers"
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.primary_output.as_deref(), Some("eDP-1"));
+        assert_eq!(config.templates.len(), 2);
+        assert_eq!(
+            config.templates[1],
+            TemplateConfig {
+                input: PathBuf::from("/home/user/.config/wayper/templates/waybar.css"),
+                output: PathBuf::from("/home/user/.config/waybar/colors.css"),
+                post_command: Some("pkill -SIGUSR2 waybar".to_string()),
+            }
+        );
+        assert!(config.get_output_config("default", "eDP-1").is_ok());
+    }
 }
@@ -0,0 +1,370 @@
+//! Easing curves, how an animation speeds up and slows down on its way from 0 to 1.
+//!
+//! Written like CSS: a named curve such as `ease-in-out-cubic`, `cubic-bezier(x1, y1, x2, y2)`, or
+//! `spring(stiffness, damping)`. A spring can also be a table,
+//! `{ type = "spring", stiffness = 180, damping = 12 }`.
+
+use std::{f32::consts::PI, str::FromStr};
+
+use serde::Deserialize;
+
+/// A spring that overshoots a little before it settles
+const DEFAULT_STIFFNESS: f32 = 100.0;
+const DEFAULT_DAMPING: f32 = 10.0;
+
+/// An easing curve, mapping the progress of an animation to how far along it is drawn
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "EasingSetting")]
+pub enum Easing {
+    Linear,
+    In(Curve),
+    Out(Curve),
+    InOut(Curve),
+    /// Control points of a CSS cubic bezier, the x coordinates are kept within 0 and 1
+    CubicBezier([f32; 4]),
+    /// A mass on a spring let go at 0, settling on 1 at the end
+    Spring {
+        stiffness: f32,
+        damping: f32,
+    },
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Easing::InOut(Curve::Cubic)
+    }
+}
+
+/// Curves eased in, out or both ways
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Curve {
+    Quad,
+    Cubic,
+    Quint,
+    Expo,
+    /// Pulls back before it goes
+    Back,
+    Elastic,
+    Bounce,
+}
+
+impl Curve {
+    const ALL: [Curve; 7] = [
+        Curve::Quad,
+        Curve::Cubic,
+        Curve::Quint,
+        Curve::Expo,
+        Curve::Back,
+        Curve::Elastic,
+        Curve::Bounce,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Curve::Quad => "quad",
+            Curve::Cubic => "cubic",
+            Curve::Quint => "quint",
+            Curve::Expo => "expo",
+            Curve::Back => "back",
+            Curve::Elastic => "elastic",
+            Curve::Bounce => "bounce",
+        }
+    }
+
+    /// The curve eased in, starting slow
+    fn ease_in(&self, t: f32) -> f32 {
+        match self {
+            Curve::Quad => t * t,
+            Curve::Cubic => t * t * t,
+            Curve::Quint => t.powi(5),
+            Curve::Expo if t <= 0.0 => 0.0,
+            Curve::Expo => 2f32.powf(10.0 * t - 10.0),
+            Curve::Back => {
+                const OVERSHOOT: f32 = 1.70158;
+                (OVERSHOOT + 1.0) * t * t * t - OVERSHOOT * t * t
+            }
+            Curve::Elastic if t <= 0.0 || t >= 1.0 => t,
+            Curve::Elastic => {
+                -(2f32.powf(10.0 * t - 10.0)) * ((t * 10.0 - 10.75) * 2.0 * PI / 3.0).sin()
+            }
+            Curve::Bounce => 1.0 - bounce_out(1.0 - t),
+        }
+    }
+}
+
+fn bounce_out(t: f32) -> f32 {
+    const N: f32 = 7.5625;
+    const D: f32 = 2.75;
+    if t < 1.0 / D {
+        N * t * t
+    } else if t < 2.0 / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
+
+impl Easing {
+    /// How far along an animation is drawn at `t`, its progress from 0 to 1. Always 0 at the
+    /// start and 1 at the end, some curves overshoot in between.
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        if t <= 0.0 || t >= 1.0 {
+            return t;
+        }
+        match self {
+            Easing::Linear => t,
+            Easing::In(curve) => curve.ease_in(t),
+            Easing::Out(curve) => 1.0 - curve.ease_in(1.0 - t),
+            Easing::InOut(curve) if t < 0.5 => curve.ease_in(2.0 * t) / 2.0,
+            Easing::InOut(curve) => 1.0 - curve.ease_in(2.0 - 2.0 * t) / 2.0,
+            Easing::CubicBezier(points) => cubic_bezier(*points, t),
+            Easing::Spring { stiffness, damping } => spring(*stiffness, *damping, t),
+        }
+    }
+}
+
+/// The y of the CSS cubic bezier through (0, 0) and (1, 1) at x = `t`
+fn cubic_bezier([x1, y1, x2, y2]: [f32; 4], t: f32) -> f32 {
+    let (x1, x2) = (x1.clamp(0.0, 1.0), x2.clamp(0.0, 1.0));
+    let bezier = |a: f32, b: f32, s: f32| {
+        let u = 1.0 - s;
+        3.0 * u * u * s * a + 3.0 * u * s * s * b + s * s * s
+    };
+    // x grows with s, so halving the range always finds it
+    let (mut low, mut high) = (0.0f32, 1.0f32);
+    let mut s = t;
+    for _ in 0..32 {
+        let x = bezier(x1, x2, s);
+        if (x - t).abs() < 1e-6 {
+            break;
+        }
+        if x < t {
+            low = s;
+        } else {
+            high = s;
+        }
+        s = (low + high) / 2.0;
+    }
+    bezier(y1, y2, s)
+}
+
+/// A damped spring of unit mass, played until it has settled to within a thousandth of 1
+fn spring(stiffness: f32, damping: f32, t: f32) -> f32 {
+    let omega = stiffness.max(0.1).sqrt();
+    // a spring without damping never settles
+    let zeta = (damping / (2.0 * omega)).max(0.05);
+    let position = |time: f32| -> f32 {
+        if zeta < 1.0 {
+            let omega_d = omega * (1.0 - zeta * zeta).sqrt();
+            let decay = (-zeta * omega * time).exp();
+            1.0 - decay * ((omega_d * time).cos() + zeta * omega / omega_d * (omega_d * time).sin())
+        } else if zeta == 1.0 {
+            1.0 - (-omega * time).exp() * (1.0 + omega * time)
+        } else {
+            let root = (zeta * zeta - 1.0).sqrt();
+            let (r1, r2) = (-omega * (zeta - root), -omega * (zeta + root));
+            1.0 - (r2 * (r1 * time).exp() - r1 * (r2 * time).exp()) / (r2 - r1)
+        }
+    };
+    // the slowest part of the motion decays at this rate
+    let rate = omega * (zeta - (zeta * zeta - 1.0).max(0.0).sqrt());
+    let settled = 1000f32.ln() / rate * 1.5;
+    position(t * settled) / position(settled)
+}
+
+impl FromStr for Easing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("invalid easing \"{s}\"");
+        if s == "linear" {
+            return Ok(Easing::Linear);
+        }
+        if let Some(args) = s.strip_prefix("cubic-bezier(") {
+            let points = numbers(args).ok_or_else(invalid)?;
+            let points: [f32; 4] = points
+                .try_into()
+                .map_err(|_| format!("{}, expected 4 numbers", invalid()))?;
+            return Ok(Easing::CubicBezier(points));
+        }
+        if s == "spring" {
+            return Ok(Easing::Spring {
+                stiffness: DEFAULT_STIFFNESS,
+                damping: DEFAULT_DAMPING,
+            });
+        }
+        if let Some(args) = s.strip_prefix("spring(") {
+            let [stiffness, damping] = numbers(args)
+                .ok_or_else(invalid)?
+                .try_into()
+                .map_err(|_| format!("{}, expected a stiffness and a damping", invalid()))?;
+            return Ok(Easing::Spring { stiffness, damping });
+        }
+
+        // ease-in, ease-out and ease-in-out are cubic like in most tools, the short names are
+        // eased out
+        let (ease, curve) = match s.strip_prefix("ease-") {
+            Some(rest) => Curve::ALL
+                .iter()
+                .find_map(|curve| {
+                    let ease = rest.strip_suffix(curve.name())?.strip_suffix('-')?;
+                    Some((ease, *curve))
+                })
+                .unwrap_or((rest, Curve::Cubic)),
+            None => (
+                "out",
+                *Curve::ALL
+                    .iter()
+                    .find(|curve| curve.name() == s)
+                    .ok_or_else(|| {
+                        format!(
+                            "{}, expected linear, a curve like ease-in-out-cubic, cubic-bezier(x1, y1, x2, y2) or spring(stiffness, damping)",
+                            invalid()
+                        )
+                    })?,
+            ),
+        };
+        match ease {
+            "in" => Ok(Easing::In(curve)),
+            "out" => Ok(Easing::Out(curve)),
+            "in-out" => Ok(Easing::InOut(curve)),
+            _ => Err(format!(
+                "{}, expected ease-in, ease-out or ease-in-out",
+                invalid()
+            )),
+        }
+    }
+}
+
+/// The numbers of a `(a, b, ...)` argument list, after its opening parenthesis
+fn numbers(args: &str) -> Option<Vec<f32>> {
+    args.strip_suffix(')')?
+        .split(',')
+        .map(|n| n.trim().parse().ok())
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SpringType {
+    Spring,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EasingSetting {
+    Name(String),
+    Spring {
+        #[serde(rename = "type")]
+        _spring: SpringType,
+        stiffness: Option<f32>,
+        damping: Option<f32>,
+    },
+}
+
+impl TryFrom<EasingSetting> for Easing {
+    type Error = String;
+
+    fn try_from(setting: EasingSetting) -> Result<Self, Self::Error> {
+        match setting {
+            EasingSetting::Name(name) => name.parse(),
+            EasingSetting::Spring {
+                stiffness, damping, ..
+            } => Ok(Easing::Spring {
+                stiffness: stiffness.unwrap_or(DEFAULT_STIFFNESS),
+                damping: damping.unwrap_or(DEFAULT_DAMPING),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_easings() {
+        let parse = |s: &str| s.parse::<Easing>().unwrap();
+        assert_eq!(parse("linear"), Easing::Linear);
+        assert_eq!(parse("ease-in-quad"), Easing::In(Curve::Quad));
+        assert_eq!(parse("ease-out-expo"), Easing::Out(Curve::Expo));
+        assert_eq!(parse("ease-in-out-bounce"), Easing::InOut(Curve::Bounce));
+        assert_eq!(parse("ease-in-out"), Easing::InOut(Curve::Cubic));
+        assert_eq!(parse("elastic"), Easing::Out(Curve::Elastic));
+        assert_eq!(
+            parse("cubic-bezier(0.25, 0.1, 0.25, 1)"),
+            Easing::CubicBezier([0.25, 0.1, 0.25, 1.0])
+        );
+        assert_eq!(
+            parse("spring(180, 12)"),
+            Easing::Spring {
+                stiffness: 180.0,
+                damping: 12.0
+            }
+        );
+        assert!("ease-sideways".parse::<Easing>().is_err());
+        assert!("cubic-bezier(0.25, 0.1)".parse::<Easing>().is_err());
+        assert!("wobble".parse::<Easing>().is_err());
+
+        #[derive(Deserialize)]
+        struct Config {
+            easing: Easing,
+        }
+        let config: Config =
+            toml::from_str(r#"easing = { type = "spring", damping = 20 }"#).unwrap();
+        assert_eq!(
+            config.easing,
+            Easing::Spring {
+                stiffness: 100.0,
+                damping: 20.0
+            }
+        );
+    }
+
+    #[test]
+    fn test_easing_endpoints() {
+        let mut easings = vec![
+            Easing::Linear,
+            Easing::CubicBezier([0.25, 0.1, 0.25, 1.0]),
+            Easing::CubicBezier([0.68, -0.6, 0.32, 1.6]),
+            // under, critically and over damped
+            Easing::Spring {
+                stiffness: 100.0,
+                damping: 10.0,
+            },
+            Easing::Spring {
+                stiffness: 100.0,
+                damping: 20.0,
+            },
+            Easing::Spring {
+                stiffness: 100.0,
+                damping: 60.0,
+            },
+        ];
+        for curve in Curve::ALL {
+            easings.extend([Easing::In(curve), Easing::Out(curve), Easing::InOut(curve)]);
+        }
+        for easing in easings {
+            assert_eq!(easing.apply(0.0), 0.0, "{easing:?}");
+            assert_eq!(easing.apply(1.0), 1.0, "{easing:?}");
+            // held past the ends
+            assert_eq!(easing.apply(-0.5), 0.0, "{easing:?}");
+            assert_eq!(easing.apply(1.5), 1.0, "{easing:?}");
+            // and continuous up to them
+            assert!(easing.apply(1e-4).abs() < 0.01, "{easing:?}");
+            assert!((easing.apply(1.0 - 1e-4) - 1.0).abs() < 0.01, "{easing:?}");
+        }
+
+        // the halfway points of the symmetric curves
+        assert_eq!(Easing::InOut(Curve::Cubic).apply(0.5), 0.5);
+        assert!((Easing::CubicBezier([0.0, 0.0, 1.0, 1.0]).apply(0.3) - 0.3).abs() < 1e-4);
+        assert!(Easing::In(Curve::Back).apply(0.2) < 0.0);
+    }
+}
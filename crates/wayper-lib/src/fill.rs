@@ -0,0 +1,456 @@
+//! Flat colors and gradients, shown instead of an image or around one.
+//!
+//! They are written like in CSS: `#1e1e2e`, `linear-gradient(135deg, #1e1e2e, #89b4fa 70%)`,
+//! `radial-gradient(at 50% 30%, #89b4fa, #1e1e2e)` or
+//! `conic-gradient(from 90deg at 50% 50%, #f38ba8, #89b4fa, #f38ba8)`. Stops without a position
+//! are spread evenly between the ones around them. In a rotation a fill stands in for an image
+//! as a `fill:` path.
+//!
+//! Around an image that doesn't cover the output, a [`Background`] can also be `auto`, the
+//! average color of the edges of the image, or `blur`, a blurred copy of the image.
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::palette::{Palette, Rgb};
+
+/// Most stops a gradient can have
+pub const MAX_STOPS: usize = 8;
+const PATH_PREFIX: &str = "fill:";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fill {
+    Color(Rgb),
+    Gradient(Box<Gradient>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    pub kind: GradientKind,
+    /// At least two, ordered by position
+    pub stops: Vec<GradientStop>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GradientKind {
+    /// Toward `angle` degrees, clockwise from the top
+    Linear { angle: f32 },
+    /// Out of `center` to the farthest corner. The center is in fractions of the size.
+    Radial { center: [f32; 2] },
+    /// Around `center`, starting `angle` degrees clockwise from the top
+    Conic { center: [f32; 2], angle: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GradientStop {
+    pub color: Rgb,
+    /// Where the color is reached, in 0.0..=1.0
+    pub position: f32,
+}
+
+impl Fill {
+    /// The fill a `fill:` path stands for
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.to_str()?.strip_prefix(PATH_PREFIX)?.parse().ok()
+    }
+
+    /// Whether a path stands for a fill rather than a file
+    pub fn is_fill(path: &Path) -> bool {
+        path.to_str()
+            .is_some_and(|path| path.starts_with(PATH_PREFIX))
+    }
+
+    /// The path standing for this fill in a rotation
+    pub fn to_path(&self) -> PathBuf {
+        PathBuf::from(format!("{PATH_PREFIX}{self}"))
+    }
+
+    /// The palette of the colors of the fill, weighted by how much of it they cover
+    pub fn palette(&self) -> Option<Palette> {
+        let pixels = match self {
+            Fill::Color(color) => vec![color.r, color.g, color.b, 255],
+            Fill::Gradient(gradient) => (0..256)
+                .flat_map(|i| {
+                    let color = gradient.color_at(i as f32 / 255.0);
+                    [color.r, color.g, color.b, 255]
+                })
+                .collect(),
+        };
+        Palette::from_rgba(&pixels)
+    }
+}
+
+impl Gradient {
+    /// The color `t` along the gradient, mixed in sRGB like CSS does
+    pub fn color_at(&self, t: f32) -> Rgb {
+        let first = self.stops[0];
+        let last = self.stops[self.stops.len() - 1];
+        if t <= first.position {
+            return first.color;
+        }
+        let Some(pair) = self.stops.windows(2).find(|pair| t <= pair[1].position) else {
+            return last.color;
+        };
+        let (from, to) = (pair[0], pair[1]);
+        let span = to.position - from.position;
+        let amount = match span > 0.0 {
+            true => (t - from.position) / span,
+            false => 1.0,
+        };
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round() as u8;
+        Rgb::new(
+            mix(from.color.r, to.color.r),
+            mix(from.color.g, to.color.g),
+            mix(from.color.b, to.color.b),
+        )
+    }
+
+    fn parse(name: &str, args: &str) -> Result<Self, String> {
+        let mut parts = args.split(',').map(str::trim).peekable();
+        let options = match parts.peek() {
+            Some(first) if !first.starts_with('#') => parts.next().unwrap_or_default(),
+            _ => "",
+        };
+        let kind = match name {
+            "linear-gradient" => GradientKind::Linear {
+                angle: match options {
+                    "" => 180.0,
+                    side if side.starts_with("to ") => side_angle(&side[3..])?,
+                    angle => parse_angle(angle)?,
+                },
+            },
+            "radial-gradient" => GradientKind::Radial {
+                center: match options {
+                    "" => [0.5, 0.5],
+                    at => parse_center(at.strip_prefix("at ").ok_or_else(|| {
+                        format!("invalid radial gradient \"{at}\", expected `at <x>% <y>%`")
+                    })?)?,
+                },
+            },
+            "conic-gradient" => {
+                let (from, at) = match options.split_once("at ") {
+                    Some((from, at)) => (from.trim(), Some(at)),
+                    None => (options, None),
+                };
+                GradientKind::Conic {
+                    center: at.map_or(Ok([0.5, 0.5]), parse_center)?,
+                    angle: match from {
+                        "" => 0.0,
+                        from => parse_angle(from.strip_prefix("from ").ok_or_else(|| {
+                            format!("invalid conic gradient \"{from}\", expected `from <angle>deg`")
+                        })?)?,
+                    },
+                }
+            }
+            name => return Err(format!("unknown gradient \"{name}\"")),
+        };
+
+        let stops = parts
+            .map(|stop| {
+                let (color, position) = match stop.split_once(' ') {
+                    Some((color, position)) => (color, Some(parse_percent(position)?)),
+                    None => (stop, None),
+                };
+                Ok((color.parse::<Rgb>()?, position))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if !(2..=MAX_STOPS).contains(&stops.len()) {
+            return Err(format!(
+                "a gradient needs 2 to {MAX_STOPS} colors, got {}",
+                stops.len()
+            ));
+        }
+        Ok(Self {
+            kind,
+            stops: spread_stops(&stops),
+        })
+    }
+}
+
+/// Give every stop a position. The ends default to the edges, stops without a position are spread
+/// evenly between the ones that have one, and no stop comes before the one preceding it.
+fn spread_stops(stops: &[(Rgb, Option<f32>)]) -> Vec<GradientStop> {
+    let mut positions = stops
+        .iter()
+        .map(|(_, position)| *position)
+        .collect::<Vec<_>>();
+    let last = positions.len() - 1;
+    positions[0].get_or_insert(0.0);
+    positions[last].get_or_insert(1.0);
+
+    let mut start = 0;
+    for end in 1..positions.len() {
+        let Some(end_position) = positions[end] else {
+            continue;
+        };
+        let start_position = positions[start].unwrap_or_default();
+        for (i, position) in positions.iter_mut().enumerate().take(end).skip(start + 1) {
+            let amount = (i - start) as f32 / (end - start) as f32;
+            *position = Some(start_position + (end_position - start_position) * amount);
+        }
+        start = end;
+    }
+
+    let mut previous = 0.0_f32;
+    stops
+        .iter()
+        .zip(positions)
+        .map(|((color, _), position)| {
+            previous = position.unwrap_or_default().clamp(previous, 1.0);
+            GradientStop {
+                color: *color,
+                position: previous,
+            }
+        })
+        .collect()
+}
+
+fn parse_angle(angle: &str) -> Result<f32, String> {
+    angle
+        .trim()
+        .strip_suffix("deg")
+        .and_then(|degrees| degrees.parse().ok())
+        .ok_or_else(|| format!("invalid angle \"{angle}\", expected <degrees>deg"))
+}
+
+fn side_angle(side: &str) -> Result<f32, String> {
+    match side.trim() {
+        "top" => Ok(0.0),
+        "right" => Ok(90.0),
+        "bottom" => Ok(180.0),
+        "left" => Ok(270.0),
+        side => Err(format!("invalid side \"{side}\"")),
+    }
+}
+
+fn parse_percent(percent: &str) -> Result<f32, String> {
+    percent
+        .trim()
+        .strip_suffix('%')
+        .and_then(|percent| percent.parse::<f32>().ok())
+        .map(|percent| percent / 100.0)
+        .ok_or_else(|| format!("invalid position \"{percent}\", expected <n>%"))
+}
+
+fn parse_center(center: &str) -> Result<[f32; 2], String> {
+    match center.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [x, y] => Ok([parse_percent(x)?, parse_percent(y)?]),
+        _ => Err(format!("invalid center \"{center}\", expected <x>% <y>%")),
+    }
+}
+
+impl FromStr for Fill {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.starts_with('#') {
+            return s.parse().map(Fill::Color);
+        }
+        let (name, args) = s
+            .strip_suffix(')')
+            .and_then(|s| s.split_once('('))
+            .ok_or_else(|| {
+                format!("invalid fill \"{s}\", expected a #rrggbb color or a gradient")
+            })?;
+        Gradient::parse(name.trim(), args).map(|gradient| Fill::Gradient(Box::new(gradient)))
+    }
+}
+
+impl std::fmt::Display for Fill {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let gradient = match self {
+            Fill::Color(color) => return write!(f, "{color}"),
+            Fill::Gradient(gradient) => gradient,
+        };
+        match gradient.kind {
+            GradientKind::Linear { angle } => write!(f, "linear-gradient({angle}deg")?,
+            GradientKind::Radial { center } => write!(
+                f,
+                "radial-gradient(at {}% {}%",
+                center[0] * 100.0,
+                center[1] * 100.0
+            )?,
+            GradientKind::Conic { center, angle } => write!(
+                f,
+                "conic-gradient(from {angle}deg at {}% {}%",
+                center[0] * 100.0,
+                center[1] * 100.0
+            )?,
+        }
+        for stop in &gradient.stops {
+            write!(f, ", {} {}%", stop.color, stop.position * 100.0)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl<'de> Deserialize<'de> for Fill {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// What is painted around an image that doesn't cover the output
+#[derive(Clone, Debug, PartialEq)]
+pub enum Background {
+    Fill(Fill),
+    /// The average color of the edges of the image
+    Auto,
+    /// The image scaled to cover the output, blurred and dimmed
+    Blur,
+}
+
+impl FromStr for Background {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "auto" => Ok(Background::Auto),
+            "blur" => Ok(Background::Blur),
+            s => s.parse().map(Background::Fill).map_err(|_| {
+                format!("invalid background \"{s}\", expected a color, a gradient, auto or blur")
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for Background {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Background::Fill(fill) => write!(f, "{fill}"),
+            Background::Auto => write!(f, "auto"),
+            Background::Blur => write!(f, "blur"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Background {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stops(gradient: &Fill) -> Vec<f32> {
+        match gradient {
+            Fill::Gradient(gradient) => gradient.stops.iter().map(|stop| stop.position).collect(),
+            Fill::Color(_) => vec![],
+        }
+    }
+
+    #[test]
+    fn test_parse_fills() {
+        assert_eq!(
+            "#1e1e2e".parse::<Fill>(),
+            Ok(Fill::Color(Rgb::new(0x1e, 0x1e, 0x2e)))
+        );
+
+        let linear = "linear-gradient(135deg, #1e1e2e, #89b4fa 70%, #f5c2e7)"
+            .parse::<Fill>()
+            .unwrap();
+        let Fill::Gradient(gradient) = &linear else {
+            panic!("not a gradient: {linear:?}");
+        };
+        assert_eq!(gradient.kind, GradientKind::Linear { angle: 135.0 });
+        assert_eq!(stops(&linear), [0.0, 0.7, 1.0]);
+
+        let radial = "radial-gradient(#89b4fa, #1e1e2e)".parse::<Fill>().unwrap();
+        let Fill::Gradient(gradient) = &radial else {
+            panic!("not a gradient: {radial:?}");
+        };
+        assert_eq!(gradient.kind, GradientKind::Radial { center: [0.5, 0.5] });
+
+        let conic = "conic-gradient(from 90deg at 25% 75%, #f38ba8, #89b4fa, #a6e3a1, #f38ba8)"
+            .parse::<Fill>()
+            .unwrap();
+        let Fill::Gradient(gradient) = &conic else {
+            panic!("not a gradient: {conic:?}");
+        };
+        assert_eq!(
+            gradient.kind,
+            GradientKind::Conic {
+                center: [0.25, 0.75],
+                angle: 90.0
+            }
+        );
+        assert_eq!(stops(&conic).len(), 4);
+
+        assert!("linear-gradient(#1e1e2e)".parse::<Fill>().is_err());
+        assert!(
+            "sparkle-gradient(#1e1e2e, #ffffff)"
+                .parse::<Fill>()
+                .is_err()
+        );
+        assert!("1e1e2e".parse::<Fill>().is_err());
+    }
+
+    #[test]
+    fn test_spread_stops() {
+        let positions = |s: &str| stops(&s.parse().unwrap());
+        assert_eq!(
+            positions("linear-gradient(#000, #111, #222, #333, #444)"),
+            [0.0, 0.25, 0.5, 0.75, 1.0]
+        );
+        assert_eq!(
+            positions("linear-gradient(#000 25%, #111, #222 75%, #333)"),
+            [0.25, 0.5, 0.75, 1.0]
+        );
+        // out of order positions are moved up to the stop before them
+        assert_eq!(
+            positions("linear-gradient(#000, #111 50%, #222 30%)"),
+            [0.0, 0.5, 0.5]
+        );
+    }
+
+    #[test]
+    fn test_fill_paths() {
+        for fill in [
+            "#1e1e2e",
+            "linear-gradient(to right, #1e1e2e, #89b4fa 70%)",
+            "conic-gradient(from 45deg, #f38ba8, #89b4fa)",
+        ] {
+            let fill = fill.parse::<Fill>().unwrap();
+            let path = fill.to_path();
+            assert!(Fill::is_fill(&path));
+            assert_eq!(Fill::from_path(&path), Some(fill));
+        }
+        assert_eq!(Fill::from_path(Path::new("/photos/a.jpg")), None);
+    }
+
+    #[test]
+    fn test_color_at() {
+        let Ok(Fill::Gradient(gradient)) = "linear-gradient(#000000, #ffffff 50%, #ff0000)".parse()
+        else {
+            panic!("not a gradient");
+        };
+        assert_eq!(gradient.color_at(0.0), Rgb::new(0, 0, 0));
+        assert_eq!(gradient.color_at(0.25), Rgb::new(128, 128, 128));
+        assert_eq!(gradient.color_at(0.5), Rgb::new(255, 255, 255));
+        assert_eq!(gradient.color_at(1.0), Rgb::new(255, 0, 0));
+    }
+
+    #[test]
+    fn test_parse_backgrounds() {
+        assert_eq!("auto".parse::<Background>(), Ok(Background::Auto));
+        assert_eq!(" blur ".parse::<Background>(), Ok(Background::Blur));
+        assert_eq!(
+            "#ffffff".parse::<Background>(),
+            Ok(Background::Fill(Fill::Color(Rgb::new(255, 255, 255))))
+        );
+        assert!(matches!(
+            "radial-gradient(#89b4fa, #1e1e2e)".parse::<Background>(),
+            Ok(Background::Fill(Fill::Gradient(_)))
+        ));
+        assert!("blurry".parse::<Background>().is_err());
+    }
+}
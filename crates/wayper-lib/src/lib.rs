@@ -1,6 +1,12 @@
 //! Library for common code
 // TODO: actually only common code, so don't bloat up unrelated binaries
 pub mod config;
+pub mod easing;
 pub mod event_source;
+pub mod fill;
+pub mod palette;
+pub mod paths;
+pub mod playlist;
+pub mod sidecar;
 #[cfg(target_os = "linux")]
 pub mod socket;
@@ -0,0 +1,497 @@
+//! Palette extraction from decoded images.
+//!
+//! Pixels are clustered with k-means in Oklab, which is close enough to perceptual that the
+//! clusters match what people would call "the colors" of an image. The clusters are then
+//! assigned roles (dominant, accent, light, dark), and a Material You style tonal palette is
+//! derived from the accent hue.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Number of clusters
+const CLUSTERS: usize = 8;
+/// Upper bound of pixels sampled from the image
+const MAX_SAMPLES: usize = 16_384;
+const ITERATIONS: usize = 12;
+/// Tones of the tonal palettes, Material You uses the same set
+pub const TONES: [u8; 13] = [0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 95, 99, 100];
+
+/// An sRGB color. Serialized as `#rrggbb`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Parse `#rgb` or `#rrggbb`, the `#` is optional
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim().trim_start_matches('#');
+        let digit = |i: usize, len: usize| u8::from_str_radix(hex.get(i..i + len)?, 16).ok();
+        match hex.len() {
+            3 => Some(Self::new(
+                digit(0, 1)? * 17,
+                digit(1, 1)? * 17,
+                digit(2, 1)? * 17,
+            )),
+            6 => Some(Self::new(digit(0, 2)?, digit(2, 2)?, digit(4, 2)?)),
+            _ => None,
+        }
+    }
+
+    pub fn to_hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    /// Linear components in 0.0..=1.0, for the GPU
+    pub fn to_linear(&self) -> [f32; 3] {
+        [
+            srgb_to_linear(self.r),
+            srgb_to_linear(self.g),
+            srgb_to_linear(self.b),
+        ]
+    }
+
+    pub fn to_oklab(&self) -> Oklab {
+        let [r, g, b] = self.to_linear();
+        Oklab::from_linear(r, g, b)
+    }
+
+    /// Hue in degrees, saturation and lightness in 0.0..=1.0
+    pub fn to_hsl(&self) -> (f32, f32, f32) {
+        let r = self.r as f32 / 255.0;
+        let g = self.g as f32 / 255.0;
+        let b = self.b as f32 / 255.0;
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.0;
+        let d = max - min;
+        if d == 0.0 {
+            return (0.0, 0.0, l);
+        }
+        let s = d / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == r {
+            60.0 * ((g - b) / d).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / d + 2.0)
+        } else {
+            60.0 * ((r - g) / d + 4.0)
+        };
+        (h, s, l)
+    }
+
+    pub fn from_hsl(h: f32, s: f32, l: f32) -> Self {
+        let s = s.clamp(0.0, 1.0);
+        let l = l.clamp(0.0, 1.0);
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let h = h.rem_euclid(360.0) / 60.0;
+        let x = c * (1.0 - (h.rem_euclid(2.0) - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let m = l - c / 2.0;
+        let to_u8 = |v: f32| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+        Self::new(to_u8(r), to_u8(g), to_u8(b))
+    }
+}
+
+impl std::fmt::Display for Rgb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+impl std::str::FromStr for Rgb {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(s).ok_or_else(|| format!("invalid color \"{s}\", expected #rrggbb"))
+    }
+}
+
+impl Serialize for Rgb {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for Rgb {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A color in the Oklab space
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Oklab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+impl Oklab {
+    pub fn from_linear(r: f32, g: f32, b: f32) -> Self {
+        let l = 0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b;
+        let m = 0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b;
+        let s = 0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b;
+        let (l, m, s) = (l.cbrt(), m.cbrt(), s.cbrt());
+        Self {
+            l: 0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+            a: 1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+            b: 0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+        }
+    }
+
+    /// Linear sRGB, possibly out of gamut
+    pub fn to_linear(&self) -> [f32; 3] {
+        let l = self.l + 0.396_337_78 * self.a + 0.215_803_76 * self.b;
+        let m = self.l - 0.105_561_346 * self.a - 0.063_854_17 * self.b;
+        let s = self.l - 0.089_484_18 * self.a - 1.291_485_5 * self.b;
+        let (l, m, s) = (l * l * l, m * m * m, s * s * s);
+        [
+            4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+            -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+            -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+        ]
+    }
+
+    pub fn chroma(&self) -> f32 {
+        self.a.hypot(self.b)
+    }
+
+    pub fn hue(&self) -> f32 {
+        self.b.atan2(self.a)
+    }
+
+    fn from_lch(l: f32, c: f32, h: f32) -> Self {
+        Self {
+            l,
+            a: c * h.cos(),
+            b: c * h.sin(),
+        }
+    }
+
+    fn in_gamut(&self) -> bool {
+        self.to_linear()
+            .iter()
+            .all(|v| (-0.0001..=1.0001).contains(v))
+    }
+
+    /// Convert to sRGB, reducing chroma until the color fits in the gamut
+    pub fn to_rgb(&self) -> Rgb {
+        let mut color = *self;
+        if !color.in_gamut() {
+            let (l, h) = (color.l, color.hue());
+            let (mut low, mut high) = (0.0, color.chroma());
+            for _ in 0..16 {
+                let mid = (low + high) / 2.0;
+                if Self::from_lch(l, mid, h).in_gamut() {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+            color = Self::from_lch(l, low, h);
+        }
+        let [r, g, b] = color.to_linear();
+        Rgb::new(linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b))
+    }
+
+    fn distance_sq(&self, other: &Self) -> f32 {
+        let (dl, da, db) = (self.l - other.l, self.a - other.a, self.b - other.b);
+        dl * dl + da * da + db * db
+    }
+}
+
+fn srgb_to_linear(v: u8) -> f32 {
+    let v = v as f32 / 255.0;
+    if v <= 0.040_45 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(v: f32) -> u8 {
+    let v = v.clamp(0.0, 1.0);
+    let v = if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (v * 255.0).round() as u8
+}
+
+/// A color and how much of the image it covers
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Swatch {
+    pub color: Rgb,
+    /// Fraction of the sampled pixels in 0.0..=1.0
+    pub population: f32,
+}
+
+/// Colors of an image
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Palette {
+    /// Clustered colors, most common first
+    pub swatches: Vec<Swatch>,
+    /// The most common color
+    pub dominant: Rgb,
+    /// The most colorful color that is still reasonably common
+    pub accent: Rgb,
+    /// The lightest color
+    pub light: Rgb,
+    /// The darkest color
+    pub dark: Rgb,
+    /// Tonal palette of the accent hue, keyed by tone (0 is black, 100 is white)
+    pub primary_tones: BTreeMap<u8, Rgb>,
+    /// Low chroma tonal palette of the accent hue, for backgrounds and surfaces
+    pub neutral_tones: BTreeMap<u8, Rgb>,
+}
+
+impl Palette {
+    /// Extract a palette from tightly packed RGBA8 pixels. Returns None if there are no
+    /// visible pixels.
+    pub fn from_rgba(pixels: &[u8]) -> Option<Self> {
+        let samples = sample_pixels(pixels);
+        if samples.is_empty() {
+            return None;
+        }
+
+        let clusters = kmeans(&samples);
+        let total = samples.len() as f32;
+        let mut clusters = clusters
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(center, count)| (center, count as f32 / total))
+            .collect::<Vec<_>>();
+        clusters.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let dominant = clusters[0].0;
+        let accent = clusters
+            .iter()
+            .max_by(|a, b| accent_score(a).total_cmp(&accent_score(b)))
+            .map(|(center, _)| *center)
+            .unwrap_or(dominant);
+        let light = clusters
+            .iter()
+            .map(|(center, _)| *center)
+            .max_by(|a, b| a.l.total_cmp(&b.l))
+            .unwrap_or(dominant);
+        let dark = clusters
+            .iter()
+            .map(|(center, _)| *center)
+            .min_by(|a, b| a.l.total_cmp(&b.l))
+            .unwrap_or(dominant);
+
+        // keep some color in the tones even if the image is nearly gray
+        let primary_chroma = accent.chroma().max(0.04);
+        let neutral_chroma = (accent.chroma() / 6.0).min(0.02);
+
+        Some(Self {
+            swatches: clusters
+                .iter()
+                .map(|(center, population)| Swatch {
+                    color: center.to_rgb(),
+                    population: *population,
+                })
+                .collect(),
+            dominant: dominant.to_rgb(),
+            accent: accent.to_rgb(),
+            light: light.to_rgb(),
+            dark: dark.to_rgb(),
+            primary_tones: tonal_palette(accent.hue(), primary_chroma),
+            neutral_tones: tonal_palette(accent.hue(), neutral_chroma),
+        })
+    }
+
+    /// Named colors of the palette, as used by hooks and templates
+    pub fn roles(&self) -> [(&'static str, Rgb); 4] {
+        [
+            ("dominant", self.dominant),
+            ("accent", self.accent),
+            ("light", self.light),
+            ("dark", self.dark),
+        ]
+    }
+}
+
+/// Colorful and common wins. Near grays never make a good accent.
+fn accent_score((center, population): &(Oklab, f32)) -> f32 {
+    let chroma = center.chroma();
+    if chroma < 0.02 {
+        return population * 0.001;
+    }
+    // penalize extreme lightness, accents should be readable on both light and dark
+    let lightness = 1.0 - (center.l - 0.6).abs();
+    chroma * population.sqrt() * lightness
+}
+
+/// Material You style tones, lightness is the tone and chroma is reduced to fit the gamut
+fn tonal_palette(hue: f32, chroma: f32) -> BTreeMap<u8, Rgb> {
+    TONES
+        .iter()
+        .map(|&tone| {
+            let l = tone as f32 / 100.0;
+            (tone, Oklab::from_lch(l, chroma, hue).to_rgb())
+        })
+        .collect()
+}
+
+/// Convert an evenly spread subset of the visible pixels to Oklab
+fn sample_pixels(pixels: &[u8]) -> Vec<Oklab> {
+    let pixel_count = pixels.len() / 4;
+    let step = pixel_count.div_ceil(MAX_SAMPLES).max(1);
+    pixels
+        .chunks_exact(4)
+        .step_by(step)
+        .filter(|px| px[3] >= 128)
+        .map(|px| Rgb::new(px[0], px[1], px[2]).to_oklab())
+        .collect()
+}
+
+/// Plain k-means, seeded with farthest point sampling so results are deterministic
+fn kmeans(samples: &[Oklab]) -> Vec<(Oklab, usize)> {
+    let mut centers = vec![samples[0]];
+    let mut nearest = samples
+        .iter()
+        .map(|s| s.distance_sq(&centers[0]))
+        .collect::<Vec<_>>();
+    while centers.len() < CLUSTERS.min(samples.len()) {
+        let (far_idx, far_dist) = nearest
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .expect("samples are not empty");
+        if *far_dist <= f32::EPSILON {
+            // fewer distinct colors than clusters
+            break;
+        }
+        let center = samples[far_idx];
+        for (dist, sample) in nearest.iter_mut().zip(samples) {
+            *dist = dist.min(sample.distance_sq(&center));
+        }
+        centers.push(center);
+    }
+
+    let mut assignments = vec![0usize; samples.len()];
+    let mut counts = vec![0usize; centers.len()];
+    for _ in 0..ITERATIONS {
+        let mut changed = false;
+        for (assignment, sample) in assignments.iter_mut().zip(samples) {
+            let closest = centers
+                .iter()
+                .enumerate()
+                .min_by(|a, b| sample.distance_sq(a.1).total_cmp(&sample.distance_sq(b.1)))
+                .map(|(i, _)| i)
+                .unwrap_or(0);
+            if *assignment != closest {
+                *assignment = closest;
+                changed = true;
+            }
+        }
+
+        let mut sums = vec![(0.0f32, 0.0f32, 0.0f32); centers.len()];
+        counts.iter_mut().for_each(|c| *c = 0);
+        for (assignment, sample) in assignments.iter().zip(samples) {
+            let sum = &mut sums[*assignment];
+            sum.0 += sample.l;
+            sum.1 += sample.a;
+            sum.2 += sample.b;
+            counts[*assignment] += 1;
+        }
+        for ((center, sum), count) in centers.iter_mut().zip(&sums).zip(&counts) {
+            if *count > 0 {
+                let n = *count as f32;
+                *center = Oklab {
+                    l: sum.0 / n,
+                    a: sum.1 / n,
+                    b: sum.2 / n,
+                };
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    centers.into_iter().zip(counts).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(colors: &[(Rgb, usize)]) -> Vec<u8> {
+        colors
+            .iter()
+            .flat_map(|(color, count)| {
+                std::iter::repeat_n([color.r, color.g, color.b, 255], *count).flatten()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hex_roundtrip() {
+        let color = Rgb::from_hex("#1e1e2e").unwrap();
+        assert_eq!(color, Rgb::new(0x1e, 0x1e, 0x2e));
+        assert_eq!(color.to_hex(), "#1e1e2e");
+        assert_eq!(Rgb::from_hex("fff").unwrap(), Rgb::new(255, 255, 255));
+        assert!(Rgb::from_hex("#12345").is_none());
+        assert!(Rgb::from_hex("#gggggg").is_none());
+    }
+
+    #[test]
+    fn test_oklab_roundtrip() {
+        for color in [
+            Rgb::new(0, 0, 0),
+            Rgb::new(255, 255, 255),
+            Rgb::new(200, 30, 90),
+            Rgb::new(12, 140, 250),
+        ] {
+            assert_eq!(color.to_oklab().to_rgb(), color);
+        }
+    }
+
+    #[test]
+    fn test_hsl_roundtrip() {
+        let color = Rgb::new(200, 30, 90);
+        let (h, s, l) = color.to_hsl();
+        assert_eq!(Rgb::from_hsl(h, s, l), color);
+    }
+
+    #[test]
+    fn test_palette_roles() {
+        let navy = Rgb::new(20, 20, 60);
+        let orange = Rgb::new(240, 120, 20);
+        let white = Rgb::new(250, 250, 250);
+        let pixels = image(&[(navy, 7000), (orange, 2000), (white, 1000)]);
+
+        let palette = Palette::from_rgba(&pixels).unwrap();
+        assert_eq!(palette.dominant, navy);
+        assert_eq!(palette.accent, orange);
+        assert_eq!(palette.light, white);
+        assert_eq!(palette.dark, navy);
+        assert_eq!(palette.swatches.len(), 3);
+        assert_eq!(palette.primary_tones[&0], Rgb::new(0, 0, 0));
+        assert_eq!(palette.primary_tones[&100], Rgb::new(255, 255, 255));
+    }
+
+    #[test]
+    fn test_palette_transparent_image() {
+        assert!(Palette::from_rgba(&[0, 0, 0, 0, 255, 255, 255, 0]).is_none());
+    }
+}
@@ -0,0 +1,41 @@
+//! XDG base directory helpers for files wayper owns
+
+use std::path::{Path, PathBuf};
+
+/// Resolve an XDG base directory from `var`, falling back to `$HOME/<fallback>` as the spec says.
+fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+    match std::env::var_os(var) {
+        Some(dir) if !dir.is_empty() && PathBuf::from(&dir).is_absolute() => PathBuf::from(dir),
+        _ => std::env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("/tmp"))
+            .join(fallback),
+    }
+}
+
+/// `$XDG_DATA_HOME/wayper`, for data the user cares about (favorites and such)
+pub fn data_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share").join("wayper")
+}
+
+/// `$XDG_STATE_HOME/wayper`, for state that should survive restarts
+pub fn state_dir() -> PathBuf {
+    xdg_dir("XDG_STATE_HOME", ".local/state").join("wayper")
+}
+
+/// `$XDG_CACHE_HOME/wayper`, for anything that can be thrown away
+pub fn cache_dir() -> PathBuf {
+    xdg_dir("XDG_CACHE_HOME", ".cache").join("wayper")
+}
+
+/// Write through a temporary file and rename it over `path`, so readers never see a half
+/// written file. Parent directories are created as needed.
+pub fn write_atomic(path: &Path, content: impl AsRef<[u8]>) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut tmp = path.to_path_buf().into_os_string();
+    tmp.push(".wayper-tmp");
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(&tmp, path)
+}
@@ -0,0 +1,280 @@
+//! Playlists, an output `path` pointing at a list of images instead of a directory.
+//!
+//! Entries are shown in order and can override the duration, fit, transition and background of
+//! the output. Two formats are read: `.wayper-playlist.toml` files with an `[[entry]]` table per
+//! image, which can also be a `fill` color or gradient, and plain `.m3u` lists where `#EXTINF`
+//! gives the duration.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{Context, Result};
+use serde::Deserialize;
+
+use crate::{
+    config::{
+        Config, FitMode, OutputConfig, TransitionConfig, TransitionSelection, default_duration,
+        default_fps,
+    },
+    fill::{Background, Fill},
+};
+
+pub const PLAYLIST_SUFFIX: &str = ".wayper-playlist.toml";
+
+/// Whether a path is a playlist, by its name
+pub fn is_playlist(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    name.ends_with(PLAYLIST_SUFFIX)
+        || path
+            .extension()
+            .is_some_and(|extension| extension == "m3u" || extension == "m3u8")
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Playlist {
+    #[serde(default, rename = "entry")]
+    pub entries: Vec<PlaylistEntry>,
+}
+
+/// An image of a playlist. Unset settings are taken from the output.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PlaylistEntry {
+    #[serde(default)]
+    pub path: PathBuf,
+    /// A color or gradient shown instead of an image
+    pub fill: Option<Fill>,
+    /// Seconds to show the image
+    pub duration: Option<u64>,
+    pub fit: Option<FitMode>,
+    pub transition: Option<EntryTransition>,
+    /// Shown around the image where it doesn't cover the output
+    pub background: Option<Background>,
+}
+
+/// The transition to an entry
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum EntryTransition {
+    /// `false` for no transition, `true` for the transition of the output even if transitions
+    /// are disabled
+    Enabled(bool),
+    /// One or more transition types, with the other settings of the output's transition
+    Type(TransitionSelection),
+    Config(TransitionConfig),
+}
+
+impl Playlist {
+    /// Read a playlist. Relative paths are relative to the playlist.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("unable to read playlist {}", path.display()))?;
+        let mut playlist = match path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(PLAYLIST_SUFFIX))
+        {
+            true => toml::from_str(&content)
+                .wrap_err_with(|| format!("invalid playlist {}", path.display()))?,
+            false => Self::from_m3u(&content),
+        };
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for entry in &mut playlist.entries {
+            entry.path = match &entry.fill {
+                Some(fill) => fill.to_path(),
+                None => dir.join(&entry.path),
+            };
+        }
+        Ok(playlist)
+    }
+
+    /// The entries by image, in the order they are listed. An image listed more than once has
+    /// the settings of each listing.
+    pub fn by_image(self) -> HashMap<PathBuf, Vec<PlaylistEntry>> {
+        let mut by_image = HashMap::<_, Vec<_>>::new();
+        for entry in self.entries {
+            by_image.entry(entry.path.clone()).or_default().push(entry);
+        }
+        by_image
+    }
+
+    fn from_m3u(content: &str) -> Self {
+        let mut entries = vec![];
+        let mut duration = None;
+        for line in content.lines().map(str::trim) {
+            if let Some(info) = line.strip_prefix("#EXTINF:") {
+                // `#EXTINF:<seconds>,<title>`, -1 for unknown
+                duration = info
+                    .split(',')
+                    .next()
+                    .and_then(|seconds| seconds.trim().parse::<f64>().ok())
+                    .filter(|seconds| *seconds > 0.0)
+                    .map(|seconds| seconds.round() as u64);
+            } else if !line.is_empty() && !line.starts_with('#') {
+                entries.push(PlaylistEntry {
+                    duration: duration.take(),
+                    ..PlaylistEntry::new(line.into())
+                });
+            }
+        }
+        Self { entries }
+    }
+}
+
+impl PlaylistEntry {
+    /// An entry with the settings of the output
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            fill: None,
+            duration: None,
+            fit: None,
+            transition: None,
+            background: None,
+        }
+    }
+
+    /// The transition to this entry, None to show it right away
+    pub fn get_transition_config(
+        &self,
+        output_config: &OutputConfig,
+        global_config: &Config,
+    ) -> Option<TransitionConfig> {
+        let output_transition = output_config.get_transition_config(global_config);
+        match &self.transition {
+            None => output_transition
+                .filter(|_| output_config.is_transitions_enabled(global_config))
+                .cloned(),
+            Some(EntryTransition::Enabled(enabled)) => {
+                output_transition.filter(|_| *enabled).cloned()
+            }
+            Some(EntryTransition::Type(transition_type)) => Some(TransitionConfig {
+                transition_type: transition_type.clone(),
+                duration_ms: output_transition.map_or_else(default_duration, |t| t.duration_ms),
+                fps: output_transition.map_or_else(default_fps, |t| t.fps),
+                slide: output_transition
+                    .map(|t| t.slide.clone())
+                    .unwrap_or_default(),
+                wipe: output_transition
+                    .map(|t| t.wipe.clone())
+                    .unwrap_or_default(),
+                circle: output_transition
+                    .map(|t| t.circle.clone())
+                    .unwrap_or_default(),
+                dissolve: output_transition
+                    .map(|t| t.dissolve.clone())
+                    .unwrap_or_default(),
+                zoom: output_transition
+                    .map(|t| t.zoom.clone())
+                    .unwrap_or_default(),
+                blur: output_transition
+                    .map(|t| t.blur.clone())
+                    .unwrap_or_default(),
+                pixelate: output_transition
+                    .map(|t| t.pixelate.clone())
+                    .unwrap_or_default(),
+                params: output_transition
+                    .map(|t| t.params.clone())
+                    .unwrap_or_default(),
+                easing: output_transition.map(|t| t.easing).unwrap_or_default(),
+            }),
+            Some(EntryTransition::Config(transition)) => Some(transition.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::TransitionTypeEnum, palette::Rgb};
+
+    #[test]
+    fn test_parse_playlists() {
+        let playlist: Playlist = toml::from_str(
+            r##"
+            [[entry]]
+            path = "logo.png"
+            duration = 300
+            fit = "contain"
+            background = "#ffffff"
+            transition = false
+
+            [[entry]]
+            path = "/photos/a.jpg"
+            transition = ["slide", "crossfade"]
+            background = "blur"
+
+            [[entry]]
+            path = "/photos/b.jpg"
+            transition = { type = "slide", duration_ms = 500 }
+
+            [[entry]]
+            fill = "radial-gradient(#89b4fa, #1e1e2e)"
+            duration = 30
+            "##,
+        )
+        .unwrap();
+        assert_eq!(playlist.entries.len(), 4);
+        assert_eq!(playlist.entries[0].duration, Some(300));
+        assert_eq!(playlist.entries[0].fit, Some(FitMode::Contain));
+        assert_eq!(
+            playlist.entries[0].background,
+            Some(Background::Fill(Fill::Color(Rgb::new(255, 255, 255))))
+        );
+        assert_eq!(
+            playlist.entries[0].transition,
+            Some(EntryTransition::Enabled(false))
+        );
+        assert_eq!(playlist.entries[1].background, Some(Background::Blur));
+        assert_eq!(
+            playlist.entries[1].transition,
+            Some(EntryTransition::Type(TransitionSelection::Random(vec![
+                TransitionTypeEnum::Slide,
+                TransitionTypeEnum::Crossfade
+            ])))
+        );
+        assert!(matches!(
+            playlist.entries[2].transition,
+            Some(EntryTransition::Config(TransitionConfig {
+                duration_ms: 500,
+                ..
+            }))
+        ));
+        assert!(matches!(playlist.entries[3].fill, Some(Fill::Gradient(_))));
+
+        let playlist = Playlist::from_m3u(
+            "#EXTM3U\n#EXTINF:300,Logo\nlogo.png\n\n#EXTINF:-1,Photo\nphotos/a.jpg\nphotos/b.jpg\n",
+        );
+        let entries = playlist
+            .entries
+            .iter()
+            .map(|entry| (entry.path.to_str().unwrap(), entry.duration))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                ("logo.png", Some(300)),
+                ("photos/a.jpg", None),
+                ("photos/b.jpg", None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repeated_entries() {
+        let playlist =
+            Playlist::from_m3u("#EXTINF:300,Logo\nlogo.png\na.jpg\n#EXTINF:10,Logo\nlogo.png\n");
+        let by_image = playlist.by_image();
+        let durations = by_image[Path::new("logo.png")]
+            .iter()
+            .map(|entry| entry.duration)
+            .collect::<Vec<_>>();
+        assert_eq!(durations, vec![Some(300), Some(10)]);
+        assert_eq!(by_image[Path::new("a.jpg")].len(), 1);
+    }
+}
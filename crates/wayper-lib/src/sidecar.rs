@@ -0,0 +1,132 @@
+//! Sidecar files, the settings of a single image in a `<image>.wayper.toml` file next to it.
+//!
+//! They override how the output fits the image: its `fit`, `align` and `zoom`, the `focus` point
+//! kept in view when the image is cropped, and a `rotation` for images stored sideways.
+
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{Context, Result};
+use serde::Deserialize;
+
+use crate::config::{Align, FitMode};
+
+pub const SIDECAR_SUFFIX: &str = ".wayper.toml";
+
+/// Settings of an image, unset ones are taken from the output
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Sidecar {
+    pub fit: Option<FitMode>,
+    pub align: Option<Align>,
+    /// Point of the image kept in view when it is cropped, in fractions of its width and height
+    pub focus: Option<[f32; 2]>,
+    pub zoom: Option<f32>,
+    #[serde(default)]
+    pub rotation: Rotation,
+}
+
+/// Clockwise rotation of an image, written in degrees
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u32")]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl TryFrom<u32> for Rotation {
+    type Error = String;
+
+    fn try_from(degrees: u32) -> Result<Self, Self::Error> {
+        match degrees {
+            0 => Ok(Rotation::None),
+            90 => Ok(Rotation::Clockwise90),
+            180 => Ok(Rotation::Clockwise180),
+            270 => Ok(Rotation::Clockwise270),
+            _ => Err(format!(
+                "invalid rotation {degrees}, expected 0, 90, 180 or 270"
+            )),
+        }
+    }
+}
+
+impl Rotation {
+    pub fn quarter_turns(&self) -> u32 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 1,
+            Rotation::Clockwise180 => 2,
+            Rotation::Clockwise270 => 3,
+        }
+    }
+}
+
+impl Sidecar {
+    /// The sidecar file of an image
+    pub fn path(image: &Path) -> PathBuf {
+        let mut path = image.as_os_str().to_owned();
+        path.push(SIDECAR_SUFFIX);
+        path.into()
+    }
+
+    /// The sidecar of an image, None if it has none
+    pub fn load(image: &Path) -> Result<Option<Self>> {
+        let path = Self::path(image);
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e)
+                    .wrap_err_with(|| format!("unable to read sidecar {}", path.display()));
+            }
+        };
+        toml::from_str(&content)
+            .map(Some)
+            .wrap_err_with(|| format!("invalid sidecar {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{HorizontalAlign, VerticalAlign};
+
+    #[test]
+    fn test_parse_sidecars() {
+        let sidecar: Sidecar = toml::from_str(
+            r#"
+            fit = "cover"
+            align = { horizontal = "left" }
+            focus = [0.4, 0.2]
+            zoom = 1.5
+            rotation = 90
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            sidecar,
+            Sidecar {
+                fit: Some(FitMode::Cover),
+                align: Some(Align {
+                    horizontal: HorizontalAlign::Left,
+                    vertical: VerticalAlign::Center,
+                }),
+                focus: Some([0.4, 0.2]),
+                zoom: Some(1.5),
+                rotation: Rotation::Clockwise90,
+            }
+        );
+        assert_eq!(toml::from_str::<Sidecar>("").unwrap(), Sidecar::default());
+        assert!(toml::from_str::<Sidecar>("rotation = 45").is_err());
+
+        assert_eq!(
+            Sidecar::path(Path::new("/photos/a.jpg")),
+            PathBuf::from("/photos/a.jpg.wayper.toml")
+        );
+        assert_eq!(
+            Sidecar::load(Path::new("/nonexistent/a.jpg")).unwrap(),
+            None
+        );
+    }
+}
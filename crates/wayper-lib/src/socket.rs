@@ -16,6 +16,8 @@ use color_eyre::eyre::{WrapErr, eyre};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::palette::{Palette, Rgb};
+
 /// Get the socket path for the current Wayland display.
 /// Each Wayland display gets its own socket to allow multiple wayper instances.
 pub fn get_socket_path() -> color_eyre::Result<PathBuf> {
@@ -199,6 +201,110 @@ pub enum SocketCommand {
 
     /// Display GPU performance metrics
     GpuMetrics,
+
+    /// Show the results of recent hook runs
+    Hooks {
+        /// Number of runs to show, newest first
+        #[arg(short, long, default_value_t = 10)]
+        limit: usize,
+    },
+
+    /// Show an image right away. The rotation continues after it on the next cycle.
+    Set {
+        image: PathBuf,
+        /// If unspecified, the image is shown on all outputs
+        #[arg(short, long)]
+        output_name: Option<String>,
+    },
+
+    /// Rescan the wallpaper directories. Changes are normally picked up automatically.
+    Rescan {
+        #[arg(short, long)]
+        output_name: Option<String>,
+    },
+
+    /// Show the color palette of the current wallpaper
+    Palette {
+        #[arg(short, long)]
+        output_name: Option<String>,
+    },
+
+    /// Add the current image to the favorites. Show them with `path = "@favorites"`.
+    ///
+    /// If no output name is specified, the primary output or the only output is used.
+    Favorite {
+        #[arg(short, long)]
+        output_name: Option<String>,
+    },
+    /// Never show the current image again
+    ///
+    /// If no output name is specified, the primary output or the only output is used.
+    Ban {
+        #[arg(short, long)]
+        output_name: Option<String>,
+    },
+    /// Move the current image to the trash and show the next one
+    ///
+    /// If no output name is specified, the primary output or the only output is used.
+    Trash {
+        #[arg(short, long)]
+        output_name: Option<String>,
+    },
+
+    /// Go back to the image shown before. Repeat to go further back.
+    Previous {
+        /// If unspecified, all outputs go back
+        #[arg(short, long)]
+        output_name: Option<String>,
+    },
+
+    /// Show the images shown recently, newest first
+    History {
+        #[arg(short, long)]
+        output_name: Option<String>,
+        #[arg(short, long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Show the most shown images, the images never shown and their screen time
+    Stats {
+        /// Number of images to list per section
+        #[arg(short, long, default_value_t = 10)]
+        limit: usize,
+    },
+
+    /// Show images that failed to load and outputs with nothing to show
+    Problems,
+
+    /// Show the images coming up next
+    Queue {
+        #[arg(short, long)]
+        output_name: Option<String>,
+        #[arg(short, long, default_value_t = 10)]
+        limit: usize,
+    },
+
+    /// Show images next, in the given order, before the rotation continues
+    Enqueue {
+        #[arg(required = true)]
+        images: Vec<PathBuf>,
+        /// If unspecified, the images are queued on all outputs
+        #[arg(short, long)]
+        output_name: Option<String>,
+    },
+
+    /// Remove an image from the queue, or clear the queue if no image is given
+    Dequeue {
+        image: Option<PathBuf>,
+        #[arg(short, long)]
+        output_name: Option<String>,
+    },
+
+    /// Shuffle the images of the rotation that haven't been shown yet in this cycle
+    Shuffle {
+        #[arg(short, long)]
+        output_name: Option<String>,
+    },
 }
 
 fn profiles_from_socket_or_config() -> Vec<CompletionCandidate> {
@@ -252,6 +358,16 @@ pub enum SocketError {
     UnexpectedError,
     #[error("Profile \"{0}\" is not defined.")]
     NoProfile(String),
+    #[error("Image not found: {}", path.display())]
+    ImageNotFound { path: PathBuf },
+    #[error("There is no earlier image in the history of {output}")]
+    NoPreviousImage { output: String },
+    #[error("There is more than one output, pick one with --output-name")]
+    NoOutputSelected,
+    #[error("Unable to update {}: {error}", path.display())]
+    ImageActionFailed { path: PathBuf, error: String },
+    #[error("The palette of the current image on {output} is not ready yet")]
+    PaletteNotReady { output: String },
 
     #[error("Daemon unimplemented command: {command}")]
     CommandUnimplemented { command: String },
@@ -279,7 +395,7 @@ impl SocketError {
 
 /// Wrapper type over possible responses from the daemon. These implement serialization to
 /// be sent across the socket
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub enum SocketOutput {
     /// A string message
     Message(String),
@@ -292,6 +408,18 @@ pub enum SocketOutput {
     Profiles(Vec<String>),
     /// GPU performance metrics
     GpuMetrics(GpuMetricsData),
+    /// Results of recent hook runs
+    HookReports(Vec<HookReport>),
+    /// Palettes of the current wallpapers
+    Palettes(Vec<OutputPalette>),
+    /// Images that failed to load and outputs with nothing to show
+    Problems(Vec<Problem>),
+    /// Recently shown images, newest first
+    History(Vec<HistoryEntry>),
+    /// How much images have been shown
+    Stats(Stats),
+    /// Images coming up next per output
+    Queue(Vec<OutputQueue>),
     /// Signals end of reply for the previous request.
     End(String),
 }
@@ -373,6 +501,32 @@ impl std::fmt::Display for SocketOutput {
                 .join("\n"),
             SocketOutput::Profiles(items) => items.join("\n"),
             SocketOutput::GpuMetrics(metrics) => metrics.to_string(),
+            SocketOutput::HookReports(reports) => reports
+                .iter()
+                .map(|report| report.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            SocketOutput::Palettes(palettes) => palettes
+                .iter()
+                .map(|palette| palette.to_string())
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            SocketOutput::History(entries) => entries
+                .iter()
+                .map(|entry| entry.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            SocketOutput::Stats(stats) => stats.to_string(),
+            SocketOutput::Queue(queues) => queues
+                .iter()
+                .map(|queue| queue.to_string())
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            SocketOutput::Problems(problems) => problems
+                .iter()
+                .map(|problem| problem.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
             SocketOutput::End(command) => format!("end of command {command}"),
         };
 
@@ -395,6 +549,320 @@ impl std::fmt::Display for OutputWallpaper {
     }
 }
 
+/// Images coming up next on an output
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct OutputQueue {
+    pub output_name: String,
+    pub upcoming: Vec<UpcomingImage>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct UpcomingImage {
+    pub image: PathBuf,
+    /// Queued by hand rather than next in the rotation
+    pub queued: bool,
+}
+
+impl std::fmt::Display for OutputQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:", self.output_name)?;
+        if self.upcoming.is_empty() {
+            write!(f, " nothing to show")?;
+        }
+        for (position, upcoming) in self.upcoming.iter().enumerate() {
+            write!(f, "\n  {:>2}. {}", position + 1, upcoming.image.display())?;
+            if upcoming.queued {
+                write!(f, " (queued)")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Palette of the wallpaper currently shown on an output
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct OutputPalette {
+    pub output_name: String,
+    pub wallpaper: String,
+    pub palette: Palette,
+}
+
+impl std::fmt::Display for OutputPalette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}: {}", self.output_name, self.wallpaper)?;
+        for (role, color) in self.palette.roles() {
+            writeln!(f, "  {role:<10}{color}")?;
+        }
+        let swatches = self
+            .palette
+            .swatches
+            .iter()
+            .map(|swatch| format!("{} ({:.0}%)", swatch.color, swatch.population * 100.0))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(f, "  {:<10}{swatches}", "swatches")?;
+        let tones = |tones: &std::collections::BTreeMap<u8, Rgb>| {
+            tones
+                .iter()
+                .map(|(tone, color)| format!("{tone}:{color}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        writeln!(
+            f,
+            "  {:<10}{}",
+            "primary",
+            tones(&self.palette.primary_tones)
+        )?;
+        write!(
+            f,
+            "  {:<10}{}",
+            "neutral",
+            tones(&self.palette.neutral_tones)
+        )
+    }
+}
+
+/// Why an image was shown
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum ShowTrigger {
+    /// The first image after the output appeared
+    Startup,
+    /// The rotation timer
+    Rotation,
+    /// Asked for the next image
+    Next,
+    /// `wayperctl set`
+    Set,
+    /// `wayperctl previous`
+    Previous,
+    /// The image before failed to load or was banned or trashed
+    Skip,
+}
+
+/// An image shown on an output
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct HistoryEntry {
+    pub output_name: String,
+    pub image: PathBuf,
+    /// Unix timestamp in seconds
+    pub shown_at: u64,
+    /// Seconds the image was shown for, None while it still is or if the daemon stopped
+    pub duration_shown: Option<u64>,
+    pub trigger: ShowTrigger,
+}
+
+impl std::fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or_default();
+        let shown = match self.duration_shown {
+            Some(duration) => format!("for {}", format_duration(duration)),
+            None => "until now".to_string(),
+        };
+        write!(
+            f,
+            "{:>8} ago  {}: {} ({}, {shown})",
+            format_duration(now.saturating_sub(self.shown_at)),
+            self.output_name,
+            self.image.display(),
+            self.trigger
+        )
+    }
+}
+
+/// How much an image has been shown
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct ImageStats {
+    pub image: PathBuf,
+    pub times_shown: u64,
+    /// Seconds on screen, on all outputs
+    pub screen_time: u64,
+}
+
+/// Summary of the history
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct Stats {
+    /// Most shown first
+    pub most_shown: Vec<ImageStats>,
+    /// Most screen time first
+    pub longest_shown: Vec<ImageStats>,
+    /// Images in the current rotations that were never shown
+    pub never_shown: Vec<PathBuf>,
+    /// Number of images in the current rotations that were never shown
+    pub never_shown_count: usize,
+    /// Seconds of screen time of all images
+    pub total_screen_time: u64,
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |f: &mut std::fmt::Formatter<'_>, images: &[ImageStats]| {
+            for image in images {
+                writeln!(
+                    f,
+                    "  {:>4}x {:>8}  {}",
+                    image.times_shown,
+                    format_duration(image.screen_time),
+                    image.image.display()
+                )?;
+            }
+            Ok(())
+        };
+        writeln!(
+            f,
+            "Total screen time: {}",
+            format_duration(self.total_screen_time)
+        )?;
+        writeln!(f, "Most shown:")?;
+        list(f, &self.most_shown)?;
+        writeln!(f, "Longest shown:")?;
+        list(f, &self.longest_shown)?;
+        write!(f, "Never shown: {}", self.never_shown_count)?;
+        for image in &self.never_shown {
+            write!(f, "\n  {}", image.display())?;
+        }
+        if self.never_shown_count > self.never_shown.len() {
+            write!(
+                f,
+                "\n  and {} more",
+                self.never_shown_count - self.never_shown.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Format seconds like `1h 02m`, `5m 03s` or `42s`
+fn format_duration(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// Something keeping wallpapers from being shown
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub enum Problem {
+    /// An image failed to load and is skipped in the rotation
+    Quarantined {
+        image: PathBuf,
+        error: String,
+        /// Number of failed loads
+        failures: u32,
+        /// The image gets another try on the next cycle
+        retry: bool,
+    },
+    /// An output has no image that can be shown and shows its fallback
+    NothingToShow { output_name: String, path: PathBuf },
+    /// The command or the URL giving the images of an output failed
+    SourceFailed { output_name: String, error: String },
+    /// A shader failed to compile, with the line it failed on
+    ShaderFailed { shader: PathBuf, error: String },
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Quarantined {
+                image,
+                error,
+                failures,
+                retry,
+            } => {
+                let status = if *retry {
+                    "retrying on the next cycle"
+                } else {
+                    "skipped until restart"
+                };
+                write!(
+                    f,
+                    "{}: {error} (failed {failures}x, {status})",
+                    image.display()
+                )
+            }
+            Problem::NothingToShow { output_name, path } => write!(
+                f,
+                "{output_name}: nothing to show in {}, showing the fallback",
+                path.display()
+            ),
+            Problem::SourceFailed { output_name, error } => {
+                write!(f, "{output_name}: source failed: {error}")
+            }
+            Problem::ShaderFailed { shader, error } => {
+                write!(f, "{}: {error}", shader.display())
+            }
+        }
+    }
+}
+
+/// How a hook run ended
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HookStatus {
+    /// The command exited on its own
+    Exited,
+    /// The command was killed after running past its timeout
+    TimedOut,
+    /// The command was killed because a newer event replaced it
+    Replaced,
+    /// The command could not be started
+    SpawnFailed,
+}
+
+/// Result of a single hook run
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct HookReport {
+    /// The event that triggered the hook
+    pub event: String,
+    pub output_name: Option<String>,
+    /// The command after placeholder substitution
+    pub command: String,
+    pub status: HookStatus,
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    /// Unix timestamp in seconds of when the run finished
+    pub finished_at: u64,
+    /// Last lines of stdout
+    pub stdout_tail: String,
+    /// Last lines of stderr
+    pub stderr_tail: String,
+}
+
+impl std::fmt::Display for HookReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match (self.status, self.exit_code) {
+            (HookStatus::Exited, Some(code)) => format!("exited with code {code}"),
+            (HookStatus::Exited, None) => "killed by signal".to_string(),
+            (HookStatus::TimedOut, _) => "timed out".to_string(),
+            (HookStatus::Replaced, _) => "replaced by a newer event".to_string(),
+            (HookStatus::SpawnFailed, _) => "failed to start".to_string(),
+        };
+        write!(f, "[{}]", self.event)?;
+        if let Some(output_name) = &self.output_name {
+            write!(f, " {output_name}")?;
+        }
+        write!(
+            f,
+            " `{}` {status} after {}ms",
+            self.command, self.duration_ms
+        )?;
+        for (name, tail) in [("stdout", &self.stdout_tail), ("stderr", &self.stderr_tail)] {
+            if !tail.is_empty() {
+                write!(f, "\n  {name}:")?;
+                for line in tail.lines() {
+                    write!(f, "\n    {line}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 /// GPU performance metrics data
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Default)]
 pub struct GpuMetricsData {
@@ -0,0 +1,54 @@
+@group(0) @binding(0)
+var blur_sampler: sampler;
+@group(0) @binding(1)
+var source: texture_2d<f32>;
+@group(0) @binding(2)
+var<uniform> params: BlurParams;
+
+struct BlurParams {
+       // one texel along the blur, zero to copy the source
+       direction: vec2<f32>,
+       // the source is scaled to cover this size when `cover` is set
+       output_size: vec2<f32>,
+       // multiplied into the color
+       dim: f32,
+       cover: u32,
+       // clockwise quarter turns of the source when covering
+       rotation: u32,
+       _pad: f32,
+}
+
+struct VertexOutput {
+       @builtin(position) pos: vec4<f32>,
+       @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+   let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+   var out: VertexOutput;
+   out.pos = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+   out.uv = uv;
+   return out;
+}
+
+// a 9 tap gaussian in 5 samples, the linear filtering blends the texel pairs
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+   var uv = in.uv;
+   if params.cover != 0u {
+      let size = rotated_size(vec2<f32>(textureDimensions(source)), params.rotation);
+      uv = rotated_uv(
+         cover_uv(uv, size, params.output_size, centered_placement()),
+         params.rotation,
+      );
+   }
+   let near = params.direction * 1.3846153846;
+   let far = params.direction * 3.2307692308;
+   var color = textureSample(source, blur_sampler, uv).rgb * 0.2270270270;
+   color += textureSample(source, blur_sampler, uv + near).rgb * 0.3162162162;
+   color += textureSample(source, blur_sampler, uv - near).rgb * 0.3162162162;
+   color += textureSample(source, blur_sampler, uv + far).rgb * 0.0702702703;
+   color += textureSample(source, blur_sampler, uv - far).rgb * 0.0702702703;
+   return vec4<f32>(color * params.dim, 1.0);
+}
@@ -0,0 +1,106 @@
+@group(0) @binding(0)
+var<uniform> node: FillNodeParams;
+
+const KIND_COLOR: u32 = 0u;
+const KIND_LINEAR: u32 = 1u;
+const KIND_RADIAL: u32 = 2u;
+const KIND_CONIC: u32 = 3u;
+const PI: f32 = 3.14159265;
+
+struct FillNodeParams {
+       rect: vec4<f32>,
+       output_size: vec2<f32>,
+       opacity: f32,
+       kind: u32,
+       // in fractions of the rect
+       center: vec2<f32>,
+       // radians clockwise from the top
+       angle: f32,
+       stop_count: u32,
+       // four positions per vector
+       positions: array<vec4<f32>, 2>,
+       // sRGB, mixed like CSS does before going linear
+       colors: array<vec4<f32>, 8>,
+}
+
+struct VertexInput {
+       @location(0) local_pos: vec2<f32>,
+       @location(1) uv: vec2<f32>,
+}
+
+struct VertexOutput {
+       @builtin(position) pos: vec4<f32>,
+       @location(0) uv: vec2<f32>,
+}
+
+fn pixel_to_clip(pos: vec2<f32>, output_size: vec2<f32>) -> vec2<f32> {
+   let normalized = pos / output_size;
+   return vec2<f32>(
+   	  normalized.x * 2.0 - 1.0,
+	  1.0 - normalized.y * 2.0,
+   );
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+   let low = c / 12.92;
+   let high = pow((c + vec3<f32>(0.055)) / 1.055, vec3<f32>(2.4));
+   return select(high, low, c <= vec3<f32>(0.04045));
+}
+
+fn position_of(i: u32) -> f32 {
+   return node.positions[i / 4u][i % 4u];
+}
+
+// how far along the gradient a pixel is, before the stop positions are applied
+fn gradient_t(pixel: vec2<f32>, size: vec2<f32>) -> f32 {
+   let offset = pixel - node.center * size;
+   if node.kind == KIND_LINEAR {
+      // screen y points down
+      let direction = vec2<f32>(sin(node.angle), -cos(node.angle));
+      let length = abs(size.x * direction.x) + abs(size.y * direction.y);
+      return dot(pixel - size * 0.5, direction) / length + 0.5;
+   }
+   if node.kind == KIND_RADIAL {
+      let corners = max(node.center * size, size - node.center * size);
+      return length(offset) / length(corners);
+   }
+   // conic
+   let turn = (atan2(offset.x, -offset.y) - node.angle) / (2.0 * PI);
+   return fract(turn);
+}
+
+fn gradient_color(t: f32) -> vec3<f32> {
+   var color = node.colors[0].rgb;
+   for (var i = 1u; i < node.stop_count; i++) {
+      let start = position_of(i - 1u);
+      let end = position_of(i);
+      let amount = select(clamp((t - start) / (end - start), 0.0, 1.0), step(end, t), end <= start);
+      color = mix(color, node.colors[i].rgb, amount);
+   }
+   return color;
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+   var out: VertexOutput;
+
+   let pixel_pos = node.rect.xy + in.local_pos * node.rect.zw;
+
+   out.pos = vec4<f32>(
+   	   pixel_to_clip(pixel_pos, node.output_size),
+	   0.0,
+	   1.0,
+   );
+
+   out.uv = in.uv;
+   return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+   var color = node.colors[0].rgb;
+   if node.kind != KIND_COLOR {
+      color = gradient_color(gradient_t(in.uv * node.rect.zw, node.rect.zw));
+   }
+   return vec4<f32>(srgb_to_linear(color), node.opacity);
+}
@@ -13,6 +13,12 @@ struct ImageNodeParams {
        opacity: f32,
        fit_mode: u32,
        background: vec4<f32>,
+       align: vec2<f32>,
+       // negative for none
+       focus: vec2<f32>,
+       zoom: f32,
+       // clockwise quarter turns
+       rotation: u32,
 }
 
 struct VertexInput {
@@ -57,6 +63,8 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
        in.uv,
        node.rect.zw,
        node.fit_mode,
+       Placement(node.align, node.focus, node.zoom),
+       node.rotation,
        node.background,
    );
    return vec4<f32>(color.rgb, color.a * node.opacity);
@@ -0,0 +1,38 @@
+// appended to shader wallpapers, after the user's code so error lines match their file
+
+struct Wayper {
+       // size of the output in pixels
+       resolution: vec2<f32>,
+       // seconds since the shader started
+       time: f32,
+       // differs between outputs, to seed them apart
+       output_hash: u32,
+       // dominant, accent, light and dark colors of the image, linear
+       palette: array<vec4<f32>, 4>,
+       // 1 when wayper_image holds an image of the rotation
+       has_image: u32,
+}
+
+struct WayperInput {
+       @builtin(position) position: vec4<f32>,
+       @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> wayper: Wayper;
+
+@group(0) @binding(1)
+var wayper_sampler: sampler;
+
+@group(0) @binding(2)
+var wayper_image: texture_2d<f32>;
+
+// a single triangle covering the output
+@vertex
+fn wayper_vs_main(@builtin(vertex_index) index: u32) -> WayperInput {
+   let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+   var out: WayperInput;
+   out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+   out.uv = uv;
+   return out;
+}
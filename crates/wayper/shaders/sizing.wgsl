@@ -4,22 +4,48 @@ const FIT_COVER: u32 = 2u;
 const FIT_CENTER: u32 = 3u;
 const FIT_TILE: u32 = 4u;
 
+// Where an image sits on the output on top of its fit
+struct Placement {
+    // fractions of the free or cropped space left of and above the image
+    align: vec2<f32>,
+    // point of the image kept in view when it is cropped, negative for none
+    focus: vec2<f32>,
+    zoom: f32,
+}
+
+fn centered_placement() -> Placement {
+    return Placement(vec2<f32>(0.5), vec2<f32>(-1.0), 1.0);
+}
+
 fn inside_unit(uv: vec2<f32>) -> bool {
     return all(uv >= vec2<f32>(0.0)) && all(uv <= vec2<f32>(1.0));
 }
 
-fn centered_uv(screen_uv: vec2<f32>, scale: vec2<f32>) -> vec2<f32> {
-    return (screen_uv - vec2<f32>(0.5)) / scale + vec2<f32>(0.5);
+// `scale` is the size of the image in fractions of the output. Where the image is smaller it is
+// aligned, where it is larger the focus is brought as close to the middle as it can be without
+// uncovering the output.
+fn placed_uv(screen_uv: vec2<f32>, scale: vec2<f32>, placement: Placement) -> vec2<f32> {
+    var offset = (vec2<f32>(1.0) - scale) * placement.align;
+    if placement.focus.x >= 0.0 {
+        let focused = clamp(
+            vec2<f32>(0.5) - placement.focus * scale,
+            vec2<f32>(1.0) - scale,
+            vec2<f32>(0.0),
+        );
+        offset = select(offset, focused, scale > vec2<f32>(1.0));
+    }
+    return (screen_uv - offset) / scale;
 }
 
-fn stretch_uv(screen_uv: vec2<f32>) -> vec2<f32> {
-    return screen_uv;
+fn stretch_uv(screen_uv: vec2<f32>, placement: Placement) -> vec2<f32> {
+    return placed_uv(screen_uv, vec2<f32>(placement.zoom), placement);
 }
 
 fn contain_uv(
     screen_uv: vec2<f32>,
     image_size: vec2<f32>,
     output_size: vec2<f32>,
+    placement: Placement,
 ) -> vec2<f32> {
     let image_aspect = image_size.x / image_size.y;
     let output_aspect = output_size.x / output_size.y;
@@ -34,13 +60,14 @@ fn contain_uv(
         scale.x = image_aspect / output_aspect;
     }
 
-    return centered_uv(screen_uv, scale);
+    return placed_uv(screen_uv, scale * placement.zoom, placement);
 }
 
 fn cover_uv(
     screen_uv: vec2<f32>,
     image_size: vec2<f32>,
     output_size: vec2<f32>,
+    placement: Placement,
 ) -> vec2<f32> {
     let image_aspect = image_size.x / image_size.y;
     let output_aspect = output_size.x / output_size.y;
@@ -55,24 +82,27 @@ fn cover_uv(
         scale.y = output_aspect / image_aspect;
     }
 
-    return centered_uv(screen_uv, scale);
+    return placed_uv(screen_uv, scale * placement.zoom, placement);
 }
 
 fn center_uv(
     screen_uv: vec2<f32>,
     image_size: vec2<f32>,
     output_size: vec2<f32>,
+    placement: Placement,
 ) -> vec2<f32> {
     let scale = image_size / output_size;
-    return centered_uv(screen_uv, scale);
+    return placed_uv(screen_uv, scale * placement.zoom, placement);
 }
 
+// tiles start at the top left corner, they are only zoomed
 fn tile_uv(
     screen_uv: vec2<f32>,
     image_size: vec2<f32>,
     output_size: vec2<f32>,
+    placement: Placement,
 ) -> vec2<f32> {
-    return fract(screen_uv * (output_size / image_size));
+    return fract(screen_uv * (output_size / image_size) / placement.zoom);
 }
 
 fn map_uv(
@@ -80,36 +110,64 @@ fn map_uv(
     image_size: vec2<f32>,
     output_size: vec2<f32>,
     fit_mode: u32,
+    placement: Placement,
 ) -> vec2<f32> {
     if fit_mode == FIT_STRETCH {
-        return stretch_uv(screen_uv);
+        return stretch_uv(screen_uv, placement);
     }
 
     if fit_mode == FIT_CONTAIN {
-        return contain_uv(screen_uv, image_size, output_size);
+        return contain_uv(screen_uv, image_size, output_size, placement);
     }
 
     if fit_mode == FIT_COVER {
-        return cover_uv(screen_uv, image_size, output_size);
+        return cover_uv(screen_uv, image_size, output_size, placement);
     }
 
     if fit_mode == FIT_CENTER {
-        return center_uv(screen_uv, image_size, output_size);
+        return center_uv(screen_uv, image_size, output_size, placement);
     }
 
     if fit_mode == FIT_TILE {
-        return tile_uv(screen_uv, image_size, output_size);
+        return tile_uv(screen_uv, image_size, output_size, placement);
+    }
+
+    return cover_uv(screen_uv, image_size, output_size, placement);
+}
+
+// Size of an image turned clockwise by `rotation` quarter turns
+fn rotated_size(size: vec2<f32>, rotation: u32) -> vec2<f32> {
+    if rotation % 2u == 1u {
+        return size.yx;
     }
+    return size;
+}
 
-    return cover_uv(screen_uv, image_size, output_size);
+// Where a point of an image turned clockwise by `rotation` quarter turns is on its texture
+fn rotated_uv(uv: vec2<f32>, rotation: u32) -> vec2<f32> {
+    switch rotation % 4u {
+        case 1u: {
+            return vec2<f32>(uv.y, 1.0 - uv.x);
+        }
+        case 2u: {
+            return vec2<f32>(1.0) - uv;
+        }
+        case 3u: {
+            return vec2<f32>(1.0 - uv.y, uv.x);
+        }
+        default: {
+            return uv;
+        }
+    }
 }
 
+// Zooming out leaves space around any fit, the background is shown there unless it is tiled
 fn should_use_background(fit_mode: u32, uv: vec2<f32>) -> bool {
-    if fit_mode == FIT_CONTAIN || fit_mode == FIT_CENTER {
-        return !inside_unit(uv);
+    if fit_mode == FIT_TILE {
+        return false;
     }
 
-    return false;
+    return !inside_unit(uv);
 }
 
 fn sample_sized(
@@ -118,15 +176,17 @@ fn sample_sized(
     screen_uv: vec2<f32>,
     output_size: vec2<f32>,
     fit_mode: u32,
+    placement: Placement,
+    rotation: u32,
     background: vec4<f32>,
 ) -> vec4<f32> {
     let dims = textureDimensions(tex);
-    let image_size = vec2<f32>(f32(dims.x), f32(dims.y));
-    let uv = map_uv(screen_uv, image_size, output_size, fit_mode);
+    let image_size = rotated_size(vec2<f32>(f32(dims.x), f32(dims.y)), rotation);
+    let uv = map_uv(screen_uv, image_size, output_size, fit_mode, placement);
 
     if should_use_background(fit_mode, uv) {
         return background;
     }
 
-    return textureSample(tex, samp, uv);
+    return textureSample(tex, samp, rotated_uv(uv, rotation));
 }
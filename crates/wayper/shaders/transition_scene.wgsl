@@ -0,0 +1,117 @@
+@group(0) @binding(0)
+var samp: sampler;
+
+@group(0) @binding(1)
+var from_tex: texture_2d<f32>;
+
+@group(0) @binding(2)
+var to_tex: texture_2d<f32>;
+
+@group(0) @binding(3)
+var<uniform> node: TransitionNodeParams;
+
+// laid out like ImageNodeParams, the opacity is left unused
+struct TransitionImage {
+       rect: vec4<f32>,
+       output_size: vec2<f32>,
+       opacity: f32,
+       fit_mode: u32,
+       background: vec4<f32>,
+       align: vec2<f32>,
+       // negative for none
+       focus: vec2<f32>,
+       zoom: f32,
+       // clockwise quarter turns
+       rotation: u32,
+}
+
+struct TransitionNodeParams {
+       rect: vec4<f32>,
+       output_size: vec2<f32>,
+       // eased
+       progress: f32,
+       kind: u32,
+       from_image: TransitionImage,
+       to_image: TransitionImage,
+       // wipe direction
+       direction: vec2<f32>,
+       // circle center, in fractions of the output
+       center: vec2<f32>,
+       softness: f32,
+       seed: u32,
+       // zoom scale, blur radius, or pixel and noise size in pixels
+       amount: f32,
+       // zero on the first image of an output
+       has_from: u32,
+}
+
+struct VertexInput {
+       @location(0) local_pos: vec2<f32>,
+       @location(1) uv: vec2<f32>,
+}
+
+struct VertexOutput {
+       @builtin(position) pos: vec4<f32>,
+       @location(0) uv: vec2<f32>,
+}
+
+fn pixel_to_clip(pos: vec2<f32>, output_size: vec2<f32>) -> vec2<f32> {
+   let normalized = pos / output_size;
+   return vec2<f32>(
+   	  normalized.x * 2.0 - 1.0,
+	  1.0 - normalized.y * 2.0,
+   );
+}
+
+// an image as it would be drawn on its own, `uv` is in fractions of the output
+fn sample_image(tex: texture_2d<f32>, image: TransitionImage, uv: vec2<f32>) -> vec4<f32> {
+   let rect_uv = (uv * node.output_size - image.rect.xy) / image.rect.zw;
+   let color = sample_sized(
+       tex,
+       samp,
+       rect_uv,
+       image.rect.zw,
+       image.fit_mode,
+       Placement(image.align, image.focus, image.zoom),
+       image.rotation,
+       image.background,
+   );
+   return select(color, image.background, !inside_unit(rect_uv));
+}
+
+fn from_color(uv: vec2<f32>) -> vec4<f32> {
+   let color = sample_image(from_tex, node.from_image, uv);
+   return select(node.from_image.background, color, node.has_from != 0u);
+}
+
+fn to_color(uv: vec2<f32>) -> vec4<f32> {
+   return sample_image(to_tex, node.to_image, uv);
+}
+
+// size of the output in pixels
+fn output_size() -> vec2<f32> {
+   return node.output_size;
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+   var out: VertexOutput;
+
+   let pixel_pos = node.rect.xy + in.local_pos * node.rect.zw;
+
+   out.pos = vec4<f32>(
+   	   pixel_to_clip(pixel_pos, node.output_size),
+	   0.0,
+	   1.0,
+   );
+
+   out.uv = in.uv;
+   return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+   let progress = clamp(node.progress, 0.0, 1.0);
+   let color = transition(in.uv, progress, from_color(in.uv), to_color(in.uv));
+   return vec4<f32>(color.rgb, 1.0);
+}
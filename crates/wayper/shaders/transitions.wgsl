@@ -0,0 +1,128 @@
+const KIND_WIPE: u32 = 0u;
+const KIND_CIRCLE: u32 = 1u;
+const KIND_DISSOLVE: u32 = 2u;
+const KIND_ZOOM: u32 = 3u;
+const KIND_BLUR: u32 = 4u;
+const KIND_PIXELATE: u32 = 5u;
+
+const BLUR_TAPS: u32 = 16u;
+const GOLDEN_ANGLE: f32 = 2.39996323;
+
+// smoothstep is undefined for equal edges
+fn soft_edge(softness: f32) -> f32 {
+   return max(softness, 1e-4);
+}
+
+// rises from 0 to 1 around the middle of the transition, where the images swap
+fn swap(progress: f32) -> f32 {
+   return smoothstep(0.4, 0.6, progress);
+}
+
+// 0 at both ends of the transition, 1 halfway through
+fn peak(progress: f32) -> f32 {
+   return 1.0 - abs(2.0 * progress - 1.0);
+}
+
+fn wipe(uv: vec2<f32>, progress: f32, old_color: vec4<f32>, new_color: vec4<f32>) -> vec4<f32> {
+   let direction = normalize(node.direction);
+   // how far the corners are from the middle along the direction
+   let extent = 0.5 * (abs(direction.x) + abs(direction.y));
+   let softness = soft_edge(node.softness * 2.0 * extent);
+   let edge = mix(-extent - softness, extent + softness, progress);
+   let along = dot(uv - vec2<f32>(0.5), direction);
+   return mix(new_color, old_color, smoothstep(edge - softness, edge + softness, along));
+}
+
+fn circle(uv: vec2<f32>, progress: f32, old_color: vec4<f32>, new_color: vec4<f32>) -> vec4<f32> {
+   let center = node.center * node.output_size;
+   let farthest = length(max(center, node.output_size - center));
+   let softness = soft_edge(node.softness * length(node.output_size));
+   let radius = mix(-softness, farthest + softness, progress);
+   let dist = length(uv * node.output_size - center);
+   return mix(new_color, old_color, smoothstep(radius - softness, radius + softness, dist));
+}
+
+fn hash(cell: vec2<u32>) -> f32 {
+   var h = cell.x * 1664525u + cell.y * 1013904223u + node.seed * 2654435761u;
+   h ^= h >> 16u;
+   h *= 2246822519u;
+   h ^= h >> 13u;
+   h *= 3266489917u;
+   h ^= h >> 16u;
+   return f32(h) / 4294967295.0;
+}
+
+// value noise with cells `amount` pixels wide
+fn noise(uv: vec2<f32>) -> f32 {
+   let position = uv * node.output_size / max(node.amount, 1.0);
+   let cell = vec2<u32>(floor(position));
+   let f = fract(position);
+   let t = f * f * (vec2<f32>(3.0) - 2.0 * f);
+   let top = mix(hash(cell), hash(cell + vec2<u32>(1u, 0u)), t.x);
+   let bottom = mix(hash(cell + vec2<u32>(0u, 1u)), hash(cell + vec2<u32>(1u, 1u)), t.x);
+   return mix(top, bottom, t.y);
+}
+
+fn dissolve(uv: vec2<f32>, progress: f32, old_color: vec4<f32>, new_color: vec4<f32>) -> vec4<f32> {
+   let softness = soft_edge(node.softness);
+   let threshold = mix(-softness, 1.0 + softness, progress);
+   let n = noise(uv);
+   return mix(old_color, new_color, smoothstep(n - softness, n + softness, threshold));
+}
+
+fn zoom(uv: vec2<f32>, progress: f32) -> vec4<f32> {
+   let from_scale = mix(1.0, node.amount, progress);
+   let to_scale = mix(node.amount, 1.0, progress);
+   let old_color = from_color((uv - vec2<f32>(0.5)) / from_scale + vec2<f32>(0.5));
+   let new_color = to_color((uv - vec2<f32>(0.5)) / to_scale + vec2<f32>(0.5));
+   return mix(old_color, new_color, progress);
+}
+
+// taps on a golden angle spiral, denser in the middle
+fn blur(uv: vec2<f32>, progress: f32) -> vec4<f32> {
+   let radius = node.amount * peak(progress);
+   var old_color = vec4<f32>(0.0);
+   var new_color = vec4<f32>(0.0);
+   for (var i = 0u; i < BLUR_TAPS; i++) {
+      let dist = radius * sqrt((f32(i) + 0.5) / f32(BLUR_TAPS));
+      let angle = f32(i) * GOLDEN_ANGLE;
+      let offset = dist * vec2<f32>(cos(angle), sin(angle)) / node.output_size;
+      let tap = clamp(uv + offset, vec2<f32>(0.0), vec2<f32>(1.0));
+      old_color += from_color(tap);
+      new_color += to_color(tap);
+   }
+   return mix(old_color, new_color, swap(progress)) / f32(BLUR_TAPS);
+}
+
+fn pixelate(uv: vec2<f32>, progress: f32) -> vec4<f32> {
+   let size = max(node.amount * peak(progress), 1.0);
+   let block = (floor(uv * node.output_size / size) + vec2<f32>(0.5)) * size;
+   let block_uv = clamp(block / node.output_size, vec2<f32>(0.0), vec2<f32>(1.0));
+   return mix(from_color(block_uv), to_color(block_uv), swap(progress));
+}
+
+fn transition(uv: vec2<f32>, progress: f32, old_color: vec4<f32>, new_color: vec4<f32>) -> vec4<f32> {
+   switch node.kind {
+      case KIND_WIPE: {
+         return wipe(uv, progress, old_color, new_color);
+      }
+      case KIND_CIRCLE: {
+         return circle(uv, progress, old_color, new_color);
+      }
+      case KIND_DISSOLVE: {
+         return dissolve(uv, progress, old_color, new_color);
+      }
+      case KIND_ZOOM: {
+         return zoom(uv, progress);
+      }
+      case KIND_BLUR: {
+         return blur(uv, progress);
+      }
+      case KIND_PIXELATE: {
+         return pixelate(uv, progress);
+      }
+      default: {
+         return mix(old_color, new_color, progress);
+      }
+   }
+}
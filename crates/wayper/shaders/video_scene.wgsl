@@ -0,0 +1,102 @@
+@group(0) @binding(0)
+var samp: sampler;
+
+@group(0) @binding(1)
+var tex_y: texture_2d<f32>;
+
+@group(0) @binding(2)
+var<uniform> node: ImageNodeParams;
+
+@group(0) @binding(3)
+var tex_u: texture_2d<f32>;
+
+@group(0) @binding(4)
+var tex_v: texture_2d<f32>;
+
+struct ImageNodeParams {
+       rect: vec4<f32>,
+       output_size: vec2<f32>,
+       opacity: f32,
+       fit_mode: u32,
+       background: vec4<f32>,
+       align: vec2<f32>,
+       // negative for none
+       focus: vec2<f32>,
+       zoom: f32,
+       // clockwise quarter turns
+       rotation: u32,
+}
+
+struct VertexInput {
+       @location(0) local_pos: vec2<f32>,
+       @location(1) uv: vec2<f32>,
+}
+
+struct VertexOutput {
+       @builtin(position) pos: vec4<f32>,
+       @location(0) uv: vec2<f32>,
+}
+
+fn pixel_to_clip(pos: vec2<f32>, output_size: vec2<f32>) -> vec2<f32> {
+   let normalized = pos / output_size;
+   return vec2<f32>(
+   	  normalized.x * 2.0 - 1.0,
+	  1.0 - normalized.y * 2.0,
+   );
+}
+
+// limited range BT.709, what nearly every video is encoded with
+fn yuv_to_rgb(yuv: vec3<f32>) -> vec3<f32> {
+   let y = (yuv.x - 16.0 / 255.0) * (255.0 / 219.0);
+   let u = (yuv.y - 128.0 / 255.0) * (255.0 / 224.0);
+   let v = (yuv.z - 128.0 / 255.0) * (255.0 / 224.0);
+   let rgb = vec3<f32>(
+       y + 1.5748 * v,
+       y - 0.1873 * u - 0.4681 * v,
+       y + 1.8556 * u,
+   );
+   return clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// image textures are sRGB and sampled linear, videos have to match
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+   let low = c / 12.92;
+   let high = pow((c + vec3<f32>(0.055)) / 1.055, vec3<f32>(2.4));
+   return select(high, low, c <= vec3<f32>(0.04045));
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+   var out: VertexOutput;
+
+   let pixel_pos = node.rect.xy + in.local_pos * node.rect.zw;
+
+   out.pos = vec4<f32>(
+   	   pixel_to_clip(pixel_pos, node.output_size),
+	   0.0,
+	   1.0,
+   );
+
+   out.uv = in.uv;
+   return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+   let dims = textureDimensions(tex_y);
+   let video_size = rotated_size(vec2<f32>(f32(dims.x), f32(dims.y)), node.rotation);
+   let placement = Placement(node.align, node.focus, node.zoom);
+   let uv = map_uv(in.uv, video_size, node.rect.zw, node.fit_mode, placement);
+   let texture_uv = rotated_uv(uv, node.rotation);
+
+   let yuv = vec3<f32>(
+       textureSample(tex_y, samp, texture_uv).r,
+       textureSample(tex_u, samp, texture_uv).r,
+       textureSample(tex_v, samp, texture_uv).r,
+   );
+   var color = vec4<f32>(srgb_to_linear(yuv_to_rgb(yuv)), 1.0);
+   if should_use_background(node.fit_mode, uv) {
+       color = node.background;
+   }
+   return vec4<f32>(color.rgb, color.a * node.opacity);
+}
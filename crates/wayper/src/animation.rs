@@ -0,0 +1,218 @@
+//! Animated GIF, APNG and WebP images: decoding their frames, and the clock picking the frame an
+//! output shows. The clock also keeps the play time of videos.
+
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use image::{
+    AnimationDecoder, ImageFormat, ImageResult, RgbaImage,
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+};
+use wayper_lib::config::AnimationConfig;
+
+/// Delays below this are shown as [`DEFAULT_DELAY`], like browsers do
+const MIN_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+/// The most time a tick moves an animation on. Outputs get no frame callbacks while hidden or
+/// idle, a longer gap is a pause.
+const MAX_STEP: Duration = Duration::from_millis(250);
+
+pub struct Frame {
+    pub image: RgbaImage,
+    /// How long the frame is shown, zero for a still image
+    pub delay: Duration,
+}
+
+/// Decode the frames of an animated image, None if it isn't animated. Frames past the limits are
+/// dropped.
+pub fn decode_frames(
+    image_path: &Path,
+    bytes: &[u8],
+    limits: &AnimationConfig,
+) -> ImageResult<Option<Vec<Frame>>> {
+    let frames = match image::guess_format(bytes) {
+        Ok(ImageFormat::Gif) => GifDecoder::new(Cursor::new(bytes))?.into_frames(),
+        Ok(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(Cursor::new(bytes))?;
+            if !decoder.is_apng()? {
+                return Ok(None);
+            }
+            decoder.apng()?.into_frames()
+        }
+        Ok(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(Cursor::new(bytes))?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+        _ => return Ok(None),
+    };
+
+    let max_bytes = limits.max_mb * 1024 * 1024;
+    let mut decoded = vec![];
+    let mut total_bytes = 0;
+    for frame in frames {
+        let frame = frame?;
+        let delay = match Duration::from(frame.delay()) {
+            delay if delay < MIN_DELAY => DEFAULT_DELAY,
+            delay => delay,
+        };
+        let image = frame.into_buffer();
+        total_bytes += image.as_raw().len() as u64;
+        if decoded.len() >= limits.max_frames.max(1)
+            || (total_bytes > max_bytes && !decoded.is_empty())
+        {
+            tracing::warn!(
+                "{} has too many frames, showing the first {}",
+                image_path.display(),
+                decoded.len()
+            );
+            break;
+        }
+        decoded.push(Frame { image, delay });
+    }
+    // a single frame is a still image
+    Ok((decoded.len() > 1).then_some(decoded))
+}
+
+/// Where an output is in an animated image or a video, moved on from its frame callbacks so
+/// every frame stays up for its delay
+#[derive(Debug, Clone)]
+pub struct AnimationClock {
+    pub image: PathBuf,
+    /// Delays of the frames, empty for a video
+    delays: Arc<[Duration]>,
+    pub frame: usize,
+    /// Time spent on the current frame
+    elapsed: Duration,
+    /// Time played in all, paused time not counted
+    pub position: Duration,
+    last_tick: Option<Instant>,
+}
+
+impl AnimationClock {
+    pub fn new(image: PathBuf, delays: Arc<[Duration]>) -> Self {
+        Self {
+            image,
+            delays,
+            frame: 0,
+            elapsed: Duration::ZERO,
+            position: Duration::ZERO,
+            last_tick: None,
+        }
+    }
+
+    /// A clock for a video, its frames are picked by play time
+    pub fn video(video: PathBuf) -> Self {
+        Self::new(video, Arc::new([]))
+    }
+
+    /// Move on by the time since the last tick. Returns true if the frame changed, always for a
+    /// video that played on.
+    pub fn tick(&mut self, now: Instant) -> bool {
+        let step = self.last_tick.map_or(Duration::ZERO, |last_tick| {
+            now.saturating_duration_since(last_tick).min(MAX_STEP)
+        });
+        self.last_tick = Some(now);
+        self.position += step;
+        if self.delays.is_empty() {
+            return !step.is_zero();
+        }
+        self.elapsed += step;
+
+        let previous = self.frame;
+        while self.elapsed >= self.delays[self.frame] {
+            self.elapsed -= self.delays[self.frame];
+            self.frame = (self.frame + 1) % self.delays.len();
+        }
+        self.frame != previous
+    }
+
+    /// Stop the clock until the next tick
+    pub fn pause(&mut self) {
+        self.last_tick = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{
+        Delay, Frame as ImageFrame, Rgba,
+        codecs::gif::{GifEncoder, Repeat},
+    };
+
+    use super::*;
+
+    fn gif(delays_ms: &[u32]) -> Vec<u8> {
+        let mut bytes = vec![];
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            encoder.set_repeat(Repeat::Infinite).unwrap();
+            for (i, delay) in delays_ms.iter().enumerate() {
+                let image = RgbaImage::from_pixel(4, 4, Rgba([i as u8 * 50, 0, 0, 255]));
+                let delay = Delay::from_numer_denom_ms(*delay, 1);
+                encoder
+                    .encode_frame(ImageFrame::from_parts(image, 0, 0, delay))
+                    .unwrap();
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_decode_frames() {
+        let path = Path::new("a.gif");
+        let limits = AnimationConfig::default();
+        let frames = decode_frames(path, &gif(&[50, 0, 200]), &limits)
+            .unwrap()
+            .unwrap();
+        let delays = frames.iter().map(|frame| frame.delay).collect::<Vec<_>>();
+        assert_eq!(delays, [50, 100, 200].map(Duration::from_millis).to_vec());
+
+        let limits = AnimationConfig {
+            max_frames: 2,
+            ..limits
+        };
+        let frames = decode_frames(path, &gif(&[50, 50, 50]), &limits)
+            .unwrap()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+
+        assert!(decode_frames(path, &gif(&[50]), &limits).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_clock() {
+        let delays: Arc<[Duration]> = [100, 200].map(Duration::from_millis).into();
+        let mut clock = AnimationClock::new(PathBuf::from("a.gif"), delays);
+        let start = Instant::now();
+        assert!(!clock.tick(start));
+        assert!(!clock.tick(start + Duration::from_millis(90)));
+        assert!(clock.tick(start + Duration::from_millis(110)));
+        assert_eq!(clock.frame, 1);
+
+        // a long gap moves on by a single step at most, 10 + 250ms is 60ms into the first frame
+        assert!(clock.tick(start + Duration::from_secs(60)));
+        assert_eq!(clock.frame, 0);
+        assert!(!clock.tick(start + Duration::from_millis(60_030)));
+        assert!(clock.tick(start + Duration::from_millis(60_050)));
+        assert_eq!(clock.frame, 1);
+
+        // paused, the time in between doesn't count
+        clock.pause();
+        assert!(!clock.tick(start + Duration::from_secs(120)));
+        assert_eq!(clock.frame, 1);
+        assert_eq!(clock.position, Duration::from_millis(410));
+
+        let mut clock = AnimationClock::video(PathBuf::from("a.mp4"));
+        assert!(!clock.tick(start));
+        assert!(clock.tick(start + Duration::from_millis(16)));
+        assert_eq!(clock.position, Duration::from_millis(16));
+        assert_eq!(clock.frame, 0);
+    }
+}
@@ -0,0 +1,189 @@
+//! Wallpaper packs read straight from `.zip`, `.tar`, `.tar.gz` and `.tar.zst` archives.
+//!
+//! An image inside an archive is addressed as `pack.zip!/dir/image.jpg`, so it can be passed
+//! around, shown and saved like any other image path. Only the archive exists on disk, its
+//! modification time and size stand in for those of its images.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+/// Between the path of the archive and the name of the entry
+const SEPARATOR: &str = "!/";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+}
+
+fn kind(path: &Path) -> Option<Kind> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    [
+        (".zip", Kind::Zip),
+        (".tar", Kind::Tar),
+        (".tar.gz", Kind::TarGz),
+        (".tgz", Kind::TarGz),
+        (".tar.zst", Kind::TarZst),
+        (".tzst", Kind::TarZst),
+    ]
+    .into_iter()
+    .find(|(suffix, _)| name.ends_with(suffix))
+    .map(|(_, kind)| kind)
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    kind(path).is_some()
+}
+
+/// The archive and the entry name of an image inside an archive
+pub fn split(path: &Path) -> Option<(&Path, &str)> {
+    let (archive, entry) = path.to_str()?.split_once(SEPARATOR)?;
+    let archive = Path::new(archive);
+    is_archive(archive).then_some((archive, entry))
+}
+
+fn entry_path(archive: &Path, entry: &str) -> PathBuf {
+    PathBuf::from(format!("{}{SEPARATOR}{entry}", archive.display()))
+}
+
+/// The file on disk holding an image, the archive for images inside one
+pub fn on_disk(path: &Path) -> &Path {
+    split(path).map_or(path, |(archive, _)| archive)
+}
+
+/// Whether an image file, or the archive of an image inside one, exists
+pub fn exists(path: &Path) -> bool {
+    on_disk(path).is_file()
+}
+
+/// The images in an archive, by their extension
+pub fn list_images(archive: &Path) -> io::Result<Vec<PathBuf>> {
+    let is_image = |name: &str| {
+        !name.ends_with('/')
+            && mime_guess::from_path(name)
+                .iter()
+                .any(|mime| mime.type_() == "image")
+    };
+    let names = match kind(archive) {
+        Some(Kind::Zip) => {
+            let zip = zip::ZipArchive::new(BufReader::new(File::open(archive)?))
+                .map_err(io::Error::other)?;
+            zip.file_names().map(str::to_string).collect::<Vec<_>>()
+        }
+        Some(kind) => {
+            let mut names = vec![];
+            for entry in tar(archive, kind)?.entries()? {
+                let entry = entry?;
+                if entry.header().entry_type().is_file() {
+                    names.push(entry.path()?.to_string_lossy().to_string());
+                }
+            }
+            names
+        }
+        None => return Err(io::Error::other("not an archive")),
+    };
+    Ok(names
+        .into_iter()
+        .filter(|name| is_image(name))
+        .map(|name| entry_path(archive, &name))
+        .collect())
+}
+
+/// Read an image inside an archive
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    let (archive, name) = split(path).ok_or_else(|| io::Error::other("not in an archive"))?;
+    let mut content = vec![];
+    match kind(archive) {
+        Some(Kind::Zip) => {
+            let mut zip = zip::ZipArchive::new(BufReader::new(File::open(archive)?))
+                .map_err(io::Error::other)?;
+            zip.by_name(name)
+                .map_err(io::Error::other)?
+                .read_to_end(&mut content)?;
+        }
+        Some(kind) => {
+            // tar has no index, read up to the entry
+            let mut tar = tar(archive, kind)?;
+            let mut entry = tar
+                .entries()?
+                .filter_map(Result::ok)
+                .find(|entry| entry.path().is_ok_and(|path| path == Path::new(name)))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such entry"))?;
+            entry.read_to_end(&mut content)?;
+        }
+        None => unreachable!("split only gives archives"),
+    }
+    Ok(content)
+}
+
+fn tar(archive: &Path, kind: Kind) -> io::Result<tar::Archive<Box<dyn Read>>> {
+    let file = BufReader::new(File::open(archive)?);
+    let reader: Box<dyn Read> = match kind {
+        Kind::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+        Kind::TarZst => Box::new(zstd::Decoder::with_buffer(file)?),
+        _ => Box::new(file),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_read_archives() {
+        let dir = std::env::temp_dir().join(format!("wayper-archive-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let zip_path = dir.join("pack.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        for (name, content) in [("a.png", "png"), ("dir/b.jpg", "jpg"), ("notes.txt", "txt")] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let tar_path = dir.join("pack.tar.zst");
+        let encoder = zstd::Encoder::new(File::create(&tar_path).unwrap(), 0).unwrap();
+        let mut tar = tar::Builder::new(encoder.auto_finish());
+        for (name, content) in [("c.webp", "webp"), ("readme.md", "md")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            tar.append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+        tar.into_inner().unwrap();
+
+        let images = list_images(&zip_path).unwrap();
+        assert_eq!(
+            images,
+            vec![
+                entry_path(&zip_path, "a.png"),
+                entry_path(&zip_path, "dir/b.jpg")
+            ]
+        );
+        assert_eq!(split(&images[1]), Some((zip_path.as_path(), "dir/b.jpg")));
+        assert_eq!(on_disk(&images[1]), zip_path);
+        assert!(exists(&images[1]));
+        assert_eq!(read(&images[1]).unwrap(), b"jpg");
+
+        let images = list_images(&tar_path).unwrap();
+        assert_eq!(images, vec![entry_path(&tar_path, "c.webp")]);
+        assert_eq!(read(&images[0]).unwrap(), b"webp");
+        assert!(read(&entry_path(&tar_path, "missing.png")).is_err());
+
+        // a `!/` outside of an archive is just part of the path
+        assert_eq!(split(Path::new("/walls/wow!/a.png")), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,375 @@
+//! Backgrounds made from the image they are drawn around: the average color of its edges, or a
+//! copy of it scaled to cover the output, blurred and dimmed
+
+use std::num::NonZeroUsize;
+
+use image::RgbaImage;
+use lru::LruCache;
+use wayper_lib::palette::Rgb;
+use wgpu::util::DeviceExt;
+
+/// Times the output size is halved before blurring
+const DOWNSAMPLES: u32 = 4;
+/// Horizontal and vertical gaussian passes over the downsampled copy
+const BLUR_PASSES: usize = 3;
+/// Brightness of the blurred copy, so the image in front of it stands out
+const BLUR_DIM: f32 = 0.6;
+/// Blurred copies kept, one for each image and output size
+const BLUR_CACHE_SIZE: NonZeroUsize = NonZeroUsize::new(8).expect("non-zero");
+const BLUR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Average color of the outermost pixels of an image, linear RGBA
+pub fn edge_color(image: &RgbaImage) -> [f32; 4] {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+    let rows = (0..width).flat_map(|x| [(x, 0), (x, height - 1)]);
+    let columns = (1..height.saturating_sub(1)).flat_map(|y| [(0, y), (width - 1, y)]);
+
+    let mut sum = [0u64; 3];
+    let mut count = 0;
+    for (x, y) in rows.chain(columns) {
+        let pixel = image.get_pixel(x, y);
+        for (sum, channel) in sum.iter_mut().zip(pixel.0) {
+            *sum += channel as u64;
+        }
+        count += 1;
+    }
+    let [r, g, b] = sum.map(|sum| (sum / count) as u8);
+    let [r, g, b] = Rgb::new(r, g, b).to_linear();
+    [r, g, b, 1.0]
+}
+
+/// Parameters of a blur pass, laid out like `BlurParams` in the shader
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct BlurParams {
+    direction: [f32; 2],
+    output_size: [f32; 2],
+    dim: f32,
+    cover: u32,
+    rotation: u32,
+    _pad: f32,
+}
+
+impl BlurParams {
+    /// Scale the source, turned clockwise by `rotation` quarter turns, to cover an output
+    fn cover(output_size: (u32, u32), rotation: u32) -> Self {
+        Self {
+            direction: [0.0; 2],
+            output_size: [output_size.0 as f32, output_size.1 as f32],
+            dim: 1.0,
+            cover: 1,
+            rotation,
+            _pad: 0.0,
+        }
+    }
+
+    /// Blur along `direction`, one texel long
+    fn blur(direction: [f32; 2], dim: f32) -> Self {
+        Self {
+            direction,
+            output_size: [1.0; 2],
+            dim,
+            cover: 0,
+            rotation: 0,
+            _pad: 0.0,
+        }
+    }
+}
+
+struct BlurPipeline {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl BlurPipeline {
+    fn new(device: &wgpu::Device) -> Self {
+        let shader_str = format!(
+            "{}\n{}",
+            include_str!("../shaders/sizing.wgsl"),
+            include_str!("../shaders/background_blur.wgsl")
+        );
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Background Blur Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_str.into()),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Background Blur Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Background Blur Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Background Blur Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: BLUR_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        tracing::info!("background blur pipeline initialized successfully");
+        Self {
+            pipeline,
+            bind_group_layout,
+        }
+    }
+
+    /// Draw `source` into a new texture of `size`
+    fn pass(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        sampler: &wgpu::Sampler,
+        source: &wgpu::Texture,
+        size: (u32, u32),
+        params: BlurParams,
+    ) -> wgpu::Texture {
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("background blur texture"),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: BLUR_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("background blur params"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let source_view = source.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("background blur bg"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&source_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+        });
+
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("background blur pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        drop(render_pass);
+        target
+    }
+}
+
+/// Blurred copies of images, made on the GPU: the image is scaled to cover half the output,
+/// halved a few more times and blurred with a separable gaussian
+pub struct BackgroundBlur {
+    pipeline: Option<BlurPipeline>,
+    /// By texture cache key, output size and rotation
+    textures: LruCache<(String, (u32, u32), u32), wgpu::Texture>,
+}
+
+impl Default for BackgroundBlur {
+    fn default() -> Self {
+        Self {
+            pipeline: None,
+            textures: LruCache::new(BLUR_CACHE_SIZE),
+        }
+    }
+}
+
+impl BackgroundBlur {
+    /// The blurred copy of the image cached as `cache_key` for an output of `output_size`, turned
+    /// clockwise by `rotation` quarter turns, made if it isn't cached
+    #[allow(clippy::too_many_arguments)]
+    pub fn blurred(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sampler: &wgpu::Sampler,
+        cache_key: &str,
+        source: &wgpu::Texture,
+        output_size: (u32, u32),
+        rotation: u32,
+    ) -> &wgpu::Texture {
+        let key = (cache_key.to_string(), output_size, rotation);
+        if !self.textures.contains(&key) {
+            let start = std::time::Instant::now();
+            let texture = self.blur(device, queue, sampler, source, output_size, rotation);
+            tracing::debug!(
+                time_ms = start.elapsed().as_millis(),
+                cache_key,
+                "Background blurred"
+            );
+            self.textures.put(key.clone(), texture);
+        }
+        self.textures
+            .get(&key)
+            .expect("blurred texture was just cached")
+    }
+
+    fn blur(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sampler: &wgpu::Sampler,
+        source: &wgpu::Texture,
+        output_size: (u32, u32),
+        rotation: u32,
+    ) -> wgpu::Texture {
+        let pipeline = self
+            .pipeline
+            .get_or_insert_with(|| BlurPipeline::new(device));
+        let size = |halvings: u32| {
+            (
+                (output_size.0 >> halvings).max(1),
+                (output_size.1 >> halvings).max(1),
+            )
+        };
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("background blur encoder"),
+        });
+        let mut current = pipeline.pass(
+            device,
+            &mut encoder,
+            sampler,
+            source,
+            size(1),
+            BlurParams::cover(output_size, rotation),
+        );
+        // sampling between four texels halves the copy
+        for halvings in 2..=DOWNSAMPLES {
+            current = pipeline.pass(
+                device,
+                &mut encoder,
+                sampler,
+                &current,
+                size(halvings),
+                BlurParams::blur([0.0; 2], 1.0),
+            );
+        }
+        let (width, height) = size(DOWNSAMPLES);
+        for pass in 0..BLUR_PASSES {
+            let horizontal = pipeline.pass(
+                device,
+                &mut encoder,
+                sampler,
+                &current,
+                (width, height),
+                BlurParams::blur([1.0 / width as f32, 0.0], 1.0),
+            );
+            let dim = if pass + 1 == BLUR_PASSES {
+                BLUR_DIM
+            } else {
+                1.0
+            };
+            current = pipeline.pass(
+                device,
+                &mut encoder,
+                sampler,
+                &horizontal,
+                (width, height),
+                BlurParams::blur([0.0, 1.0 / height as f32], dim),
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn test_edge_color() {
+        // a white image with a black center, only its white edges count
+        let mut image = RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255]));
+        for (x, y) in [(1, 1), (1, 2), (2, 1), (2, 2)] {
+            image.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+        }
+        assert_eq!(edge_color(&image), [1.0, 1.0, 1.0, 1.0]);
+
+        let line = RgbaImage::from_pixel(3, 1, Rgba([0, 0, 0, 255]));
+        assert_eq!(edge_color(&line), [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(edge_color(&RgbaImage::new(0, 0)), [0.0, 0.0, 0.0, 1.0]);
+    }
+}
@@ -0,0 +1,101 @@
+//! Commands given in the config, for hooks and command sources. A command line is split like a
+//! shell would, `{name}` placeholders in its arguments are filled in and the same values are
+//! exported as `WAYPER_NAME` environment variables.
+
+use std::{
+    borrow::Borrow,
+    collections::BTreeMap,
+    process::{Command, Stdio},
+};
+
+/// The arguments of a command line with its placeholders filled in
+pub fn args<K: Borrow<str> + Ord>(command_line: &str, vars: &BTreeMap<K, String>) -> Vec<String> {
+    shlex::Shlex::new(command_line)
+        .map(|arg| substitute(&arg, vars))
+        .collect()
+}
+
+/// Replace every `{name}` in the argument, in one pass so values are taken as they are.
+/// Unknown placeholders are left alone.
+pub fn substitute<K: Borrow<str> + Ord>(arg: &str, vars: &BTreeMap<K, String>) -> String {
+    let mut substituted = String::with_capacity(arg.len());
+    let mut rest = arg;
+    while let Some(start) = rest.find('{') {
+        substituted.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest
+            .find('}')
+            .and_then(|end| Some((vars.get(&rest[1..end])?, end)));
+        match value {
+            Some((value, end)) => {
+                substituted.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                substituted.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    substituted.push_str(rest);
+    substituted
+}
+
+/// The values as `WAYPER_NAME` environment variables
+pub fn env_vars<K: Borrow<str>>(
+    vars: &BTreeMap<K, String>,
+) -> impl Iterator<Item = (String, &String)> {
+    vars.iter()
+        .map(|(name, value)| (format!("WAYPER_{}", name.borrow().to_uppercase()), value))
+}
+
+/// The command running `args` with the values in its environment and no stdin, None without a
+/// program
+pub fn command<K: Borrow<str>>(args: &[String], vars: &BTreeMap<K, String>) -> Option<Command> {
+    let (program, program_args) = args.split_first()?;
+    let mut command = Command::new(program);
+    command
+        .args(program_args)
+        .envs(env_vars(vars))
+        .stdin(Stdio::null());
+    Some(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitute_placeholders() {
+        let vars = BTreeMap::from([
+            ("output", "eDP-1".to_string()),
+            ("image", "/wall/a.png".to_string()),
+            ("index", "3".to_string()),
+        ]);
+        assert_eq!(
+            args(
+                "cmd --file={image}:{output}:{index}:{unknown} '{image} b'",
+                &vars
+            ),
+            vec![
+                "cmd",
+                "--file=/wall/a.png:eDP-1:3:{unknown}",
+                "/wall/a.png b"
+            ]
+        );
+        assert!(
+            env_vars(&vars).any(|(name, value)| name == "WAYPER_IMAGE" && value == "/wall/a.png")
+        );
+
+        // values aren't expanded again
+        let vars = BTreeMap::from([
+            ("output", "eDP-1".to_string()),
+            ("profile", "{image}".to_string()),
+            ("image", "/wall/{output}/a.png".to_string()),
+        ]);
+        assert_eq!(
+            substitute("{image} {output} {profile} {{output}} {", &vars),
+            "/wall/{output}/a.png eDP-1 {image} {eDP-1} {"
+        );
+    }
+}
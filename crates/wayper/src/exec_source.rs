@@ -0,0 +1,448 @@
+//! Images picked by a command, see `source = { exec = ... }`.
+//!
+//! A command either runs for every image and prints a single line, or keeps running and prints
+//! a line whenever the output should switch, and is started again when it stops. A line is a path, or a JSON object with a `path`
+//! and the settings of a playlist entry. Commands run on their own threads and their results
+//! come back to the event loop as [`SourceEvent`]s.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{BufRead, BufReader},
+    process::{Child, Stdio},
+    sync::{Arc, Mutex, mpsc},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use smithay_client_toolkit::reexports::calloop::channel::Sender;
+use wayper_lib::{config::ExecSourceConfig, playlist::PlaylistEntry, socket::Problem};
+
+use crate::{archive, command, process_group};
+
+/// How often a command that printed its line is checked for exit
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long a streaming command that stopped waits to be started again, doubled for every
+/// restart without a line in between
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// A line from the command of an output, or why there is none
+pub struct SourceEvent {
+    pub output_name: String,
+    /// The command that sent it, results of replaced commands are dropped
+    id: u64,
+    pub result: Result<PlaylistEntry, String>,
+}
+
+/// Placeholders of the command, also exported as `WAYPER_*` environment variables
+pub type SourceVars = BTreeMap<&'static str, String>;
+
+pub struct ExecSources {
+    tx: Sender<SourceEvent>,
+    next_id: u64,
+    /// The latest command per output
+    current: HashMap<String, u64>,
+    /// Commands running for a single image
+    running: HashMap<String, JoinHandle<()>>,
+    /// Commands streaming images
+    streams: HashMap<String, Arc<Mutex<Stream>>>,
+    /// Outputs that advance as soon as their image arrives
+    waiting: HashSet<String>,
+    /// The last error per output, until its command succeeds
+    errors: BTreeMap<String, String>,
+}
+
+impl ExecSources {
+    pub fn new(tx: Sender<SourceEvent>) -> Self {
+        Self {
+            tx,
+            next_id: 0,
+            current: HashMap::new(),
+            running: HashMap::new(),
+            streams: HashMap::new(),
+            waiting: HashSet::new(),
+            errors: BTreeMap::new(),
+        }
+    }
+
+    /// Run the command for the next image of an output, unless it is running already. A
+    /// `waiting` output advances as soon as the image arrives, otherwise it is kept for the next
+    /// advance.
+    pub fn request(
+        &mut self,
+        output_name: &str,
+        config: &ExecSourceConfig,
+        vars: SourceVars,
+        waiting: bool,
+    ) {
+        if waiting {
+            self.waiting.insert(output_name.to_string());
+        }
+        if self
+            .running
+            .get(output_name)
+            .is_some_and(|handle| !handle.is_finished())
+        {
+            return;
+        }
+
+        let id = self.start(output_name);
+        let tx = self.tx.clone();
+        let name = output_name.to_string();
+        let config = config.clone();
+        let handle = std::thread::spawn(move || {
+            let result = run_once(&config, &vars);
+            let _ = tx.send(SourceEvent {
+                output_name: name,
+                id,
+                result,
+            });
+        });
+        self.running.insert(output_name.to_string(), handle);
+    }
+
+    /// Start the streaming command of an output. A command that stops is started again after a
+    /// delay, until the stream is stopped.
+    pub fn start_stream(&mut self, output_name: &str, config: &ExecSourceConfig, vars: SourceVars) {
+        self.stop(output_name);
+        let id = self.start(output_name);
+        tracing::info!("streaming images for {output_name} from {}", config.exec);
+
+        let stream = Arc::new(Mutex::new(Stream::default()));
+        self.streams
+            .insert(output_name.to_string(), Arc::clone(&stream));
+        let tx = self.tx.clone();
+        let output_name = output_name.to_string();
+        let exec = config.exec.clone();
+        std::thread::spawn(move || {
+            let send = |result| {
+                tx.send(SourceEvent {
+                    output_name: output_name.clone(),
+                    id,
+                    result,
+                })
+                .map_err(drop)
+            };
+            let mut delay = RESTART_DELAY;
+            while !stream.lock().unwrap().stopped {
+                let running = match spawn(&exec, &vars) {
+                    Ok(child) => stream_lines(&stream, child, &send, &mut delay),
+                    Err(error) => send(Err(format!("{error}, retrying in {}s", delay.as_secs()))),
+                };
+                if running.is_err() {
+                    return;
+                }
+                std::thread::sleep(delay);
+                delay = (delay * 2).min(MAX_RESTART_DELAY);
+            }
+        });
+    }
+
+    /// Stop the commands of an output, their results are dropped
+    pub fn stop(&mut self, output_name: &str) {
+        self.current.remove(output_name);
+        self.running.remove(output_name);
+        self.waiting.remove(output_name);
+        self.errors.remove(output_name);
+        if let Some(stream) = self.streams.remove(output_name)
+            && let Some(mut child) = stream.lock().unwrap().stop()
+            && let Err(e) = kill(&mut child)
+        {
+            tracing::error!("unable to stop the source of {output_name}: {e}");
+        }
+    }
+
+    /// Take in a result. Returns None if it came from a replaced command, else whether the
+    /// output should advance right away.
+    pub fn finished(&mut self, event: &SourceEvent) -> Option<bool> {
+        if self.current.get(&event.output_name) != Some(&event.id) {
+            return None;
+        }
+        match &event.result {
+            Ok(_) => self.errors.remove(&event.output_name),
+            Err(error) => self.errors.insert(event.output_name.clone(), error.clone()),
+        };
+        let streaming = self.streams.contains_key(&event.output_name);
+        Some(self.waiting.remove(&event.output_name) || streaming)
+    }
+
+    pub fn problems(&self) -> Vec<Problem> {
+        self.errors
+            .iter()
+            .map(|(output_name, error)| Problem::SourceFailed {
+                output_name: output_name.clone(),
+                error: error.clone(),
+            })
+            .collect()
+    }
+
+    /// A new command for an output, replacing the previous one
+    fn start(&mut self, output_name: &str) -> u64 {
+        self.next_id += 1;
+        self.current.insert(output_name.to_string(), self.next_id);
+        self.next_id
+    }
+}
+
+/// The running command of a stream, shared with the thread reading it
+#[derive(Default)]
+struct Stream {
+    /// Kept after it exits, until it is started again
+    child: Option<Child>,
+    stopped: bool,
+}
+
+impl Stream {
+    /// Keep the stream from being started again, returns its command
+    fn stop(&mut self) -> Option<Child> {
+        self.stopped = true;
+        self.child.take()
+    }
+}
+
+/// Read the lines of a streaming command until it exits. Err once the stream is stopped or
+/// its results aren't wanted anymore.
+fn stream_lines(
+    stream: &Mutex<Stream>,
+    mut child: Child,
+    send: &impl Fn(Result<PlaylistEntry, String>) -> Result<(), ()>,
+    delay: &mut Duration,
+) -> Result<(), ()> {
+    let stdout = child.stdout.take().expect("stdout is piped");
+    {
+        let mut stream = stream.lock().unwrap();
+        if stream.stopped {
+            let _ = kill(&mut child);
+            return Err(());
+        }
+        stream.child = Some(child);
+    }
+    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+        if !line.trim().is_empty() {
+            send(parse_line(&line))?;
+            *delay = RESTART_DELAY;
+        }
+    }
+    // polled, so stopping the stream never waits on a command that closed its output but keeps
+    // running
+    let status = loop {
+        let mut stream = stream.lock().unwrap();
+        let Some(child) = stream.child.as_mut() else {
+            return Err(());
+        };
+        match child.try_wait() {
+            Ok(Some(status)) => break Ok(status),
+            Ok(None) => {}
+            Err(e) => break Err(e),
+        }
+        drop(stream);
+        std::thread::sleep(POLL_INTERVAL);
+    };
+    send(Err(match status {
+        Ok(status) => format!(
+            "the command stopped, {status}, restarting in {}s",
+            delay.as_secs()
+        ),
+        Err(e) => format!(
+            "the command stopped: {e}, restarting in {}s",
+            delay.as_secs()
+        ),
+    }))
+}
+
+fn spawn(command_line: &str, vars: &SourceVars) -> Result<Child, String> {
+    let args = command::args(command_line, vars);
+    let Some(mut command) = command::command(&args, vars) else {
+        return Err("empty command".to_string());
+    };
+    process_group::spawn(command.stdout(Stdio::piped()))
+        .map_err(|e| format!("unable to run {}: {e}", args[0]))
+}
+
+/// Kill the command and everything it started, unless it has exited already
+fn kill(child: &mut Child) -> std::io::Result<()> {
+    if matches!(child.try_wait(), Ok(None)) {
+        process_group::kill(child.id())?;
+    }
+    child.wait().map(|_| ())
+}
+
+/// Run the command and read its first line, within the timeout
+fn run_once(config: &ExecSourceConfig, vars: &SourceVars) -> Result<PlaylistEntry, String> {
+    let start = Instant::now();
+    let timeout = Duration::from_secs(config.timeout);
+    let mut child = spawn(&config.exec, vars)?;
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let (line_tx, line_rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let line = BufReader::new(stdout)
+            .lines()
+            .map_while(Result::ok)
+            .find(|line| !line.trim().is_empty());
+        let _ = line_tx.send(line);
+    });
+
+    let result = match line_rx.recv_timeout(timeout) {
+        Ok(Some(line)) => parse_line(&line),
+        Ok(None) => {
+            while start.elapsed() < timeout && matches!(child.try_wait(), Ok(None)) {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            Err(match child.try_wait() {
+                Ok(Some(status)) => format!("the command printed nothing, {status}"),
+                Ok(None) => "the command printed nothing".to_string(),
+                Err(e) => format!("the command printed nothing: {e}"),
+            })
+        }
+        Err(_) => Err(format!("the command timed out after {}s", config.timeout)),
+    };
+
+    // the result doesn't wait for the command, it gets the rest of the timeout to finish
+    let exec = config.exec.clone();
+    std::thread::spawn(move || {
+        while start.elapsed() < timeout && matches!(child.try_wait(), Ok(None)) {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        if let Err(e) = kill(&mut child) {
+            tracing::error!("unable to stop {exec}: {e}");
+        }
+    });
+    result
+}
+
+/// A path, or a playlist entry as JSON
+fn parse_line(line: &str) -> Result<PlaylistEntry, String> {
+    let line = line.trim();
+    let entry = match line.starts_with('{') {
+        true => serde_json::from_str(line).map_err(|e| format!("invalid line {line}: {e}"))?,
+        false => PlaylistEntry::new(line.into()),
+    };
+    match archive::exists(&entry.path) {
+        true => Ok(entry),
+        false => Err(format!("{} is not a file", entry.path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wayper_lib::config::FitMode;
+
+    use super::*;
+
+    fn exec(command: &str, timeout: u64) -> ExecSourceConfig {
+        ExecSourceConfig {
+            exec: command.to_string(),
+            timeout,
+            stream: false,
+        }
+    }
+
+    #[test]
+    fn test_run_once() {
+        let image = std::env::temp_dir().join(format!("wayper-exec-{}.png", std::process::id()));
+        std::fs::write(&image, "").unwrap();
+        let vars = BTreeMap::from([("image", image.display().to_string())]);
+
+        let entry = run_once(&exec("echo {image}", 5), &vars).unwrap();
+        assert_eq!(entry.path, image);
+
+        let command = r#"sh -c 'echo "{\"path\": \"$WAYPER_IMAGE\", \"fit\": \"contain\", \"duration\": 5}"'"#;
+        let entry = run_once(&exec(command, 5), &vars).unwrap();
+        assert_eq!(entry.path, image);
+        assert_eq!(entry.fit, Some(FitMode::Contain));
+        assert_eq!(entry.duration, Some(5));
+
+        // the line is used right away, the command finishes on its own
+        let start = Instant::now();
+        let entry = run_once(&exec("sh -c 'echo {image}; sleep 5'", 10), &vars).unwrap();
+        assert_eq!(entry.path, image);
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        assert!(run_once(&exec("echo /does/not/exist", 5), &vars).is_err());
+        assert!(run_once(&exec("true", 5), &vars).is_err());
+        let start = Instant::now();
+        assert!(run_once(&exec("sleep 5", 1), &vars).is_err());
+        assert!(start.elapsed() < Duration::from_secs(3));
+
+        std::fs::remove_file(&image).unwrap();
+    }
+
+    #[test]
+    fn test_restart_stream() {
+        let image = std::env::temp_dir().join(format!("wayper-stream-{}.png", std::process::id()));
+        std::fs::write(&image, "").unwrap();
+        let (tx, rx) = smithay_client_toolkit::reexports::calloop::channel::channel();
+        let mut sources = ExecSources::new(tx);
+        let config = ExecSourceConfig {
+            exec: "echo {image}".to_string(),
+            timeout: 5,
+            stream: true,
+        };
+        sources.start_stream(
+            "eDP-1",
+            &config,
+            BTreeMap::from([("image", image.display().to_string())]),
+        );
+
+        let mut results = (0..3).map(|_| rx.recv().unwrap().result);
+        assert_eq!(results.next().unwrap().unwrap().path, image);
+        assert!(
+            results
+                .next()
+                .unwrap()
+                .unwrap_err()
+                .contains("restarting in 1s")
+        );
+        assert_eq!(results.next().unwrap().unwrap().path, image);
+        sources.stop("eDP-1");
+
+        std::fs::remove_file(&image).unwrap();
+    }
+
+    #[test]
+    fn test_stop_stream_that_closed_its_output() {
+        let (tx, _rx) = smithay_client_toolkit::reexports::calloop::channel::channel();
+        let mut sources = ExecSources::new(tx);
+        let pid_file = std::env::temp_dir().join(format!("wayper-exec-{}.pid", std::process::id()));
+        let config = ExecSourceConfig {
+            exec: format!(
+                "sh -c 'exec >&-; sleep 30 & echo $! > {}; wait'",
+                pid_file.display()
+            ),
+            timeout: 5,
+            stream: true,
+        };
+        sources.start_stream("eDP-1", &config, SourceVars::new());
+
+        let start = Instant::now();
+        let pid = loop {
+            match std::fs::read_to_string(&pid_file) {
+                Ok(pid) if pid.ends_with('\n') => break pid,
+                _ => {}
+            }
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "the stream never started"
+            );
+            std::thread::sleep(POLL_INTERVAL);
+        };
+        std::fs::remove_file(&pid_file).unwrap();
+        // the reader has seen the end of the output by now
+        std::thread::sleep(POLL_INTERVAL * 4);
+
+        let stopping = Instant::now();
+        sources.stop("eDP-1");
+        assert!(stopping.elapsed() < Duration::from_secs(1));
+        // gone, or killed and waiting to be reaped by whoever inherited it
+        let dead = || {
+            std::fs::read_to_string(format!("/proc/{}/stat", pid.trim()))
+                .map_or(true, |stat| stat.contains(") Z "))
+        };
+        let start = Instant::now();
+        while !dead() && start.elapsed() < Duration::from_secs(1) {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        assert!(dead(), "sleep {} still running", pid.trim());
+    }
+}
@@ -0,0 +1,126 @@
+//! Colors and gradients drawn in place of an image, or behind one as its background
+
+use wayper_lib::fill::{Fill, GradientKind, MAX_STOPS};
+
+use crate::{scene::Rect, wgpu_renderer::scene_node_pipeline};
+
+/// Parameters of a fill node, laid out like `FillNodeParams` in the shader
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FillNodeParams {
+    rect: [f32; 4],
+    output_size: [f32; 2],
+    opacity: f32,
+    kind: u32,
+    center: [f32; 2],
+    angle: f32,
+    stop_count: u32,
+    positions: [[f32; 4]; MAX_STOPS / 4],
+    colors: [[f32; 4]; MAX_STOPS],
+}
+
+impl FillNodeParams {
+    pub fn new(fill: &Fill, rect: Rect, output_size: (u32, u32), opacity: f32) -> Self {
+        let mut params = Self {
+            rect: rect.as_array(),
+            output_size: [output_size.0 as f32, output_size.1 as f32],
+            opacity,
+            kind: 0,
+            center: [0.5, 0.5],
+            angle: 0.0,
+            stop_count: 1,
+            positions: [[0.0; 4]; MAX_STOPS / 4],
+            colors: [[0.0; 4]; MAX_STOPS],
+        };
+        let srgb =
+            |color: wayper_lib::palette::Rgb| [color.r, color.g, color.b].map(|c| c as f32 / 255.0);
+        let gradient = match fill {
+            Fill::Color(color) => {
+                let [r, g, b] = srgb(*color);
+                params.colors[0] = [r, g, b, 1.0];
+                return params;
+            }
+            Fill::Gradient(gradient) => gradient,
+        };
+        (params.kind, params.center, params.angle) = match gradient.kind {
+            GradientKind::Linear { angle } => (1, [0.5, 0.5], angle),
+            GradientKind::Radial { center } => (2, center, 0.0),
+            GradientKind::Conic { center, angle } => (3, center, angle),
+        };
+        params.angle = params.angle.to_radians();
+        params.stop_count = gradient.stops.len().min(MAX_STOPS) as u32;
+        for (i, stop) in gradient.stops.iter().take(MAX_STOPS).enumerate() {
+            params.positions[i / 4][i % 4] = stop.position;
+            let [r, g, b] = srgb(stop.color);
+            params.colors[i] = [r, g, b, 1.0];
+        }
+        params
+    }
+}
+
+/// The pipeline drawing fill nodes
+#[derive(Default)]
+pub struct FillPipeline {
+    pipeline: Option<wgpu::RenderPipeline>,
+    bind_group_layout: Option<wgpu::BindGroupLayout>,
+}
+
+impl FillPipeline {
+    fn init_pipeline(&mut self, device: &wgpu::Device, surface_format: wgpu::TextureFormat) {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Scene Fill Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/fill_scene.wgsl").into()),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Scene Fill Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        self.pipeline = Some(scene_node_pipeline(
+            device,
+            "Scene Fill",
+            &shader,
+            &bind_group_layout,
+            surface_format,
+        ));
+        self.bind_group_layout = Some(bind_group_layout);
+        tracing::info!("scene fill node pipeline initialized successfully");
+    }
+
+    /// Bind group drawing a fill with the parameters in `params`
+    pub fn create_bind_group(
+        &mut self,
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        params: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        if self.pipeline.is_none() {
+            self.init_pipeline(device, surface_format);
+        }
+        let layout = self
+            .bind_group_layout
+            .as_ref()
+            .expect("fill pipeline was just initialized");
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("fill node bg"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params.as_entire_binding(),
+            }],
+        })
+    }
+
+    pub fn pipeline(&self) -> Option<&wgpu::RenderPipeline> {
+        self.pipeline.as_ref()
+    }
+}
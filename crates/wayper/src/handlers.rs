@@ -1,5 +1,13 @@
 use std::sync::mpsc::Sender;
-use std::{collections::HashMap, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
 
 use smithay_client_toolkit::reexports::client;
 use smithay_client_toolkit::{
@@ -17,14 +25,32 @@ use smithay_client_toolkit::{
     },
     shm::Shm,
 };
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
-use wayper_lib::config::Config;
+use wayper_lib::{
+    config::{Config, HookConfig, HookEvent, OutputConfig},
+    fill::Fill,
+    palette::Palette,
+    socket::{Problem, ShowTrigger, SocketError},
+};
 
-use crate::wgpu_renderer::{RenderCommand, create_surface_from_handles};
+use crate::wgpu_renderer::{RenderCommand, RenderEvent, create_surface_from_handles};
 use crate::{
+    animation::AnimationClock,
+    archive,
+    exec_source::{ExecSources, SourceEvent, SourceVars},
+    history::History,
+    hooks::{HookContext, HookRunner},
+    http_source::{HttpEvent, HttpSources},
+    library::{FAVORITES_SOURCE, Library},
     map::{OutputKey, OutputMap},
+    notifications::{NotificationAction, Notifier},
     output::OutputRepr,
+    quarantine::Quarantine,
+    rotation::{self, Rotation},
+    state::{self, StateStore},
+    templates::{TemplateRenderer, TemplateVars},
+    watcher::DirWatcher,
 };
 
 mod compositor;
@@ -38,6 +64,64 @@ pub type OutputId = u32;
 /// The key should be the output id from WlOutput
 pub type DrawTokens = HashMap<OutputId, RegistrationToken>;
 
+/// Number of image palettes kept around, more than the renderer keeps textures so a cached
+/// texture always has its palette
+const PALETTE_CACHE_SIZE: NonZeroUsize = NonZeroUsize::new(64).expect("non-zero");
+/// Wallpaper changes waiting on palettes. Past this, the oldest fires without one.
+const MAX_PENDING_CHANGES: usize = 16;
+
+/// What to do with the image an output is showing
+#[derive(Debug, Clone, Copy)]
+pub enum Mark {
+    /// Add it to the favorites
+    Favorite,
+    /// Never show it again
+    Ban,
+    /// Move it to the trash
+    Trash,
+}
+
+/// A change to the images coming up on an output
+#[derive(Debug, Clone)]
+pub enum QueueEdit {
+    /// Show these images next, after the ones already queued
+    Enqueue(Vec<PathBuf>),
+    /// Remove an image from the queue, or everything
+    Dequeue(Option<PathBuf>),
+    /// Shuffle the rest of the rotation
+    Shuffle,
+}
+
+/// A wallpaper change waiting for the palette of its image
+pub struct PendingChange {
+    image: PathBuf,
+    output_name: String,
+    hook: Option<HookConfig>,
+    context: HookContext,
+}
+
+/// Palettes of decoded images, and wallpaper changes waiting for them
+pub struct Palettes {
+    cache: LruCache<PathBuf, Option<Palette>>,
+    pending: Vec<PendingChange>,
+}
+
+impl Default for Palettes {
+    fn default() -> Self {
+        Self {
+            cache: LruCache::new(PALETTE_CACHE_SIZE),
+            pending: vec![],
+        }
+    }
+}
+
+impl Palettes {
+    /// The palette of an image. The outer option is None if the image hasn't been decoded yet.
+    pub fn get(&self, image: &Path) -> Option<Option<&Palette>> {
+        self.cache.peek(image).map(Option::as_ref)
+    }
+}
+
 pub struct Wayper {
     pub compositor_state: CompositorState,
     pub registry_state: RegistryState,
@@ -51,6 +135,21 @@ pub struct Wayper {
     pub outputs: OutputMap,
     pub config: Config,
     pub socket_counter: u64,
+    pub notifier: Notifier,
+    pub library: Library,
+    pub hooks: HookRunner,
+    pub palettes: Palettes,
+    /// Frame delays of the animated images decoded so far
+    pub animations: HashMap<PathBuf, Arc<[Duration]>>,
+    /// Shaders whose latest version failed to compile, with the error
+    pub shader_errors: HashMap<PathBuf, String>,
+    pub history: History,
+    pub quarantine: Quarantine,
+    pub templates: TemplateRenderer,
+    pub state: StateStore,
+    pub watcher: Option<DirWatcher>,
+    pub sources: ExecSources,
+    pub http_sources: HttpSources,
 
     pub renderer_tx: Sender<RenderCommand>,
     pub wgpu_instance: wgpu::Instance,
@@ -121,11 +220,32 @@ impl Wayper {
                 }
             };
 
-            let img_list = utils::get_img_list(output_config.as_ref());
+            let img_list = utils::get_img_list(output_config.as_ref(), &self.library);
+            if let Some(output_config) = output_config.as_ref()
+                && output_config.source.is_none()
+                && img_list.is_empty()
+            {
+                self.notifier.error(
+                    &format!("No wallpapers for {name}"),
+                    &format!("{} contains no images", output_config.path.display()),
+                );
+            }
+
+            let order = output_config
+                .as_ref()
+                .map(|output_config| output_config.image_order())
+                .unwrap_or_default();
+            let rotation = self
+                .state
+                .restore(&self.current_profile, &name, img_list, order);
+            self.state
+                .update(&self.current_profile, &name, rotation.clone());
 
             // transition config is set when wallpaper starts switching
             let transition = None;
 
+            let playlist = utils::get_playlist(output_config.as_ref()).by_image();
+            let hook_config = output_config.clone();
             let now = Instant::now();
             outputs_map.insert(
                 name.clone(),
@@ -143,16 +263,32 @@ impl Wayper {
                     buffer: None,
                     first_configure: true,
                     ping_draw: None,
-                    img_list,
-                    index: 0,
+                    rotation: Rotation::restored(rotation),
+                    playlist,
+                    sidecars: HashMap::new(),
+                    next_trigger: None,
                     visible: true,
                     should_next: false,
                     last_render_instant: now,
                     transition,
+                    animation: None,
+                    previous_animation: None,
+                    motion: None,
+                    previous_motion: None,
+                    fallback: false,
+                    shader_clock: None,
+                    palette: None,
                     created_at: now,
                     frame_count: 0,
                 },
             );
+
+            let context = HookContext::new(HookEvent::OutputAdded)
+                .output(&name)
+                .profile(&self.current_profile);
+            self.fire_hook(HookEvent::OutputAdded, hook_config.as_ref(), context);
+            self.update_watches();
+            self.start_source(&name);
         } else {
             warn!("we had this output {name} earlier, skipping....");
         }
@@ -184,21 +320,40 @@ impl Wayper {
         }
 
         info!("Changing current profile to: \"{profile}\"");
+        let prev_profile = std::mem::take(&mut self.current_profile);
 
         // set the profile
         self.current_profile = profile.to_string();
 
         // refresh the img list
+        let mut output_names = vec![];
         for output in self.outputs.iter() {
             let output_name = output.lock().unwrap().output_name.clone();
+            output_names.push(output_name.clone());
 
             let output_config = self
                 .config
                 .get_output_config(profile.as_str(), &*output_name)?;
 
             let mut output = output.lock().unwrap();
-            output.img_list = utils::get_img_list(Some(&output_config));
-            output.index = 0;
+            let rotation = self.state.restore(
+                &profile,
+                &output_name,
+                utils::get_img_list(Some(&output_config), &self.library),
+                output_config.image_order(),
+            );
+            output.rotation.restore(rotation);
+            output.playlist = utils::get_playlist(Some(&output_config)).by_image();
+            output.rotation.source_next = None;
+            if output_config.source.is_none() && output.rotation.img_list.is_empty() {
+                self.notifier.error(
+                    &format!("No wallpapers for {output_name}"),
+                    &format!(
+                        "{} in profile \"{profile}\" contains no images",
+                        output_config.path.display()
+                    ),
+                );
+            }
             output.output_config = Some(output_config);
             if let Some(ping_draw) = output.ping_draw.as_ref() {
                 ping_draw.ping();
@@ -207,8 +362,808 @@ impl Wayper {
             }
         }
 
+        // commands of the previous profile are replaced
+        for output_name in output_names {
+            self.start_source(&output_name);
+        }
+
+        self.state.set_active_profile(&profile);
+        self.notifier.profile_changed(&profile);
+        let context = HookContext::new(HookEvent::ProfileChange)
+            .profile(&profile)
+            .var("prev_profile", prev_profile);
+        self.fire_hook(HookEvent::ProfileChange, None, context);
+
+        self.update_watches();
+
         Ok(profile)
     }
+
+    /// Rebuild the image lists of the outputs matching `filter`, keeping their place in the
+    /// rotation. Returns a summary line per output.
+    pub fn rescan(&mut self, filter: impl Fn(&OutputRepr) -> bool) -> Vec<String> {
+        let mut summary = vec![];
+        for output in self.outputs.iter() {
+            let mut output = output.lock().unwrap();
+            if !filter(&output) {
+                continue;
+            }
+            let Some(output_config) = output.output_config.clone() else {
+                continue;
+            };
+
+            let images = utils::get_img_list(Some(&output_config), &self.library);
+            output.playlist = utils::get_playlist(Some(&output_config)).by_image();
+            let (added, removed) = state::image_changes(&output.rotation.img_list, &images);
+
+            let rotation = output
+                .rotation
+                .state()
+                .reconcile(images, output_config.image_order());
+            let previous_next = output
+                .rotation
+                .img_list
+                .get(output.rotation.index + 1)
+                .cloned();
+            let current_gone = output.rotation.override_img.is_none()
+                && output
+                    .current_img()
+                    .is_some_and(|image| !rotation.order.contains(&image));
+            // also true when an image is listed more or fewer times
+            let changed = output.rotation.img_list != rotation.order;
+            output.rotation.img_list = rotation.order;
+            output.rotation.index = rotation.index;
+            if current_gone && !output.first_configure && !output.rotation.img_list.is_empty() {
+                // the index is on the image after the removed one, which the next advance
+                // should show rather than skip
+                output.rotation.index = output
+                    .rotation
+                    .index
+                    .checked_sub(1)
+                    .unwrap_or(output.rotation.img_list.len() - 1);
+            }
+            let next_changed =
+                previous_next.as_ref() != output.rotation.img_list.get(output.rotation.index + 1);
+            summary.push(format!(
+                "{}: {} images (+{added} -{removed})",
+                output.output_name,
+                output.rotation.img_list.len()
+            ));
+            if !changed {
+                continue;
+            }
+            info!("{}", summary.last().unwrap());
+            self.state.update(
+                &self.current_profile,
+                &output.output_name,
+                output.rotation.state(),
+            );
+
+            // there is something to show again
+            if output.fallback
+                && added > 0
+                && let Some(ping_draw) = output.ping_draw.as_ref()
+            {
+                ping_draw.ping();
+            }
+
+            if next_changed {
+                self.preload_next(&output);
+            }
+        }
+        self.update_watches();
+        summary
+    }
+
+    /// Keep the next image of an output preloaded after it changed, so it shows without a
+    /// decode stall
+    fn preload_next(&self, output: &OutputRepr) {
+        if output.first_configure {
+            return;
+        }
+        if let Some(next_image) = output.peek_next_img(&self.quarantine) {
+            let request = RenderCommand::RequestTextureLoad {
+                image_path: next_image,
+                output_name: output.output_name.clone(),
+            };
+            if let Err(e) = self.renderer_tx.send(request) {
+                error!("Failed to request pre-load: {e}");
+            }
+        }
+    }
+
+    /// Change what comes up next on the named output, or on all outputs. Returns a summary line
+    /// per output.
+    pub fn edit_queue(
+        &mut self,
+        output_name: Option<String>,
+        edit: QueueEdit,
+    ) -> Result<Vec<String>, SocketError> {
+        if let Some(output_name) = output_name.as_ref()
+            && !self
+                .outputs
+                .contains_key(OutputKey::OutputName(output_name.clone()))
+        {
+            return Err(SocketError::UnindentifiedOutput {
+                output_name: output_name.clone(),
+            });
+        }
+        if let QueueEdit::Enqueue(images) = &edit
+            && let Some(missing) = images.iter().find(|image| !rotation::can_show(image))
+        {
+            return Err(SocketError::ImageNotFound {
+                path: missing.clone(),
+            });
+        }
+
+        let mut summary = vec![];
+        for output in self.outputs.iter() {
+            let mut output = output.lock().unwrap();
+            if output_name
+                .as_ref()
+                .is_some_and(|name| *name != output.output_name)
+            {
+                continue;
+            }
+            let next_before = output.peek_next_img(&self.quarantine);
+            let line = match &edit {
+                QueueEdit::Enqueue(images) => {
+                    output.rotation.queue.extend(images.iter().cloned());
+                    format!("{} images queued", output.rotation.queue.len())
+                }
+                QueueEdit::Dequeue(Some(image)) => {
+                    let before = output.rotation.queue.len();
+                    output.rotation.queue.retain(|queued| queued != image);
+                    if output.rotation.queue.len() == before {
+                        format!("{} is not queued", image.display())
+                    } else {
+                        format!("removed {} from the queue", image.display())
+                    }
+                }
+                QueueEdit::Dequeue(None) => {
+                    let cleared = output.rotation.queue.len();
+                    output.rotation.queue.clear();
+                    format!("cleared {cleared} images from the queue")
+                }
+                QueueEdit::Shuffle => {
+                    output.shuffle_remaining();
+                    "shuffled the rest of the rotation".to_string()
+                }
+            };
+            summary.push(format!("{}: {line}", output.output_name));
+            self.state.update(
+                &self.current_profile,
+                &output.output_name,
+                output.rotation.state(),
+            );
+
+            // there is something to show again
+            if output.fallback
+                && !output.rotation.queue.is_empty()
+                && let Some(ping_draw) = output.ping_draw.as_ref()
+            {
+                ping_draw.ping();
+            } else if output.peek_next_img(&self.quarantine) != next_before {
+                self.preload_next(&output);
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Rescan the outputs whose directories contain any of the `changed` paths, and redraw
+    /// outputs showing a changed file
+    pub fn rescan_changed(&mut self, changed: &HashSet<PathBuf>) {
+        debug!("{} paths changed on disk", changed.len());
+        self.rescan(|output| {
+            output.output_config.as_ref().is_some_and(|output_config| {
+                changed.iter().any(|path| {
+                    path.starts_with(&output_config.path) || output_config.path.starts_with(path)
+                })
+            })
+        });
+
+        // images in a changed archive changed with it
+        let in_archives = self
+            .palettes
+            .cache
+            .iter()
+            .map(|(image, _)| image.as_path())
+            .chain(self.quarantine.images())
+            .filter(|image| archive::split(image).is_some_and(|(path, _)| changed.contains(path)))
+            .map(Path::to_path_buf)
+            .collect::<Vec<_>>();
+        for path in changed
+            .iter()
+            .filter(|path| path.is_file())
+            .chain(&in_archives)
+        {
+            // the palette is recomputed when the renderer decodes the new content
+            self.palettes.cache.pop(path);
+            self.animations.remove(path);
+            if self.quarantine.release(path) {
+                info!("{} changed on disk, trying it again", path.display());
+            }
+        }
+
+        for output in self.outputs.iter() {
+            let output = output.lock().unwrap();
+            if let Some(shader) = output
+                .output_config
+                .as_ref()
+                .and_then(|output_config| output_config.shader_content())
+                .filter(|shader| changed.contains(&shader.shader))
+            {
+                let reload = self.renderer_tx.send(RenderCommand::ShaderChanged {
+                    shader: shader.shader.clone(),
+                });
+                if let Err(e) = reload {
+                    error!("failed to reload {}: {e}", shader.shader.display());
+                }
+            }
+            let Some(image) = output.current_img() else {
+                continue;
+            };
+            if !changed.contains(archive::on_disk(&image)) || !archive::exists(&image) {
+                continue;
+            }
+            // the renderer looks at the file again and drops its stale texture
+            let reload = self.renderer_tx.send(RenderCommand::RequestTextureLoad {
+                image_path: image.clone(),
+                output_name: output.output_name.clone(),
+            });
+            if let Err(e) = reload {
+                error!("failed to reload {}: {e}", image.display());
+            }
+            // a running transition picks up the new content by itself
+            if output.first_configure || output.transition.is_some() {
+                continue;
+            }
+            info!(
+                "{} changed on disk, redrawing {}",
+                image.display(),
+                output.output_name
+            );
+            let render = self.renderer_tx.send(RenderCommand::RenderScene {
+                output_name: output.output_name.clone(),
+                scene: output.static_scene(image),
+            });
+            if let Err(e) = render {
+                error!("failed to redraw {}: {e}", output.output_name);
+            }
+        }
+    }
+
+    /// Watch the directories of all outputs
+    pub fn update_watches(&mut self) {
+        let Some(watcher) = self.watcher.as_mut() else {
+            return;
+        };
+        let roots = self
+            .outputs
+            .iter()
+            .flat_map(|output| {
+                let output = output.lock().unwrap();
+                let output_config = output.output_config.as_ref();
+                // shader wallpapers are compiled again when their file changes
+                output_config
+                    .map(|output_config| output_config.path.clone())
+                    .into_iter()
+                    .chain(output_config.and_then(|output_config| {
+                        Some(output_config.shader_content()?.shader.clone())
+                    }))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<HashSet<_>>();
+        watcher.watch(roots.iter().map(PathBuf::as_path));
+    }
+
+    /// Fire the hook for `event` if one is configured. With an output config, the output's hooks
+    /// take precedence over the global ones.
+    pub fn fire_hook(
+        &mut self,
+        event: HookEvent,
+        output_config: Option<&OutputConfig>,
+        context: HookContext,
+    ) {
+        let hook = match output_config {
+            Some(output_config) => output_config.get_hook(event, &self.config),
+            None => self.config.hooks.get(event).cloned(),
+        };
+        if let Some(hook) = hook {
+            self.hooks.fire(hook, context);
+        }
+    }
+
+    /// An output is now showing `image`. The wallpaper change hook and the templates get the
+    /// palette of the image, so they wait for the renderer to decode it if that hasn't happened
+    /// yet.
+    pub fn wallpaper_changed(
+        &mut self,
+        output_name: &str,
+        image: &Path,
+        output_config: Option<&OutputConfig>,
+        context: HookContext,
+    ) {
+        // fills are never decoded, their palette comes from their colors
+        if let Some(fill) = Fill::from_path(image)
+            && !self.palettes.cache.contains(image)
+        {
+            self.palettes.cache.put(image.to_path_buf(), fill.palette());
+        }
+
+        let event = HookEvent::WallpaperChange;
+        let hook = match output_config {
+            Some(output_config) => output_config.get_hook(event, &self.config),
+            None => self.config.hooks.get(event).cloned(),
+        };
+        let templates = !self.config.templates.is_empty()
+            && self
+                .config
+                .primary_output
+                .as_ref()
+                .is_none_or(|primary| primary == output_name);
+        if hook.is_none() && !templates {
+            return;
+        }
+
+        let change = PendingChange {
+            image: image.to_path_buf(),
+            output_name: output_name.to_string(),
+            hook,
+            context,
+        };
+        match self.palettes.cache.get(image) {
+            Some(palette) => {
+                let palette = palette.clone();
+                self.finish_wallpaper_change(change, palette.as_ref());
+            }
+            None => {
+                if self.palettes.pending.len() >= MAX_PENDING_CHANGES {
+                    let oldest = self.palettes.pending.remove(0);
+                    warn!(
+                        "palette for {} never arrived, firing hook without it",
+                        oldest.image.display()
+                    );
+                    self.finish_wallpaper_change(oldest, None);
+                }
+                self.palettes.pending.push(change);
+            }
+        }
+    }
+
+    /// Fire the hook and render the templates of a wallpaper change
+    fn finish_wallpaper_change(&mut self, change: PendingChange, palette: Option<&Palette>) {
+        let PendingChange {
+            image,
+            output_name,
+            hook,
+            context,
+        } = change;
+
+        if let Some(hook) = hook {
+            let context = match palette {
+                Some(palette) => context.palette(palette),
+                None => context,
+            };
+            self.hooks.fire(hook, context);
+        }
+
+        let is_primary = self
+            .config
+            .primary_output
+            .as_ref()
+            .is_none_or(|primary| *primary == output_name);
+        if let Some(palette) = palette
+            && is_primary
+        {
+            let vars = TemplateVars::new(palette)
+                .text("image", image.display())
+                .text("output", &output_name)
+                .text("profile", &self.current_profile);
+            self.templates.render(self.config.templates.clone(), vars);
+        }
+    }
+
+    /// Finish the wallpaper changes waiting on `image`
+    fn finish_pending_changes(&mut self, image: &Path, palette: Option<&Palette>) {
+        let (ready, pending) = std::mem::take(&mut self.palettes.pending)
+            .into_iter()
+            .partition::<Vec<_>, _>(|pending| pending.image == image);
+        self.palettes.pending = pending;
+        for change in ready {
+            self.finish_wallpaper_change(change, palette);
+        }
+    }
+
+    /// Placeholders for the source command of an output
+    fn source_vars(&self, output: &OutputRepr) -> SourceVars {
+        let image = output
+            .current_img()
+            .map(|image| image.display().to_string())
+            .unwrap_or_default();
+        SourceVars::from([
+            ("output", output.output_name.clone()),
+            ("profile", self.current_profile.clone()),
+            ("image", image),
+        ])
+    }
+
+    /// Start the streaming source command or the downloads of an output, if it has either
+    pub fn start_source(&mut self, output_name: &str) {
+        self.sources.stop(output_name);
+        self.http_sources.stop(output_name);
+        let Some(output) = self
+            .outputs
+            .get(OutputKey::OutputName(output_name.to_string()))
+        else {
+            return;
+        };
+        let output = output.lock().unwrap();
+        if let Some(source) = output
+            .output_config
+            .as_ref()
+            .and_then(|output_config| output_config.http_source())
+        {
+            self.http_sources.start(output_name, source);
+        }
+        if let Some(source) = output
+            .output_config
+            .as_ref()
+            .and_then(|output_config| output_config.exec_source())
+            .filter(|source| source.stream)
+        {
+            let vars = self.source_vars(&output);
+            self.sources.start_stream(output_name, source, vars);
+        }
+    }
+
+    /// Run the source command of an output for its next image. A `waiting` output switches as
+    /// soon as the image arrives. Returns false if the output has no source command running per
+    /// image.
+    pub fn request_source(&mut self, output: &OutputRepr, waiting: bool) -> bool {
+        let Some(source) = output
+            .output_config
+            .as_ref()
+            .and_then(|output_config| output_config.exec_source())
+        else {
+            return false;
+        };
+        if !source.stream {
+            let vars = self.source_vars(output);
+            self.sources
+                .request(&output.output_name, source, vars, waiting);
+        }
+        true
+    }
+
+    /// Handle an image picked by a source command
+    pub fn handle_source_event(&mut self, event: SourceEvent) {
+        let Some(advance) = self.sources.finished(&event) else {
+            return;
+        };
+        let Some(output) = self
+            .outputs
+            .get(OutputKey::OutputName(event.output_name.clone()))
+        else {
+            return;
+        };
+        let mut output = output.lock().unwrap();
+        match event.result {
+            Ok(entry) => {
+                let image = entry.path.clone();
+                debug!(
+                    "source of {} picked {}",
+                    output.output_name,
+                    image.display()
+                );
+                // only the settings of the current and the next image are needed
+                let current = output.current_img();
+                output
+                    .playlist
+                    .retain(|path, _| Some(path) == current.as_ref());
+                output.playlist.insert(image.clone(), vec![entry]);
+                output.rotation.source_next = Some(image);
+                match output.ping_draw.as_ref() {
+                    Some(ping_draw) if advance => ping_draw.ping(),
+                    Some(_) => self.preload_next(&output),
+                    // the first configure advances to it
+                    None => {}
+                }
+            }
+            Err(error) => {
+                // keep showing the last image
+                warn!("source of {} failed: {error}", output.output_name);
+                if output.current_img().is_none() && output.dimensions.is_some() {
+                    self.show_fallback(&mut output);
+                }
+            }
+        }
+    }
+
+    /// Pick up the images of an output downloaded from its URL
+    pub fn handle_http_event(&mut self, event: HttpEvent) {
+        if !self.http_sources.finished(&event) {
+            return;
+        }
+        match event.result {
+            Ok(count) => {
+                debug!("{} has {count} downloaded images", event.output_name);
+                self.rescan(|output| output.output_name == event.output_name);
+            }
+            Err(error) => warn!(
+                "downloading images for {} failed, keeping the cached ones: {error}",
+                event.output_name
+            ),
+        }
+    }
+
+    /// Handle events sent back by the renderer thread
+    pub fn handle_render_event(&mut self, event: RenderEvent) {
+        match event {
+            RenderEvent::TextureLoadFailed { image_path, error } => {
+                warn!("unable to load {}: {error}", image_path.display());
+                if self.quarantine.record(&image_path, error.clone()) {
+                    self.notifier.error(
+                        "Unable to load wallpaper",
+                        &format!("{}: {error}", image_path.display()),
+                    );
+                }
+                self.finish_pending_changes(&image_path, None);
+                self.skip_failed(&image_path);
+            }
+            RenderEvent::PaletteReady {
+                image_path,
+                palette,
+            } => {
+                if self.quarantine.release(&image_path) {
+                    info!("{} loaded again", image_path.display());
+                }
+                self.finish_pending_changes(&image_path, palette.as_ref());
+                self.palettes.cache.put(image_path, palette);
+            }
+            RenderEvent::AnimationLoaded { image_path, delays } => {
+                debug!(
+                    "{} is animated, {} frames",
+                    image_path.display(),
+                    delays.len()
+                );
+                self.animations.insert(image_path.clone(), delays.into());
+                // outputs that switched to it before it was decoded
+                for output in self.outputs.iter() {
+                    let mut output = output.lock().unwrap();
+                    if output.current_img().as_ref() == Some(&image_path)
+                        && output.animation.is_none()
+                    {
+                        output.animation = Some(AnimationClock::new(
+                            image_path.clone(),
+                            self.animations[&image_path].clone(),
+                        ));
+                    }
+                }
+            }
+            RenderEvent::ShaderLoaded { shader } => {
+                if self.shader_errors.remove(&shader).is_some() {
+                    info!("{} compiles again", shader.display());
+                }
+            }
+            RenderEvent::ShaderFailed { shader, error } => {
+                self.notifier.error(
+                    "Unable to compile shader",
+                    &format!("{}: {error}", shader.display()),
+                );
+                self.shader_errors.insert(shader, error);
+            }
+        }
+    }
+
+    /// Move outputs showing an image that failed to load on to their next image
+    fn skip_failed(&mut self, image: &Path) {
+        for output in self.outputs.iter() {
+            let mut output = output.lock().unwrap();
+            if output.current_img().as_deref() != Some(image) {
+                continue;
+            }
+            info!("{} failed to load, skipping it", image.display());
+            output.transition = None;
+            output.next_trigger = Some(ShowTrigger::Skip);
+            match output.ping_draw.as_ref() {
+                Some(ping_draw) => ping_draw.ping(),
+                None => output.should_next = true,
+            }
+        }
+    }
+
+    /// Show the fallback on an output with nothing else to show
+    pub fn show_fallback(&mut self, output: &mut OutputRepr) {
+        if !output.fallback {
+            // a shader is drawn without images just as well
+            if output
+                .output_config
+                .as_ref()
+                .and_then(OutputConfig::shader_content)
+                .is_none()
+            {
+                warn!(
+                    "{} has no image that can be shown, showing the fallback",
+                    output.output_name
+                );
+            }
+            self.history.stop(&output.output_name);
+        }
+        let fallback = output
+            .output_config
+            .as_ref()
+            .map(|output_config| output_config.get_fallback(&self.config))
+            .or_else(|| self.config.fallback.clone())
+            .unwrap_or_default();
+        let render = self.renderer_tx.send(RenderCommand::RenderScene {
+            output_name: output.output_name.clone(),
+            scene: output.fallback_scene(fallback, &self.quarantine),
+        });
+        if let Err(e) = render {
+            error!(
+                "failed to render the fallback for {}: {e}",
+                output.output_name
+            );
+        }
+        output.fallback = true;
+        output.transition = None;
+    }
+
+    /// Images that failed to load and outputs with nothing to show
+    pub fn problems(&self) -> Vec<Problem> {
+        let mut problems = self.quarantine.problems();
+        for output in self.outputs.iter() {
+            let output = output.lock().unwrap();
+            if output.fallback
+                && let Some(output_config) = output.output_config.as_ref()
+                && output_config.source.is_none()
+                && output_config.content.is_none()
+            {
+                problems.push(Problem::NothingToShow {
+                    output_name: output.output_name.clone(),
+                    path: output_config.path.clone(),
+                });
+            }
+        }
+        problems.extend(self.sources.problems());
+        problems.extend(self.http_sources.problems());
+        problems.extend(
+            self.shader_errors
+                .iter()
+                .map(|(shader, error)| Problem::ShaderFailed {
+                    shader: shader.clone(),
+                    error: error.clone(),
+                }),
+        );
+        problems
+    }
+
+    /// Mark the current image of an output, see [`Mark`]. Without an output name, the primary
+    /// output or else the only output is used. Returns a message for the user.
+    pub fn mark_current(
+        &mut self,
+        output_name: Option<String>,
+        mark: Mark,
+    ) -> Result<String, SocketError> {
+        let output_name = match output_name.or_else(|| self.config.primary_output.clone()) {
+            Some(output_name) => output_name,
+            None => {
+                let mut outputs = self.outputs.iter();
+                match (outputs.next(), outputs.next()) {
+                    (Some(output), None) => output.lock().unwrap().output_name.clone(),
+                    _ => return Err(SocketError::NoOutputSelected),
+                }
+            }
+        };
+        let image = self
+            .outputs
+            .get(OutputKey::OutputName(output_name.clone()))
+            .ok_or_else(|| SocketError::UnindentifiedOutput {
+                output_name: output_name.clone(),
+            })?
+            .lock()
+            .unwrap()
+            .current_img()
+            .ok_or_else(|| SocketError::NoCurrentImage {
+                output: output_name.clone(),
+            })?;
+        let failed = |error: String| SocketError::ImageActionFailed {
+            path: image.clone(),
+            error,
+        };
+
+        let message = match mark {
+            Mark::Favorite => {
+                let added = self
+                    .library
+                    .add_favorite(image.clone())
+                    .map_err(|e| failed(e.to_string()))?;
+                self.favorites_changed();
+                match added {
+                    true => format!("Added {} to favorites", image.display()),
+                    false => format!("{} is already a favorite", image.display()),
+                }
+            }
+            Mark::Ban | Mark::Trash => {
+                match mark {
+                    Mark::Trash if archive::split(&image).is_some() => {
+                        return Err(failed(
+                            "images inside an archive can't be trashed, ban it instead".to_string(),
+                        ));
+                    }
+                    Mark::Trash => trash::delete(&image).map_err(|e| failed(e.to_string()))?,
+                    _ => {
+                        self.library
+                            .add_ban(image.clone())
+                            .map_err(|e| failed(e.to_string()))?;
+                    }
+                }
+
+                // move on right away on every output showing it
+                let showing = self
+                    .outputs
+                    .iter()
+                    .filter(|output| output.lock().unwrap().current_img().as_ref() == Some(&image))
+                    .collect::<Vec<_>>();
+                self.rescan(|_| true);
+                for output in showing {
+                    let mut output = output.lock().unwrap();
+                    output.rotation.override_img = None;
+                    output.next_trigger = Some(ShowTrigger::Skip);
+                    match output.ping_draw.as_ref() {
+                        Some(ping_draw) => ping_draw.ping(),
+                        None => output.should_next = true,
+                    }
+                }
+                match mark {
+                    Mark::Trash => format!("Moved {} to the trash", image.display()),
+                    _ => format!("Banned {}", image.display()),
+                }
+            }
+        };
+        info!("{output_name}: {message}");
+        Ok(message)
+    }
+
+    /// Update the outputs showing the favorites
+    fn favorites_changed(&mut self) {
+        self.rescan(|output| {
+            output
+                .output_config
+                .as_ref()
+                .is_some_and(|output_config| output_config.path == Path::new(FAVORITES_SOURCE))
+        });
+    }
+
+    /// Handle actions clicked on desktop notifications
+    pub fn handle_notification_action(&mut self, action: NotificationAction) {
+        match action {
+            NotificationAction::Next { output_name } => {
+                match self.outputs.get(OutputKey::OutputName(output_name.clone())) {
+                    Some(output) => {
+                        let mut output = output.lock().unwrap();
+                        output.next_trigger = Some(ShowTrigger::Next);
+                        match output.ping_draw.as_ref() {
+                            Some(ping_draw) => ping_draw.ping(),
+                            None => error!("ping draw does not exist for {output_name}"),
+                        }
+                    }
+                    None => warn!("output {output_name} from notification no longer exists"),
+                }
+            }
+            NotificationAction::Favorite { output_name, image } => {
+                match self.library.add_favorite(image.clone()) {
+                    Ok(true) => {
+                        info!("{output_name}: added {} to favorites", image.display());
+                        self.favorites_changed();
+                    }
+                    Ok(false) => info!("{} is already a favorite", image.display()),
+                    Err(e) => error!("unable to add favorite: {e}"),
+                }
+            }
+        }
+    }
 }
 
 delegate_compositor!(Wayper);
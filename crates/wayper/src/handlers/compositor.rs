@@ -1,16 +1,20 @@
-use std::time::Instant;
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
 
 use smithay_client_toolkit::{
     compositor::CompositorHandler,
     reexports::client::{self, Proxy, QueueHandle},
 };
 use tracing::{debug, error, info, trace};
-use wayper_lib::config::FitMode;
+use wayper_lib::{config::HookEvent, socket::ShowTrigger};
 
 use crate::{
-    handlers::{Wayper, utils},
+    handlers::Wayper,
+    hooks::HookContext,
     map::OutputKey,
-    scene::{ImageNode, Rect, Scene, SceneNode},
+    scene::{ImageNode, Rect, Scene, SceneNode, TransitionEffect, TransitionNode},
     wgpu_renderer::RenderCommand,
 };
 
@@ -71,18 +75,14 @@ impl CompositorHandler for Wayper {
                 // i wonder if this is good practice or nah?
                 let progress = transition.progress();
                 let eased_progress = transition.eased_progress();
-                let trans = transition.transition_type;
+                let trans = transition.transition_type.clone();
                 let transition_direction = transition.direction;
+                let transition_effect = transition.effect.clone();
                 let is_complete = transition.is_complete();
                 let transition_elapsed = transition
                     .start_time
                     .map(|t| t.elapsed().as_millis())
                     .unwrap_or(0);
-                let fit = output_handle
-                    .output_config
-                    .as_ref()
-                    .map(|cfg| cfg.fit)
-                    .unwrap_or(FitMode::default());
                 let output_name = output_handle.output_name.clone();
 
                 trace!(
@@ -92,70 +92,155 @@ impl CompositorHandler for Wayper {
                     "Transition frame"
                 );
 
-                let previous_img = output_handle.previous_img();
+                let previous_img = match output_handle.first_configure {
+                    true => None,
+                    false => self
+                        .history
+                        .shown_before(&output_name)
+                        .map(Path::to_path_buf),
+                };
                 let Some(current_img) = output_handle.current_img() else {
-                    error!("no current image found for {}", output_name);
+                    self.show_fallback(&mut output_handle);
+                    surface.frame(qh, surface.clone());
+                    surface.commit();
                     return;
                 };
 
+                // both images keep playing while transitioning between them
+                output_handle.tick_animations(Instant::now());
+                let current_frame = output_handle.frame(&current_img);
+                let current_position = output_handle.position(&current_img);
+                let previous_frame = previous_img
+                    .as_ref()
+                    .map_or(0, |previous_img| output_handle.frame(previous_img));
+                let previous_position = previous_img
+                    .as_ref()
+                    .map_or(Duration::ZERO, |previous_img| {
+                        output_handle.position(previous_img)
+                    });
+
+                let background = output_handle.background(&current_img);
+                let current_backdrop = output_handle.backdrop(&current_img);
+                let previous_backdrop = previous_img
+                    .as_ref()
+                    .and_then(|previous_img| output_handle.backdrop(previous_img));
+                let current_placement = output_handle.placement(&current_img);
+                let previous_placement = previous_img
+                    .as_ref()
+                    .map(|previous_img| output_handle.placement(previous_img))
+                    .unwrap_or_default();
+                let current_rect = output_handle.rect(&current_img);
+                let previous_rect = previous_img.as_ref().map_or(current_rect, |previous_img| {
+                    output_handle.rect(previous_img)
+                });
+                let current_fit = output_handle.fit(&current_img);
+                let previous_fit = previous_img
+                    .as_ref()
+                    .map(|previous_img| output_handle.fit(previous_img))
+                    .unwrap_or_default();
+
                 let render_start = Instant::now();
-                match trans {
-                    wayper_lib::config::TransitionTypeEnum::Crossfade => {
+                match (trans, transition_effect) {
+                    // shader transitions always have an effect, they crossfade if one doesn't
+                    (wayper_lib::config::TransitionTypeEnum::Crossfade, _) | (_, None) => {
                         let mut nodes: Vec<_> = vec![];
                         if let Some(previous_img) = previous_img {
                             nodes.push(SceneNode::Image(
-                                ImageNode::fullscreen(previous_img, output_size, fit)
+                                ImageNode::fullscreen(previous_img, output_size, previous_fit)
+                                    .with_frame(previous_frame)
+                                    .with_position(previous_position)
+                                    .with_backdrop(previous_backdrop)
+                                    .with_placement(previous_placement)
+                                    .with_rect(previous_rect)
                                     .with_opacity(1.0),
                             ));
                         }
                         nodes.push(SceneNode::Image(
-                            ImageNode::fullscreen(current_img, output_size, fit)
+                            ImageNode::fullscreen(current_img, output_size, current_fit)
+                                .with_frame(current_frame)
+                                .with_position(current_position)
+                                .with_backdrop(current_backdrop)
+                                .with_placement(current_placement)
+                                .with_rect(current_rect)
                                 .with_opacity(eased_progress),
                         ));
                         let render_scene = self.renderer_tx.send(RenderCommand::RenderScene {
                             output_name: output_name.clone(),
-                            scene: Scene {
-                                background: [0.0, 0.0, 0.0, 1.0],
-                                nodes,
-                            },
+                            scene: Scene { background, nodes },
                         });
                         if let Err(e) = render_scene {
                             error!("failed to render transition frame: {e}");
                         }
                     }
-                    wayper_lib::config::TransitionTypeEnum::Slide => {
+                    (wayper_lib::config::TransitionTypeEnum::Slide, _) => {
                         let dx = transition_direction[0];
                         let dy = transition_direction[1];
                         let offset_x = (dx * output_size.0 as f32 * eased_progress).round();
                         let offset_y = (dy * output_size.1 as f32 * eased_progress).round();
+                        // the images slide on top of their motion
                         let old_rect = Rect {
-                            x: offset_x,
-                            y: offset_y,
-                            width: output_size.0 as f32,
-                            height: output_size.1 as f32,
+                            x: previous_rect.x + offset_x,
+                            y: previous_rect.y + offset_y,
+                            ..previous_rect
                         };
                         let new_rect = Rect {
-                            x: offset_x - dx * output_size.0 as f32,
-                            y: offset_y - dy * output_size.1 as f32,
-                            width: output_size.0 as f32,
-                            height: output_size.1 as f32,
+                            x: current_rect.x + offset_x - dx * output_size.0 as f32,
+                            y: current_rect.y + offset_y - dy * output_size.1 as f32,
+                            ..current_rect
                         };
                         let mut nodes = vec![];
                         if let Some(previous_img) = previous_img {
                             nodes.push(SceneNode::Image(
-                                ImageNode::fullscreen(previous_img, output_size, fit)
+                                ImageNode::fullscreen(previous_img, output_size, previous_fit)
+                                    .with_frame(previous_frame)
+                                    .with_position(previous_position)
+                                    .with_backdrop(previous_backdrop)
+                                    .with_placement(previous_placement)
                                     .with_rect(old_rect),
                             ));
                         }
                         nodes.push(SceneNode::Image(
-                            ImageNode::fullscreen(current_img, output_size, fit)
+                            ImageNode::fullscreen(current_img, output_size, current_fit)
+                                .with_frame(current_frame)
+                                .with_position(current_position)
+                                .with_backdrop(current_backdrop)
+                                .with_placement(current_placement)
                                 .with_rect(new_rect),
                         ));
+                        let render_scene = self.renderer_tx.send(RenderCommand::RenderScene {
+                            output_name: output_name.clone(),
+                            scene: Scene { background, nodes },
+                        });
+
+                        if let Err(e) = render_scene {
+                            error!("failed to render transition frame: {e}");
+                        }
+                    }
+                    (_, Some(effect)) => {
+                        let from = previous_img.map(|previous_img| {
+                            ImageNode::fullscreen(previous_img, output_size, previous_fit)
+                                .with_frame(previous_frame)
+                                .with_position(previous_position)
+                                .with_backdrop(previous_backdrop)
+                                .with_placement(previous_placement)
+                                .with_rect(previous_rect)
+                        });
+                        let to = ImageNode::fullscreen(current_img, output_size, current_fit)
+                            .with_frame(current_frame)
+                            .with_position(current_position)
+                            .with_backdrop(current_backdrop)
+                            .with_placement(current_placement)
+                            .with_rect(current_rect);
                         let render_scene = self.renderer_tx.send(RenderCommand::RenderScene {
                             output_name: output_name.clone(),
                             scene: Scene {
-                                background: [0.0, 0.0, 0.0, 1.0],
-                                nodes,
+                                background,
+                                nodes: vec![SceneNode::Transition(TransitionNode {
+                                    from,
+                                    to,
+                                    effect,
+                                    progress: eased_progress,
+                                })],
                             },
                         });
 
@@ -168,7 +253,7 @@ impl CompositorHandler for Wayper {
                 trace!(render_time_us = render_time.as_micros(), "GPU render time");
 
                 if is_complete {
-                    let current_index = output_handle.index;
+                    let current_index = output_handle.rotation.index;
                     let current_image = output_handle.current_img();
 
                     info!(
@@ -183,7 +268,18 @@ impl CompositorHandler for Wayper {
                     );
 
                     debug!("Transition complete for {}", output_name);
+                    if let Some(current_image) = current_image.as_ref() {
+                        let context = HookContext::new(HookEvent::TransitionEnd)
+                            .output(&output_name)
+                            .profile(&self.current_profile)
+                            .image(current_image)
+                            .index(current_index);
+                        let output_config = output_handle.output_config.clone();
+                        self.fire_hook(HookEvent::TransitionEnd, output_config.as_ref(), context);
+                    }
                     output_handle.transition = None;
+                    output_handle.previous_animation = None;
+                    output_handle.previous_motion = None;
                     output_handle.last_render_instant = Instant::now();
                     output_handle.frame_count += 1;
 
@@ -211,46 +307,70 @@ impl CompositorHandler for Wayper {
                     frame_count
                 );
 
-                output_handle.next();
-
-                let Some(image) = output_handle.current_img() else {
-                    error!("no image found for {}", output_handle.output_name);
+                // with a source command there is nothing to advance to until it picked an
+                // image, the current one stays meanwhile
+                if output_handle.rotation.pending_override.is_none()
+                    && output_handle.rotation.queue.is_empty()
+                    && output_handle.rotation.source_next.is_none()
+                    && self.request_source(&output_handle, true)
+                {
+                    output_handle.should_next = false;
+                    surface.frame(qh, surface.clone());
+                    surface.commit();
                     return;
-                };
-
-                let should_transition = output_handle
-                    .output_config
-                    .as_ref()
-                    .map(|cfg| cfg.is_transitions_enabled(&self.config))
-                    .unwrap_or(true);
+                }
 
-                let transition_config = output_handle
-                    .output_config
-                    .as_ref()
-                    .and_then(|cfg| cfg.get_transition_config(&self.config));
+                let prev_image = output_handle.current_img();
+                let trigger = output_handle
+                    .next_trigger
+                    .take()
+                    .unwrap_or(ShowTrigger::Rotation);
+                let image = output_handle.next(&mut self.quarantine);
+                self.state.update(
+                    &self.current_profile,
+                    &output_handle.output_name,
+                    output_handle.rotation.state(),
+                );
 
-                if should_transition && let Some(transition_cfg) = transition_config {
+                let Some(image) = image else {
+                    self.show_fallback(&mut output_handle);
+                    output_handle.should_next = false;
+                    surface.frame(qh, surface.clone());
+                    surface.commit();
+                    return;
+                };
+                self.history
+                    .record(&output_handle.output_name, &image, trigger);
+                output_handle.start_animation(&self.animations, &self.config);
+                // pick the next one ahead of time, so it can be preloaded
+                self.request_source(&output_handle, false);
+                self.notifier
+                    .image_changed(&output_handle.output_name, &image);
+
+                if let Some(transition_cfg) = output_handle.transition_config(&image, &self.config)
+                {
                     let transition_type = transition_cfg.pick_random_type();
                     let duration_ms = transition_cfg.duration_ms;
                     let target_fps = transition_cfg.fps;
                     let transition_direction = match transition_type {
-                        wayper_lib::config::TransitionTypeEnum::Crossfade => [0.0, 0.0],
                         wayper_lib::config::TransitionTypeEnum::Slide => {
                             transition_cfg.slide.direction.as_vec2()
                         }
+                        _ => [0.0, 0.0],
                     };
+                    let transition_effect =
+                        TransitionEffect::new(&transition_type, &transition_cfg, &mut rand::rng());
 
                     output_handle.transition = Some(crate::output::TransitionData::new(
-                        transition_type,
+                        transition_type.clone(),
                         duration_ms,
                         target_fps,
                         transition_direction,
+                        transition_effect,
+                        transition_cfg.easing,
                     ));
 
-                    let transition_name = match transition_type {
-                        wayper_lib::config::TransitionTypeEnum::Crossfade => "crossfade",
-                        wayper_lib::config::TransitionTypeEnum::Slide => "slide",
-                    };
+                    let transition_name = transition_type.name();
 
                     info!(
                         "{} transitioning with {}",
@@ -288,22 +408,9 @@ impl CompositorHandler for Wayper {
                         );
                     }
                 } else {
-                    let current_index = output_handle.index;
-                    let output_size: (u32, u32) = output_handle.dimensions.unwrap_or((0, 0));
-                    let fit = output_handle
-                        .output_config
-                        .as_ref()
-                        .map(|cfg| cfg.fit)
-                        .unwrap_or(FitMode::default());
+                    let current_index = output_handle.rotation.index;
                     let output_name = output_handle.output_name.clone();
-                    let scene = Scene {
-                        background: [0.0, 0.0, 0.0, 1.0],
-                        nodes: vec![SceneNode::Image(ImageNode::fullscreen(
-                            image.clone(),
-                            output_size,
-                            fit,
-                        ))],
-                    };
+                    let scene = output_handle.static_scene(image.clone());
                     let render_frame = self.renderer_tx.send(RenderCommand::RenderScene {
                         output_name: output_name.clone(),
                         scene,
@@ -322,6 +429,8 @@ impl CompositorHandler for Wayper {
 
                     output_handle.last_render_instant = Instant::now();
                     output_handle.frame_count += 1;
+                    // the first image may come after the fallback
+                    output_handle.first_configure = false;
 
                     // Log GPU metrics every 100 frames
                     if output_handle.frame_count % 100 == 0 {
@@ -335,8 +444,8 @@ impl CompositorHandler for Wayper {
 
                 output_handle.should_next = false;
 
-                let next_image = output_handle.peek_next_img();
                 if let Some(_dims) = output_handle.dimensions
+                    && let Some(next_image) = output_handle.peek_next_img(&self.quarantine)
                     && let Err(e) = self.renderer_tx.send(RenderCommand::RequestTextureLoad {
                         image_path: next_image,
                         output_name: output_handle.output_name.clone(),
@@ -345,12 +454,43 @@ impl CompositorHandler for Wayper {
                     error!("Failed to request pre-load: {}", e);
                 }
 
-                if let Some(config) = &output_handle.output_config
-                    && let Some(command) = config.run_command.clone()
-                {
-                    let img_path = image.clone();
-                    std::thread::spawn(|| utils::run_command(command, img_path));
+                let context = HookContext::new(HookEvent::WallpaperChange)
+                    .output(&output_handle.output_name)
+                    .profile(&self.current_profile)
+                    .image(&image)
+                    .prev_image(prev_image.as_deref())
+                    .index(output_handle.rotation.index);
+                let output_config = output_handle.output_config.clone();
+                let output_name = output_handle.output_name.clone();
+                self.wallpaper_changed(&output_name, &image, output_config.as_ref(), context);
+            } else if output_handle.tick_shader(Instant::now()) {
+                let image = output_handle.current_img();
+                output_handle.palette = image
+                    .as_ref()
+                    .and_then(|image| self.palettes.cache.peek(image).cloned().flatten());
+                if let Some(scene) = output_handle.shader_scene(image) {
+                    let render_frame = self.renderer_tx.send(RenderCommand::RenderScene {
+                        output_name: output_handle.output_name.clone(),
+                        scene,
+                    });
+                    if let Err(e) = render_frame {
+                        error!("failed to render shader frame: {e}");
+                    }
+                }
+            } else if !output_handle.fallback
+                && (output_handle.tick_animations(Instant::now())
+                    || output_handle.motion_due(Instant::now()))
+                && let Some(image) = output_handle.current_img()
+            {
+                let scene = output_handle.static_scene(image);
+                let render_frame = self.renderer_tx.send(RenderCommand::RenderScene {
+                    output_name: output_handle.output_name.clone(),
+                    scene,
+                });
+                if let Err(e) = render_frame {
+                    error!("failed to render animation frame: {e}");
                 }
+                output_handle.last_render_instant = Instant::now();
             }
             surface.frame(qh, surface.clone());
             surface.commit();
@@ -1,4 +1,4 @@
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
 use smithay_client_toolkit::{
     reexports::{
@@ -8,9 +8,11 @@ use smithay_client_toolkit::{
     shell::{WaylandSurface, wlr_layer::LayerShellHandler},
 };
 use tracing::{debug, error, info, instrument, trace, warn};
-use wayper_lib::event_source::DrawSource;
+use wayper_lib::{
+    config::HookEvent, easing::Easing, event_source::DrawSource, socket::ShowTrigger,
+};
 
-use crate::{handlers::Wayper, map::OutputKey};
+use crate::{handlers::Wayper, hooks::HookContext, map::OutputKey, scene::TransitionEffect};
 
 impl LayerShellHandler for Wayper {
     fn closed(
@@ -71,118 +73,164 @@ impl LayerShellHandler for Wayper {
                         }
                     };
 
-                    let img_list_len = output_guard.img_list.len();
-                    let current_index = output_guard.index;
-                    if img_list_len == 0 {
-                        warn!(
-                            "no images found for output {}, skipping initial render",
-                            output_guard.output_name
-                        );
-                        return;
-                    }
-
-                    if let Some(current_img) = output_guard.img_list.get(current_index)
-                        && let Err(e) = self.renderer_tx.send(
+                    let current_index = output_guard.rotation.index;
+                    // a restored one-off image is shown before the rotation continues
+                    let current_img = output_guard
+                        .current_img()
+                        .filter(|image| !self.quarantine.skips(image, &output_name));
+                    if let Some(current_img) = current_img {
+                        if let Err(e) = self.renderer_tx.send(
                             crate::wgpu_renderer::RenderCommand::RequestTextureLoad {
-                                image_path: current_img.to_path_buf(),
+                                image_path: current_img.clone(),
                                 output_name: output_name.clone(),
                             },
-                        )
-                    {
-                        error!("Failed to pre-load current image: {}", e);
-                    }
+                        ) {
+                            error!("Failed to pre-load current image: {}", e);
+                        }
 
-                    let next_index = (current_index + 1) % img_list_len;
-                    if let Some(next_img) = output_guard.img_list.get(next_index)
-                        && let Err(e) = self.renderer_tx.send(
-                            crate::wgpu_renderer::RenderCommand::RequestTextureLoad {
-                                image_path: next_img.to_path_buf(),
-                                output_name: output_name.clone(),
-                            },
-                        )
-                    {
-                        error!("Failed to pre-load next image: {}", e);
-                    }
+                        self.history
+                            .record(&output_name, &current_img, ShowTrigger::Startup);
+                        output_guard.start_animation(&self.animations, &self.config);
 
-                    // TODO: is this needed?
-                    // let next_next_index = (current_index + 2) % img_list_len;
-                    // if let Some(next_next_img) = output_guard.img_list.get(next_next_index)
-                    //     && let Err(e) = self.renderer_tx.request_texture_load(
-                    //         next_next_img,
-                    //         (new_width, new_height),
-                    //         output_name.clone(),
-                    //     )
-                    // {
-                    //     error!("Failed to pre-load image after next: {}", e);
-                    // }
-
-                    let (duration_ms, target_fps, transition_type, transition_direction) =
-                        if output_config.is_transitions_enabled(&self.config)
-                            && let Some(transition_cfg) =
-                                output_config.get_transition_config(&self.config)
+                        // the first image counts as a wallpaper change too
+                        let context = HookContext::new(HookEvent::WallpaperChange)
+                            .output(&output_name)
+                            .profile(&self.current_profile)
+                            .image(&current_img)
+                            .prev_image(None)
+                            .index(current_index);
+                        self.wallpaper_changed(
+                            &output_name,
+                            &current_img,
+                            Some(&output_config),
+                            context,
+                        );
+
+                        // the image after the one shown now
+                        if let Some(next_img) = output_guard
+                            .rotation
+                            .upcoming(&output_name, false, 1, &self.quarantine)
+                            .pop()
+                            && let Err(e) = self.renderer_tx.send(
+                                crate::wgpu_renderer::RenderCommand::RequestTextureLoad {
+                                    image_path: next_img.image,
+                                    output_name: output_name.clone(),
+                                },
+                            )
+                        {
+                            error!("Failed to pre-load next image: {}", e);
+                        }
+
+                        // TODO: is this needed?
+                        // let next_next_index = (current_index + 2) % img_list_len;
+                        // if let Some(next_next_img) = output_guard.rotation.img_list.get(next_next_index)
+                        //     && let Err(e) = self.renderer_tx.request_texture_load(
+                        //         next_next_img,
+                        //         (new_width, new_height),
+                        //         output_name.clone(),
+                        //     )
+                        // {
+                        //     error!("Failed to pre-load image after next: {}", e);
+                        // }
+
+                        let (
+                            duration_ms,
+                            target_fps,
+                            transition_type,
+                            transition_direction,
+                            transition_effect,
+                            transition_easing,
+                        ) = if let Some(transition_cfg) =
+                            output_guard.transition_config(&current_img, &self.config)
                         {
                             let transition_type = transition_cfg.pick_random_type();
                             let duration = transition_cfg.duration_ms;
                             let fps = transition_cfg.fps;
                             let td = match transition_type {
-                                wayper_lib::config::TransitionTypeEnum::Crossfade => [0.0, 0.0],
                                 wayper_lib::config::TransitionTypeEnum::Slide => {
                                     transition_cfg.slide.direction.as_vec2()
                                 }
+                                _ => [0.0, 0.0],
                             };
-                            (duration, fps, transition_type, td)
+                            let effect = TransitionEffect::new(
+                                &transition_type,
+                                &transition_cfg,
+                                &mut rand::rng(),
+                            );
+                            (
+                                duration,
+                                fps,
+                                transition_type,
+                                td,
+                                effect,
+                                transition_cfg.easing,
+                            )
                         } else {
                             (
                                 1,
                                 60,
                                 wayper_lib::config::TransitionTypeEnum::Crossfade,
                                 [0.0, 0.0],
+                                None,
+                                Easing::default(),
                             )
                         };
 
-                    output_guard.transition = Some(crate::output::TransitionData::new(
-                        transition_type,
-                        duration_ms,
-                        target_fps,
-                        transition_direction,
-                    ));
-
-                    info!(
-                        "Starting initial render for {} ({} ms at {} FPS)",
-                        output_guard.output_name, duration_ms, target_fps
-                    );
-
-                    if matches!(
-                        transition_type,
-                        wayper_lib::config::TransitionTypeEnum::Slide
-                    ) {
-                        let travel_x = transition_direction[0].abs() * new_width as f32;
-                        let travel_y = transition_direction[1].abs() * new_height as f32;
-                        let travel_px = (travel_x * travel_x + travel_y * travel_y).sqrt();
-                        let frame_count = duration_ms as f32 / 1000.0 * target_fps as f32;
-                        let px_per_frame = if frame_count > 0.0 {
-                            travel_px / frame_count
-                        } else {
-                            0.0
-                        };
-
-                        debug!(
-                            output = %output_guard.output_name,
-                            width = new_width,
-                            height = new_height,
+                        output_guard.transition = Some(crate::output::TransitionData::new(
+                            transition_type.clone(),
                             duration_ms,
                             target_fps,
-                            travel_px = format!("{travel_px:.1}"),
-                            px_per_frame = format!("{px_per_frame:.1}"),
-                            "Movement transition pacing"
+                            transition_direction,
+                            transition_effect,
+                            transition_easing,
+                        ));
+
+                        info!(
+                            "Starting initial render for {} ({} ms at {} FPS)",
+                            output_guard.output_name, duration_ms, target_fps
                         );
+
+                        if matches!(
+                            transition_type,
+                            wayper_lib::config::TransitionTypeEnum::Slide
+                        ) {
+                            let travel_x = transition_direction[0].abs() * new_width as f32;
+                            let travel_y = transition_direction[1].abs() * new_height as f32;
+                            let travel_px = (travel_x * travel_x + travel_y * travel_y).sqrt();
+                            let frame_count = duration_ms as f32 / 1000.0 * target_fps as f32;
+                            let px_per_frame = if frame_count > 0.0 {
+                                travel_px / frame_count
+                            } else {
+                                0.0
+                            };
+
+                            debug!(
+                                output = %output_guard.output_name,
+                                width = new_width,
+                                height = new_height,
+                                duration_ms,
+                                target_fps,
+                                travel_px = format!("{travel_px:.1}"),
+                                px_per_frame = format!("{px_per_frame:.1}"),
+                                "Movement transition pacing"
+                            );
+                        }
+                    } else {
+                        // start from the first image that can be shown, or the fallback
+                        warn!(
+                            "no image to start with for output {}",
+                            output_guard.output_name
+                        );
+                        output_guard.next_trigger = Some(ShowTrigger::Startup);
+                        output_guard.should_next = true;
                     }
 
                     layer.wl_surface().frame(_qh, layer.wl_surface().clone());
                     layer.wl_surface().commit();
                     debug!("finished configure, frame queued");
 
-                    let dur = Duration::from_secs(output_config.duration.unwrap_or(60));
+                    let current_img = output_guard.current_img();
+                    let dur = output_guard.duration(current_img.as_deref());
                     let (draw_source, ping_handle) =
                         DrawSource::from_duration(dur).expect("draw source can be initialized");
 
@@ -190,18 +238,23 @@ impl LayerShellHandler for Wayper {
 
                     let draw_token = self
                         .c_queue_handle
-                        .insert_source(draw_source, move |previous_deadline, _, _data| {
+                        .insert_source(draw_source, move |previous_deadline, _, data| {
                             let instant = Instant::now();
                             let previous_deadline = previous_deadline.get_last_deadline();
-                            let new_instant = previous_deadline + dur;
+
+                            // the image coming up decides how long until the one after it
+                            let mut output = output_handle.lock().unwrap();
+                            output.should_next = true;
+                            let next_img = output.peek_next_img(&data.quarantine);
+                            let new_instant =
+                                previous_deadline + output.duration(next_img.as_deref());
+                            drop(output);
 
                             trace!(
                                 "timer reached deadline: {:?} | new instant: {:?}",
                                 previous_deadline, new_instant
                             );
 
-                            output_handle.lock().unwrap().should_next = true;
-
                             tracing::debug!(
                                 "processing time: {} ms",
                                 (std::time::Instant::now() - instant).as_millis()
@@ -4,7 +4,9 @@ use smithay_client_toolkit::{
 };
 use tracing::{debug, error, info, instrument, trace};
 
-use crate::{handlers::Wayper, map::OutputKey};
+use wayper_lib::config::HookEvent;
+
+use crate::{handlers::Wayper, hooks::HookContext, map::OutputKey};
 
 impl OutputHandler for Wayper {
     fn output_state(&mut self) -> &mut OutputState {
@@ -43,8 +45,17 @@ impl OutputHandler for Wayper {
         output.release();
         let name = info.name.expect("output has name");
 
-        let _removed = self.outputs.remove(OutputKey::OutputName(name.clone()));
+        let removed = self.outputs.remove(OutputKey::OutputName(name.clone()));
         info!("output {name} was removed");
+        self.history.stop(&name);
+        self.sources.stop(&name);
+        self.http_sources.stop(&name);
+        let output_config = removed.lock().unwrap().output_config.clone();
+        let context = HookContext::new(HookEvent::OutputRemoved)
+            .output(&name)
+            .profile(&self.current_profile);
+        self.fire_hook(HookEvent::OutputRemoved, output_config.as_ref(), context);
+        self.update_watches();
         match self.draw_tokens.remove_entry(&info.id) {
             Some((_, token)) => {
                 self.c_queue_handle.remove(token);
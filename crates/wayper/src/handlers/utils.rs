@@ -1,68 +1,161 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
 use rand::seq::SliceRandom;
 use walkdir::WalkDir;
+use wayper_lib::{
+    config::ImageOrder,
+    playlist::{Playlist, is_playlist},
+};
+
+use crate::{
+    archive,
+    http_source::RemoteCache,
+    library::{FAVORITES_SOURCE, Library},
+    rotation, video,
+};
+
+/// Get a list of images from a config, without banned images
+pub fn get_img_list(
+    output_config: Option<&wayper_lib::config::OutputConfig>,
+    library: &Library,
+) -> Vec<std::path::PathBuf> {
+    let Some(output_config) = output_config else {
+        return vec![];
+    };
+
+    let mut files = if let Some(fill) = output_config.fill_content() {
+        vec![fill.to_path()]
+    } else if let Some(source) = output_config.http_source() {
+        RemoteCache::open().images(&source.http)
+    } else if output_config.path == Path::new(FAVORITES_SOURCE) {
+        library.favorites().cloned().collect()
+    } else if is_playlist(&output_config.path) {
+        // missing entries are dropped like removed files, the rest keeps its settings
+        get_playlist(Some(output_config))
+            .entries
+            .into_iter()
+            .map(|entry| entry.path)
+            .filter(|image| rotation::can_show(image))
+            .collect()
+    } else if archive::is_archive(&output_config.path) && output_config.path.is_file() {
+        archive::list_images(&output_config.path).unwrap_or_else(|e| {
+            tracing::error!("unable to read {}: {e}", output_config.path.display());
+            vec![]
+        })
+    } else if output_config.path.is_file() {
+        vec![output_config.path.clone()]
+    } else if output_config.path.is_dir() {
+        WalkDir::new(&output_config.path)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry.file_type().is_file()
+                    && (is_image(entry.path()) || video::is_video(entry.path()))
+            })
+            .map(|entry| entry.into_path())
+            .collect::<Vec<_>>()
+    } else {
+        vec![]
+    };
+    files.retain(|image| !library.is_banned(image));
 
-pub fn run_command(command: String, img_path: std::path::PathBuf) {
-    let mut command = shlex::Shlex::new(&command).collect::<Vec<_>>();
+    sort_images(&mut files, output_config.image_order());
+    tracing::debug!("{:?}", &files);
+    files
+}
 
-    // rudimentary substitution that I can't figure out how to do in place
-    for arg in command.iter_mut() {
-        if arg == "{image}" {
-            arg.clear();
-            arg.push_str(&img_path.display().to_string());
+/// The playlist an output shows, empty if it doesn't show one
+pub fn get_playlist(output_config: Option<&wayper_lib::config::OutputConfig>) -> Playlist {
+    match output_config {
+        Some(output_config) if is_playlist(&output_config.path) => {
+            Playlist::load(&output_config.path).unwrap_or_else(|e| {
+                tracing::error!("{e:#}");
+                Playlist::default()
+            })
         }
+        _ => Playlist::default(),
     }
+}
 
-    tracing::info!("running command {}", command.join(" "));
-
-    // let chains wooooo
-    if let Some((command, args)) = command.split_first()
-        && let Ok(child) = std::process::Command::new(command).args(args).output()
+/// Whether a file is an image, by its extension or else by sniffing its content
+fn is_image(path: &Path) -> bool {
+    if mime_guess::from_path(path)
+        .iter()
+        .any(|mime| mime.type_() == "image")
     {
-        if !child.stdout.is_empty() {
-            tracing::info!(
-                "command stdout: {}",
-                String::from_utf8(child.stdout).unwrap_or_default()
-            );
-        }
+        return true;
+    }
+    let mut header = [0; 32];
+    let Ok(read) = File::open(path).and_then(|mut file| file.read(&mut header)) else {
+        return false;
+    };
+    image::guess_format(&header[..read]).is_ok()
+}
 
-        if !child.stderr.is_empty() {
-            tracing::info!(
-                "command stderr: {}",
-                String::from_utf8(child.stderr).unwrap_or_default()
-            );
-        }
-        tracing::info!("command exited with code {:?}", child.status.code());
-    } else {
-        tracing::error!("command run error, check if the command exists and is correct");
+/// Put images in the order they should be shown
+fn sort_images(images: &mut [PathBuf], order: ImageOrder) {
+    let modified = |path: &PathBuf| {
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+    match order {
+        ImageOrder::Shuffle => images.shuffle(&mut rand::rng()),
+        ImageOrder::Name => images.sort(),
+        ImageOrder::Newest => images.sort_by_cached_key(|path| std::cmp::Reverse(modified(path))),
+        ImageOrder::Oldest => images.sort_by_cached_key(modified),
+        ImageOrder::Playlist => {}
     }
 }
 
-/// Get a list of images from a config
-pub fn get_img_list(
-    output_config: Option<&wayper_lib::config::OutputConfig>,
-) -> Vec<std::path::PathBuf> {
-    if let Some(output_config) = output_config {
-        if output_config.path.is_file() {
-            vec![output_config.path.clone()]
-        } else if output_config.path.is_dir() {
-            let mut files = WalkDir::new(&output_config.path)
-                .into_iter()
-                .filter(|e| {
-                    mime_guess::from_path(e.as_ref().unwrap().path())
-                        .iter()
-                        .any(|ev| ev.type_() == "image")
-                })
-                .map(|e| e.unwrap().path().to_owned())
-                .collect::<Vec<_>>();
+#[cfg(test)]
+mod tests {
+    use wayper_lib::config::OutputConfig;
 
-            let mut rng = rand::rng();
-            files.shuffle(&mut rng);
-            tracing::debug!("{:?}", &files);
-            files
-        } else {
-            vec![]
+    use super::*;
+
+    #[test]
+    fn test_library_images() {
+        let dir = std::env::temp_dir().join(format!("wayper-img-list-{}", std::process::id()));
+        let images = dir.join("images");
+        std::fs::create_dir_all(&images).unwrap();
+        let [a, b, c] = ["a.png", "b.png", "c.png"].map(|name| images.join(name));
+        for image in [&a, &b, &c] {
+            std::fs::write(image, "").unwrap();
         }
-    } else {
-        vec![]
+        let mut library = Library::load_from(dir.clone());
+        library.add_favorite(c.clone()).unwrap();
+        library.add_favorite(a.clone()).unwrap();
+        library.add_ban(b.clone()).unwrap();
+
+        // banned images are left out
+        let output_config = OutputConfig {
+            path: images.clone(),
+            order: ImageOrder::Name,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_img_list(Some(&output_config), &library),
+            vec![a.clone(), c.clone()]
+        );
+
+        // the favorites in the order they were added
+        let favorites = OutputConfig {
+            path: FAVORITES_SOURCE.into(),
+            order: ImageOrder::Playlist,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_img_list(Some(&favorites), &library),
+            vec![c.clone(), a.clone()]
+        );
+        library.add_ban(c.clone()).unwrap();
+        assert_eq!(get_img_list(Some(&favorites), &library), vec![a.clone()]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,293 @@
+//! What was shown on every output and for how long, stored as `history.json` in the state
+//! directory. A limited number of recent entries is kept per output, the totals per image are
+//! kept forever.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use wayper_lib::{
+    paths::write_atomic,
+    socket::{HistoryEntry, ImageStats, ShowTrigger, Stats},
+};
+
+use crate::archive;
+
+const HISTORY_FILE: &str = "history.json";
+/// Entries kept per output
+const HISTORY_LEN: usize = 200;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Totals {
+    times_shown: u64,
+    screen_time: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Saved {
+    #[serde(default)]
+    outputs: BTreeMap<String, VecDeque<HistoryEntry>>,
+    #[serde(default)]
+    totals: BTreeMap<PathBuf, Totals>,
+}
+
+#[derive(Debug)]
+pub struct History {
+    path: PathBuf,
+    saved: Saved,
+    /// Outputs whose last entry was shown by this daemon and is still on screen
+    open: HashSet<String>,
+    /// Position in the history of outputs going back with `previous`
+    cursors: HashMap<String, usize>,
+}
+
+impl History {
+    pub fn load() -> Self {
+        let path = wayper_lib::paths::state_dir().join(HISTORY_FILE);
+        let saved = match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+                tracing::error!("ignoring invalid history in {}: {e}", path.display());
+                Saved::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Saved::default(),
+            Err(e) => {
+                tracing::error!("unable to read {}: {e}", path.display());
+                Saved::default()
+            }
+        };
+        Self::new(path, saved)
+    }
+
+    fn new(path: PathBuf, saved: Saved) -> Self {
+        Self {
+            path,
+            saved,
+            open: HashSet::new(),
+            cursors: HashMap::new(),
+        }
+    }
+
+    /// An output started showing `image`
+    pub fn record(&mut self, output_name: &str, image: &Path, trigger: ShowTrigger) {
+        let now = unix_now();
+        self.close(output_name, now);
+
+        let entries = self
+            .saved
+            .outputs
+            .entry(output_name.to_string())
+            .or_default();
+        entries.push_back(HistoryEntry {
+            output_name: output_name.to_string(),
+            image: image.to_path_buf(),
+            shown_at: now,
+            duration_shown: None,
+            trigger,
+        });
+        self.open.insert(output_name.to_string());
+        self.saved
+            .totals
+            .entry(image.to_path_buf())
+            .or_default()
+            .times_shown += 1;
+
+        if trigger != ShowTrigger::Previous {
+            self.cursors.remove(output_name);
+        }
+        while entries.len() > HISTORY_LEN {
+            entries.pop_front();
+            if let Some(cursor) = self.cursors.get_mut(output_name) {
+                *cursor = cursor.saturating_sub(1);
+            }
+        }
+        self.save();
+    }
+
+    /// An output stopped showing its image, without showing a new one
+    pub fn stop(&mut self, output_name: &str) {
+        if self.close(output_name, unix_now()) {
+            self.save();
+        }
+    }
+
+    /// Set how long the open entry of an output was shown. Returns true if there was one.
+    fn close(&mut self, output_name: &str, now: u64) -> bool {
+        if !self.open.remove(output_name) {
+            return false;
+        }
+        let Some(last) = self
+            .saved
+            .outputs
+            .get_mut(output_name)
+            .and_then(|entries| entries.back_mut())
+        else {
+            return false;
+        };
+        let duration = now.saturating_sub(last.shown_at);
+        last.duration_shown = Some(duration);
+        self.saved
+            .totals
+            .entry(last.image.clone())
+            .or_default()
+            .screen_time += duration;
+        true
+    }
+
+    /// The image shown on an output before the current one
+    pub fn shown_before(&self, output_name: &str) -> Option<&Path> {
+        let entries = self.saved.outputs.get(output_name)?;
+        let before = entries.len().checked_sub(2)?;
+        Some(&entries[before].image)
+    }
+
+    /// Step back in the history of an output, further back every time until the output moves on
+    /// by itself. Images that no longer exist are skipped.
+    pub fn previous(&mut self, output_name: &str) -> Option<PathBuf> {
+        let entries = self.saved.outputs.get(output_name)?;
+        let mut cursor = self
+            .cursors
+            .get(output_name)
+            .copied()
+            .unwrap_or(entries.len().checked_sub(1)?);
+        let current = &entries.get(cursor)?.image;
+        loop {
+            cursor = cursor.checked_sub(1)?;
+            let image = &entries[cursor].image;
+            if image != current && archive::exists(image) {
+                self.cursors.insert(output_name.to_string(), cursor);
+                return Some(image.clone());
+            }
+        }
+    }
+
+    /// Recent entries of all outputs or one of them, newest first
+    pub fn entries(&self, output_name: Option<&str>, limit: usize) -> Vec<HistoryEntry> {
+        let mut entries = self
+            .saved
+            .outputs
+            .iter()
+            .filter(|(name, _)| output_name.is_none_or(|output_name| output_name == *name))
+            .flat_map(|(_, entries)| entries.iter().rev().cloned())
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.shown_at));
+        entries.truncate(limit);
+        entries
+    }
+
+    /// Summary of the history. `images` are the images in the rotations, to find the ones never
+    /// shown.
+    pub fn stats<'a>(&self, images: impl IntoIterator<Item = &'a PathBuf>, limit: usize) -> Stats {
+        let now = unix_now();
+        let mut totals = self.saved.totals.clone();
+        // count the time of the images on screen right now
+        for output_name in &self.open {
+            if let Some(last) = self
+                .saved
+                .outputs
+                .get(output_name)
+                .and_then(|entries| entries.back())
+            {
+                totals.entry(last.image.clone()).or_default().screen_time +=
+                    now.saturating_sub(last.shown_at);
+            }
+        }
+
+        let mut shown = totals
+            .iter()
+            .filter(|(_, totals)| totals.times_shown > 0)
+            .map(|(image, totals)| ImageStats {
+                image: image.clone(),
+                times_shown: totals.times_shown,
+                screen_time: totals.screen_time,
+            })
+            .collect::<Vec<_>>();
+        let total_screen_time = shown.iter().map(|image| image.screen_time).sum();
+
+        shown.sort_by_key(|image| std::cmp::Reverse(image.screen_time));
+        let longest_shown = shown.iter().take(limit).cloned().collect();
+        shown.sort_by_key(|image| std::cmp::Reverse(image.times_shown));
+        shown.truncate(limit);
+
+        let mut never_shown = images
+            .into_iter()
+            .filter(|image| !totals.contains_key(*image))
+            .cloned()
+            .collect::<Vec<_>>();
+        never_shown.sort();
+        never_shown.dedup();
+        let never_shown_count = never_shown.len();
+        never_shown.truncate(limit);
+
+        Stats {
+            most_shown: shown,
+            longest_shown,
+            never_shown,
+            never_shown_count,
+            total_screen_time,
+        }
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_vec(&self.saved)
+            .map_err(std::io::Error::other)
+            .and_then(|json| write_atomic(&self.path, json));
+        if let Err(e) = result {
+            tracing::error!("unable to save history to {}: {e}", self.path.display());
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_previous() {
+        let dir = std::env::temp_dir().join(format!("wayper-history-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let images = ["a", "b", "c"].map(|name| {
+            let image = dir.join(name);
+            std::fs::write(&image, "").unwrap();
+            image
+        });
+
+        let mut history = History::new(dir.join(HISTORY_FILE), Saved::default());
+        assert_eq!(history.previous("eDP-1"), None);
+        for image in &images {
+            history.record("eDP-1", image, ShowTrigger::Rotation);
+        }
+        assert_eq!(history.shown_before("eDP-1"), Some(images[1].as_path()));
+
+        // walks back while going back
+        assert_eq!(history.previous("eDP-1").as_ref(), Some(&images[1]));
+        history.record("eDP-1", &images[1], ShowTrigger::Previous);
+        assert_eq!(history.previous("eDP-1").as_ref(), Some(&images[0]));
+        history.record("eDP-1", &images[0], ShowTrigger::Previous);
+        assert_eq!(history.previous("eDP-1"), None);
+
+        // starts over from the newest entry once the rotation moves on
+        history.record("eDP-1", &images[2], ShowTrigger::Rotation);
+        assert_eq!(history.previous("eDP-1").as_ref(), Some(&images[0]));
+
+        let entries = history.entries(None, 2);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].duration_shown, None);
+        assert!(entries[1].duration_shown.is_some());
+
+        let stats = history.stats(&[dir.join("d")], 10);
+        assert_eq!(stats.most_shown[0].times_shown, 2);
+        assert_eq!(stats.never_shown, vec![dir.join("d")]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
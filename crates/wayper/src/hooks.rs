@@ -0,0 +1,434 @@
+//! Hook runner. Each (event, output) pair gets its own worker thread, which handles debouncing,
+//! timeouts and the concurrency policy of the hook. Results are kept in a small ring buffer
+//! that can be queried through the socket.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    io::Read,
+    process::{Child, Stdio},
+    sync::{
+        Arc, Mutex,
+        mpsc::{Receiver, RecvTimeoutError, Sender, TryRecvError},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use wayper_lib::{
+    config::{HookConcurrency, HookConfig, HookEvent},
+    palette::Palette,
+    socket::{HookReport, HookStatus},
+};
+
+use crate::{command, process_group};
+
+/// Number of reports kept for the socket
+const MAX_REPORTS: usize = 50;
+/// Number of lines kept from the end of stdout and stderr
+const TAIL_LINES: usize = 10;
+/// How often a running command is checked for exit, timeout or a new event
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Values passed to a hook, as `{name}` placeholders and `WAYPER_NAME` environment variables
+#[derive(Debug, Clone)]
+pub struct HookContext {
+    event: HookEvent,
+    output_name: Option<String>,
+    vars: BTreeMap<String, String>,
+}
+
+impl HookContext {
+    pub fn new(event: HookEvent) -> Self {
+        let mut vars = BTreeMap::new();
+        vars.insert("event".to_string(), event.to_string());
+        Self {
+            event,
+            output_name: None,
+            vars,
+        }
+    }
+
+    pub fn output(mut self, output_name: &str) -> Self {
+        self.output_name = Some(output_name.to_string());
+        self.var("output", output_name)
+    }
+
+    pub fn profile(self, profile: &str) -> Self {
+        self.var("profile", profile)
+    }
+
+    pub fn image(self, image: &std::path::Path) -> Self {
+        self.var("image", image.display())
+    }
+
+    pub fn prev_image(self, image: Option<&std::path::Path>) -> Self {
+        match image {
+            Some(image) => self.var("prev_image", image.display()),
+            None => self.var("prev_image", ""),
+        }
+    }
+
+    pub fn index(self, index: usize) -> Self {
+        self.var("index", index)
+    }
+
+    /// Colors of the image as `palette_<role>`, and all swatches as `palette`
+    pub fn palette(self, palette: &Palette) -> Self {
+        let swatches = palette
+            .swatches
+            .iter()
+            .map(|swatch| swatch.color.to_hex())
+            .collect::<Vec<_>>()
+            .join(" ");
+        palette
+            .roles()
+            .into_iter()
+            .fold(self.var("palette", swatches), |context, (role, color)| {
+                context.var(&format!("palette_{role}"), color)
+            })
+    }
+
+    /// Set an arbitrary variable
+    pub fn var(mut self, name: &str, value: impl std::fmt::Display) -> Self {
+        self.vars.insert(name.to_string(), value.to_string());
+        self
+    }
+}
+
+struct HookJob {
+    config: HookConfig,
+    context: HookContext,
+}
+
+/// Workers are keyed by event and output, global events have no output
+type WorkerKey = (HookEvent, Option<String>);
+
+/// Dispatches hook events to per (event, output) workers
+pub struct HookRunner {
+    workers: HashMap<WorkerKey, (Sender<HookJob>, JoinHandle<()>)>,
+    reports: Arc<Mutex<VecDeque<HookReport>>>,
+}
+
+impl HookRunner {
+    pub fn new() -> Self {
+        Self {
+            workers: HashMap::new(),
+            reports: Default::default(),
+        }
+    }
+
+    /// Run `config` for the event described by `context`
+    pub fn fire(&mut self, config: HookConfig, context: HookContext) {
+        let key = (context.event, context.output_name.clone());
+
+        // respawn the worker if it somehow died
+        if let Some((_, handle)) = self.workers.get(&key)
+            && handle.is_finished()
+        {
+            self.workers.remove(&key);
+        }
+
+        let (tx, _) = self.workers.entry(key).or_insert_with(|| {
+            let (tx, rx) = std::sync::mpsc::channel();
+            let reports = self.reports.clone();
+            let handle = std::thread::spawn(move || hook_worker(rx, reports));
+            (tx, handle)
+        });
+
+        tracing::debug!("firing {} hook", context.event);
+        if let Err(e) = tx.send(HookJob { config, context }) {
+            tracing::error!("unable to send job to hook worker: {e}");
+        }
+    }
+
+    /// The latest reports, newest first
+    pub fn reports(&self, limit: usize) -> Vec<HookReport> {
+        self.reports
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+fn hook_worker(rx: Receiver<HookJob>, reports: Arc<Mutex<VecDeque<HookReport>>>) {
+    let mut pending: VecDeque<HookJob> = VecDeque::new();
+
+    loop {
+        let job = match pending.pop_front() {
+            Some(job) => job,
+            None => match rx.recv() {
+                Ok(job) => job,
+                // the runner is gone
+                Err(_) => return,
+            },
+        };
+
+        let Some(job) = debounce(&rx, job) else {
+            return;
+        };
+
+        let report = run_job(&rx, job, &mut pending);
+
+        match report.status {
+            HookStatus::Exited if report.exit_code == Some(0) => {
+                tracing::debug!("hook finished: {report}")
+            }
+            HookStatus::Replaced => tracing::debug!("hook replaced: {report}"),
+            _ => tracing::warn!("hook failed: {report}"),
+        }
+
+        let mut reports = reports.lock().unwrap();
+        if reports.len() >= MAX_REPORTS {
+            reports.pop_front();
+        }
+        reports.push_back(report);
+    }
+}
+
+/// Wait for the debounce period, newer events replace the pending one. Returns None if the
+/// runner is gone.
+fn debounce(rx: &Receiver<HookJob>, mut job: HookJob) -> Option<HookJob> {
+    loop {
+        if job.config.debounce_ms == 0 {
+            return Some(job);
+        }
+        match rx.recv_timeout(Duration::from_millis(job.config.debounce_ms)) {
+            Ok(newer) => job = newer,
+            Err(RecvTimeoutError::Timeout) => return Some(job),
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+}
+
+/// Run a single job to completion, handling events that arrive while it runs
+fn run_job(rx: &Receiver<HookJob>, job: HookJob, pending: &mut VecDeque<HookJob>) -> HookReport {
+    let args = command::args(&job.config.command, &job.context.vars);
+    let command_line = args.join(" ");
+    let start = Instant::now();
+
+    let report = |status, exit_code, stdout_tail, stderr_tail| HookReport {
+        event: job.context.event.to_string(),
+        output_name: job.context.output_name.clone(),
+        command: command_line.clone(),
+        status,
+        exit_code,
+        duration_ms: start.elapsed().as_millis() as u64,
+        finished_at: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        stdout_tail,
+        stderr_tail,
+    };
+
+    let Some(mut command) = command::command(&args, &job.context.vars) else {
+        return report(
+            HookStatus::SpawnFailed,
+            None,
+            String::new(),
+            "empty command".to_string(),
+        );
+    };
+
+    tracing::info!("running {} hook: {command_line}", job.context.event);
+    let mut child =
+        match process_group::spawn(command.stdout(Stdio::piped()).stderr(Stdio::piped())) {
+            Ok(child) => child,
+            Err(e) => {
+                return report(HookStatus::SpawnFailed, None, String::new(), e.to_string());
+            }
+        };
+
+    // drain the pipes on their own threads so a chatty command can't block on a full pipe
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let timeout = Duration::from_secs(job.config.timeout);
+    let (status, exit_code) = loop {
+        match child.try_wait() {
+            Ok(Some(exit)) => break (HookStatus::Exited, exit.code()),
+            Ok(None) => {}
+            Err(e) => {
+                tracing::error!("unable to wait on hook: {e}");
+                break (HookStatus::Exited, None);
+            }
+        }
+
+        if start.elapsed() >= timeout {
+            kill(&mut child);
+            break (HookStatus::TimedOut, None);
+        }
+
+        match rx.try_recv() {
+            Ok(newer) => match job.config.concurrency {
+                HookConcurrency::Replace => {
+                    kill(&mut child);
+                    pending.clear();
+                    pending.push_back(newer);
+                    break (HookStatus::Replaced, None);
+                }
+                HookConcurrency::Queue => pending.push_back(newer),
+                HookConcurrency::Skip => {
+                    tracing::debug!("{} hook still running, skipping event", newer.context.event)
+                }
+            },
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => {}
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    };
+
+    report(status, exit_code, tail(stdout), tail(stderr))
+}
+
+/// Kill the command and everything it started
+fn kill(child: &mut Child) {
+    if let Err(e) = process_group::kill(child.id()) {
+        tracing::error!("unable to kill hook: {e}");
+    }
+    let _ = child.wait();
+}
+
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> Option<JoinHandle<Vec<u8>>> {
+    pipe.map(|mut pipe| {
+        std::thread::spawn(move || {
+            let mut buf = vec![];
+            let _ = pipe.read_to_end(&mut buf);
+            buf
+        })
+    })
+}
+
+/// Keep the last few lines of the output. Gives up if the pipe stays open after the command
+/// ends, which happens when it leaves children behind.
+fn tail(output: Option<JoinHandle<Vec<u8>>>) -> String {
+    let Some(handle) = output else {
+        return String::new();
+    };
+    let start = Instant::now();
+    while !handle.is_finished() {
+        if start.elapsed() >= Duration::from_secs(1) {
+            return String::from("<output unavailable, pipe still open>");
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    let Ok(output) = handle.join() else {
+        return String::new();
+    };
+    let output = String::from_utf8_lossy(&output);
+    let lines = output.trim_end().lines().collect::<Vec<_>>();
+    lines[lines.len().saturating_sub(TAIL_LINES)..].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(command: &str, timeout: u64, concurrency: HookConcurrency) -> HookConfig {
+        HookConfig {
+            command: command.to_string(),
+            timeout,
+            debounce_ms: 0,
+            concurrency,
+        }
+    }
+
+    #[test]
+    fn test_context_vars() {
+        let context = HookContext::new(HookEvent::WallpaperChange)
+            .output("eDP-1")
+            .image(std::path::Path::new("/wall/{output}/a.png"))
+            .index(3);
+        assert_eq!(
+            command::args("{event} {image} {output} {index}", &context.vars),
+            vec!["wallpaper_change", "/wall/{output}/a.png", "eDP-1", "3"]
+        );
+    }
+
+    #[test]
+    fn test_palette_vars() {
+        let mut pixels = [20, 20, 60, 255].repeat(3);
+        pixels.extend([240, 120, 20, 255]);
+        let palette = Palette::from_rgba(&pixels).unwrap();
+
+        let context = HookContext::new(HookEvent::WallpaperChange).palette(&palette);
+        assert_eq!(
+            command::substitute("{palette_dominant} {palette_accent}", &context.vars),
+            "#14143c #f07814"
+        );
+        assert_eq!(
+            command::substitute("{palette}", &context.vars),
+            "#14143c #f07814"
+        );
+        assert!(
+            command::env_vars(&context.vars)
+                .any(|(name, value)| name == "WAYPER_PALETTE_DARK" && value == "#14143c")
+        );
+    }
+
+    #[test]
+    fn test_run_job_reports_output_and_timeout() {
+        let (_tx, rx) = std::sync::mpsc::channel();
+        let mut pending = VecDeque::new();
+
+        let context = HookContext::new(HookEvent::ProfileChange).profile("work");
+        let job = HookJob {
+            config: hook(
+                "sh -c 'echo {profile}; echo $WAYPER_PROFILE >&2; exit 3'",
+                5,
+                HookConcurrency::Replace,
+            ),
+            context: context.clone(),
+        };
+        let report = run_job(&rx, job, &mut pending);
+        assert_eq!(report.status, HookStatus::Exited);
+        assert_eq!(report.exit_code, Some(3));
+        assert_eq!(report.stdout_tail, "work");
+        assert_eq!(report.stderr_tail, "work");
+
+        let job = HookJob {
+            config: hook("sleep 5", 0, HookConcurrency::Replace),
+            context,
+        };
+        let report = run_job(&rx, job, &mut pending);
+        assert_eq!(report.status, HookStatus::TimedOut);
+    }
+
+    #[test]
+    fn test_timeout_kills_whole_pipeline() {
+        let (_tx, rx) = std::sync::mpsc::channel();
+        let mut pending = VecDeque::new();
+        let pid_file = std::env::temp_dir().join(format!("wayper-hook-{}", std::process::id()));
+
+        let job = HookJob {
+            config: hook(
+                &format!(
+                    "sh -c 'sleep 30 & echo $! > {}; wait; echo done'",
+                    pid_file.display()
+                ),
+                1,
+                HookConcurrency::Replace,
+            ),
+            context: HookContext::new(HookEvent::ProfileChange),
+        };
+        let report = run_job(&rx, job, &mut pending);
+        assert_eq!(report.status, HookStatus::TimedOut);
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        std::fs::remove_file(&pid_file).unwrap();
+        // gone, or killed and waiting to be reaped by whoever inherited it
+        let dead = || {
+            std::fs::read_to_string(format!("/proc/{}/stat", pid.trim()))
+                .map_or(true, |stat| stat.contains(") Z "))
+        };
+        let start = Instant::now();
+        while !dead() && start.elapsed() < Duration::from_secs(1) {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        assert!(dead(), "sleep {} still running", pid.trim());
+    }
+}
@@ -0,0 +1,581 @@
+//! Images downloaded from a URL, see `source = { http = ... }`.
+//!
+//! The URL returns an image, possibly through a redirect, or a JSON list of image URLs. Images
+//! are downloaded on their own thread into `$XDG_CACHE_HOME/wayper/remote`, revalidated with
+//! their ETag or Last-Modified date, and the least recently used ones are pruned once the cache
+//! grows past its size. An output shows what its last refresh left in the cache, so its images
+//! stay available while offline.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+    sync::{Mutex, PoisonError, mpsc},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use smithay_client_toolkit::reexports::calloop::channel::Sender;
+use ureq::{Agent, ResponseExt};
+use wayper_lib::{config::HttpSourceConfig, paths::write_atomic, socket::Problem};
+
+const REMOTE_DIR: &str = "remote";
+const INDEX_FILE: &str = "index.json";
+/// Sources refreshing more often than this are refreshed this often
+const MIN_REFRESH: Duration = Duration::from_secs(10);
+
+/// Refreshes update the index one at a time. Readers don't need it, the index is written
+/// atomically.
+static REFRESHING: Mutex<()> = Mutex::new(());
+
+/// The outcome of refreshing the URL of an output: how many images it has
+pub struct HttpEvent {
+    pub output_name: String,
+    /// The refresh loop that sent it, results of stopped loops are dropped
+    id: u64,
+    pub result: Result<usize, String>,
+}
+
+pub struct HttpSources {
+    tx: Sender<HttpEvent>,
+    next_id: u64,
+    /// The refresh loop per output, it stops once its sender is dropped
+    running: HashMap<String, (u64, mpsc::Sender<()>)>,
+    /// The last error per output, until a refresh succeeds
+    errors: BTreeMap<String, String>,
+}
+
+impl HttpSources {
+    pub fn new(tx: Sender<HttpEvent>) -> Self {
+        Self {
+            tx,
+            next_id: 0,
+            running: HashMap::new(),
+            errors: BTreeMap::new(),
+        }
+    }
+
+    /// Refresh the images of an output now, then every `refresh`
+    pub fn start(&mut self, output_name: &str, config: &HttpSourceConfig) {
+        self.stop(output_name);
+        self.next_id += 1;
+        let id = self.next_id;
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        self.running.insert(output_name.to_string(), (id, stop_tx));
+        tracing::info!("downloading images for {output_name} from {}", config.http);
+
+        let tx = self.tx.clone();
+        let output_name = output_name.to_string();
+        let config = config.clone();
+        std::thread::spawn(move || {
+            let cache = RemoteCache::open();
+            loop {
+                let result = cache.refresh(&config);
+                let event = HttpEvent {
+                    output_name: output_name.clone(),
+                    id,
+                    result,
+                };
+                if tx.send(event).is_err() {
+                    break;
+                }
+                match stop_rx.recv_timeout(config.refresh.max(MIN_REFRESH)) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    _ => break,
+                }
+            }
+        });
+    }
+
+    pub fn stop(&mut self, output_name: &str) {
+        self.running.remove(output_name);
+        self.errors.remove(output_name);
+    }
+
+    /// Take in a result. Returns false if it came from a stopped loop.
+    pub fn finished(&mut self, event: &HttpEvent) -> bool {
+        if self.running.get(&event.output_name).map(|(id, _)| *id) != Some(event.id) {
+            return false;
+        }
+        match &event.result {
+            Ok(_) => self.errors.remove(&event.output_name),
+            Err(error) => self.errors.insert(event.output_name.clone(), error.clone()),
+        };
+        true
+    }
+
+    pub fn problems(&self) -> Vec<Problem> {
+        self.errors
+            .iter()
+            .map(|(output_name, error)| Problem::SourceFailed {
+                output_name: output_name.clone(),
+                error: error.clone(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedImage {
+    /// Name of the file in the cache
+    file: String,
+    #[serde(default)]
+    validators: Validators,
+    size: u64,
+    /// Unix time in milliseconds it was last part of a refresh
+    last_used: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Listing {
+    /// Image URLs, in the order they were listed
+    images: Vec<String>,
+    #[serde(default)]
+    validators: Validators,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    /// Downloaded images by URL
+    #[serde(default)]
+    images: BTreeMap<String, CachedImage>,
+    /// The images of every source URL
+    #[serde(default)]
+    sources: BTreeMap<String, Listing>,
+}
+
+enum Fetched {
+    NotModified,
+    Body {
+        validators: Validators,
+        content_type: Option<String>,
+        /// Where it ended up after redirects
+        uri: String,
+        body: Vec<u8>,
+    },
+}
+
+pub struct RemoteCache {
+    dir: PathBuf,
+}
+
+impl RemoteCache {
+    pub fn open() -> Self {
+        Self {
+            dir: wayper_lib::paths::cache_dir().join(REMOTE_DIR),
+        }
+    }
+
+    /// The cached images of a source URL
+    pub fn images(&self, url: &str) -> Vec<PathBuf> {
+        let index = self.load();
+        let Some(listing) = index.sources.get(url) else {
+            return vec![];
+        };
+        listing
+            .images
+            .iter()
+            .filter_map(|image| index.images.get(image))
+            .map(|cached| self.dir.join(&cached.file))
+            .filter(|path| path.is_file())
+            .collect()
+    }
+
+    /// Fetch a source URL and the images it lists, and prune the cache. Returns the number of
+    /// images of the source. On failure the images of the last refresh are kept.
+    fn refresh(&self, config: &HttpSourceConfig) -> Result<usize, String> {
+        let _refreshing = REFRESHING.lock().unwrap_or_else(PoisonError::into_inner);
+        let agent: Agent = Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(config.timeout)))
+            .build()
+            .into();
+        let limit = config.cache_size_mb * 1024 * 1024;
+        let mut index = self.load();
+        let mut listing = index.sources.get(&config.http).cloned().unwrap_or_default();
+        let mut fresh = HashSet::new();
+
+        match fetch(&agent, &config.http, &listing.validators, limit)? {
+            Fetched::NotModified => {}
+            Fetched::Body {
+                validators,
+                content_type,
+                uri,
+                body,
+            } if is_image(content_type.as_deref(), &body) => {
+                // the images it gave before are kept, for URLs giving a new image every time
+                self.store(&mut index, &uri, validators.clone(), &body)?;
+                listing.images.retain(|image| *image != uri);
+                listing.images.push(uri.clone());
+                listing.validators = validators;
+                fresh.insert(uri);
+            }
+            Fetched::Body {
+                validators, body, ..
+            } => {
+                listing.images = parse_list(&body, &config.http)?;
+                listing.validators = validators;
+            }
+        }
+
+        let mut failure = None;
+        for url in listing.images.iter().filter(|url| !fresh.contains(*url)) {
+            let validators = index
+                .images
+                .get(url)
+                .filter(|cached| self.dir.join(&cached.file).is_file())
+                .map(|cached| cached.validators.clone())
+                .unwrap_or_default();
+            let stored = match fetch(&agent, url, &validators, limit) {
+                Ok(Fetched::NotModified) => Ok(()),
+                Ok(Fetched::Body {
+                    validators, body, ..
+                }) => self.store(&mut index, url, validators, &body),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = stored {
+                // a cached copy is still shown
+                tracing::warn!("{e}");
+                failure.get_or_insert(e);
+            }
+        }
+
+        let now = unix_millis();
+        for url in &listing.images {
+            if let Some(cached) = index.images.get_mut(url) {
+                cached.last_used = now;
+            }
+        }
+        index.sources.insert(config.http.clone(), listing);
+        self.prune(&mut index, limit);
+        self.save(&index)?;
+
+        let count = self.count(&index, &config.http);
+        match failure {
+            Some(e) if count == 0 => Err(e),
+            _ => Ok(count),
+        }
+    }
+
+    fn count(&self, index: &Index, url: &str) -> usize {
+        index.sources.get(url).map_or(0, |listing| {
+            listing
+                .images
+                .iter()
+                .filter(|image| index.images.contains_key(*image))
+                .count()
+        })
+    }
+
+    fn store(
+        &self,
+        index: &mut Index,
+        url: &str,
+        validators: Validators,
+        body: &[u8],
+    ) -> Result<(), String> {
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+        let extension = image::guess_format(body)
+            .ok()
+            .and_then(|format| format.extensions_str().first().copied())
+            .unwrap_or("img");
+        let file = format!("{:016x}.{extension}", hasher.finish());
+        write_atomic(&self.dir.join(&file), body)
+            .map_err(|e| format!("unable to save {url} to the cache: {e}"))?;
+        index.images.insert(
+            url.to_string(),
+            CachedImage {
+                file,
+                validators,
+                size: body.len() as u64,
+                last_used: unix_millis(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Remove the least recently used images until the cache fits in `limit` bytes
+    fn prune(&self, index: &mut Index, limit: u64) {
+        let mut total = index.images.values().map(|cached| cached.size).sum::<u64>();
+        let mut by_use = index
+            .images
+            .iter()
+            .map(|(url, cached)| (cached.last_used, url.clone()))
+            .collect::<Vec<_>>();
+        by_use.sort();
+        for (_, url) in by_use {
+            if total <= limit {
+                break;
+            }
+            let Some(cached) = index.images.remove(&url) else {
+                continue;
+            };
+            total -= cached.size;
+            tracing::debug!("pruning {url} from the cache");
+            if let Err(e) = std::fs::remove_file(self.dir.join(&cached.file)) {
+                tracing::warn!("unable to remove {} from the cache: {e}", cached.file);
+            }
+        }
+        for listing in index.sources.values_mut() {
+            listing
+                .images
+                .retain(|image| index.images.contains_key(image));
+        }
+    }
+
+    fn load(&self) -> Index {
+        let path = self.dir.join(INDEX_FILE);
+        match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+                tracing::error!("ignoring invalid cache index {}: {e}", path.display());
+                Index::default()
+            }),
+            Err(_) => Index::default(),
+        }
+    }
+
+    fn save(&self, index: &Index) -> Result<(), String> {
+        let path = self.dir.join(INDEX_FILE);
+        serde_json::to_vec(index)
+            .map_err(std::io::Error::other)
+            .and_then(|json| write_atomic(&path, json))
+            .map_err(|e| format!("unable to save {}: {e}", path.display()))
+    }
+}
+
+/// GET a URL, unless it didn't change since it was fetched with `validators`
+fn fetch(agent: &Agent, url: &str, validators: &Validators, limit: u64) -> Result<Fetched, String> {
+    let mut request = agent.get(url);
+    if let Some(etag) = &validators.etag {
+        request = request.header("If-None-Match", etag);
+    }
+    if let Some(last_modified) = &validators.last_modified {
+        request = request.header("If-Modified-Since", last_modified);
+    }
+    let mut response = request.call().map_err(|e| format!("{url}: {e}"))?;
+    if response.status() == 304 {
+        return Ok(Fetched::NotModified);
+    }
+
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let validators = Validators {
+        etag: header("etag"),
+        last_modified: header("last-modified"),
+    };
+    let content_type = header("content-type");
+    let uri = response.get_uri().to_string();
+    let body = response
+        .body_mut()
+        .with_config()
+        .limit(limit)
+        .read_to_vec()
+        .map_err(|e| format!("{url}: {e}"))?;
+    Ok(Fetched::Body {
+        validators,
+        content_type,
+        uri,
+        body,
+    })
+}
+
+fn is_image(content_type: Option<&str>, body: &[u8]) -> bool {
+    content_type.is_some_and(|content_type| content_type.starts_with("image/"))
+        || image::guess_format(body).is_ok()
+}
+
+/// A JSON list of image URLs, as strings or objects with a `url`. Relative URLs are relative to
+/// the list.
+fn parse_list(body: &[u8], base: &str) -> Result<Vec<String>, String> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Item {
+        Url(String),
+        Object { url: String },
+    }
+    let items = serde_json::from_slice::<Vec<Item>>(body)
+        .map_err(|e| format!("{base} is neither an image nor a list of images: {e}"))?;
+    Ok(items
+        .into_iter()
+        .map(|item| match item {
+            Item::Url(url) | Item::Object { url } => resolve(base, &url),
+        })
+        .collect())
+}
+
+fn resolve(base: &str, url: &str) -> String {
+    if url.contains("://") {
+        return url.to_string();
+    }
+    let Ok(base_uri) = base.parse::<ureq::http::Uri>() else {
+        return url.to_string();
+    };
+    match (
+        url.strip_prefix('/'),
+        base_uri.scheme_str(),
+        base_uri.authority(),
+    ) {
+        (Some(path), Some(scheme), Some(authority)) => format!("{scheme}://{authority}/{path}"),
+        _ => {
+            let dir = base.split(['?', '#']).next().unwrap_or(base);
+            let dir = dir.rsplit_once('/').map_or(dir, |(dir, _)| dir);
+            format!("{dir}/{url}")
+        }
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{
+            Arc,
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+        },
+    };
+
+    use super::*;
+
+    /// A server for `/list.json` listing `/a.png` and `b.png`, and `/random` redirecting to
+    /// `/c.png`. Images are 400KB and revalidate with their ETag.
+    fn serve(online: Arc<AtomicBool>, downloads: Arc<AtomicUsize>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let path = request.split(' ').nth(1).unwrap_or_default().to_string();
+                let mut if_none_match = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':')
+                        && name.eq_ignore_ascii_case("if-none-match")
+                    {
+                        if_none_match = Some(value.trim().to_string());
+                    }
+                }
+
+                let etag = format!("\"{path}\"");
+                let (status, headers, body) = match path.as_str() {
+                    _ if !online.load(Ordering::SeqCst) => ("503 Unavailable", vec![], vec![]),
+                    _ if if_none_match.as_ref() == Some(&etag) => {
+                        ("304 Not Modified", vec![], vec![])
+                    }
+                    "/list.json" => (
+                        "200 OK",
+                        vec![format!("ETag: {etag}")],
+                        br#"["/a.png", {"url": "b.png"}]"#.to_vec(),
+                    ),
+                    "/random" => ("302 Found", vec!["Location: /c.png".to_string()], vec![]),
+                    "/a.png" | "/b.png" | "/c.png" => {
+                        downloads.fetch_add(1, Ordering::SeqCst);
+                        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+                        png.resize(400 * 1024, 0);
+                        (
+                            "200 OK",
+                            vec![
+                                format!("ETag: {etag}"),
+                                "Content-Type: image/png".to_string(),
+                            ],
+                            png,
+                        )
+                    }
+                    _ => ("404 Not Found", vec![], vec![]),
+                };
+                let mut response = format!(
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    body.len()
+                );
+                for header in headers {
+                    response.push_str(&format!("{header}\r\n"));
+                }
+                response.push_str("\r\n");
+                stream.write_all(response.as_bytes()).unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+        format!("http://{address}")
+    }
+
+    fn source(url: String) -> HttpSourceConfig {
+        HttpSourceConfig {
+            http: url,
+            refresh: Duration::from_secs(60),
+            timeout: 5,
+            cache_size_mb: 1,
+        }
+    }
+
+    #[test]
+    fn test_refresh() {
+        let online = Arc::new(AtomicBool::new(true));
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let server = serve(Arc::clone(&online), Arc::clone(&downloads));
+        let cache = RemoteCache {
+            dir: std::env::temp_dir().join(format!("wayper-remote-{}", std::process::id())),
+        };
+        let list = source(format!("{server}/list.json"));
+        let random = source(format!("{server}/random"));
+
+        assert_eq!(cache.refresh(&list), Ok(2));
+        assert_eq!(cache.images(&list.http).len(), 2);
+        assert_eq!(downloads.load(Ordering::SeqCst), 2);
+
+        // revalidated, nothing downloaded again
+        assert_eq!(cache.refresh(&list), Ok(2));
+        assert_eq!(downloads.load(Ordering::SeqCst), 2);
+
+        // offline, the cached images stay
+        online.store(false, Ordering::SeqCst);
+        assert!(cache.refresh(&list).is_err());
+        assert_eq!(cache.images(&list.http).len(), 2);
+        online.store(true, Ordering::SeqCst);
+
+        // the oldest image makes room for the new one
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(cache.refresh(&random), Ok(1));
+        let images = cache.images(&random.http);
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].extension().unwrap(), "png");
+        assert_eq!(cache.images(&list.http).len(), 1);
+
+        std::fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve() {
+        let base = "https://example.com/walls/list.json?page=1";
+        assert_eq!(resolve(base, "a.png"), "https://example.com/walls/a.png");
+        assert_eq!(resolve(base, "/a.png"), "https://example.com/a.png");
+        assert_eq!(
+            resolve(base, "https://cdn.example.com/a.png"),
+            "https://cdn.example.com/a.png"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! On-disk library of images the user has marked. Stored as plain lists of paths in the data
+//! directory so they are easy to edit or sync by hand.
+
+use std::path::{Path, PathBuf};
+
+use color_eyre::{Result, eyre::WrapErr};
+use indexmap::IndexSet;
+use wayper_lib::paths::write_atomic;
+
+use crate::archive;
+
+const FAVORITES_FILE: &str = "favorites";
+const BANS_FILE: &str = "bans";
+
+/// Output `path` that shows the favorites instead of a directory
+pub const FAVORITES_SOURCE: &str = "@favorites";
+
+#[derive(Debug, Default)]
+pub struct Library {
+    dir: PathBuf,
+    favorites: IndexSet<PathBuf>,
+    bans: IndexSet<PathBuf>,
+}
+
+impl Library {
+    /// Load the library from the data directory. Missing files are treated as empty lists.
+    pub fn load() -> Self {
+        Self::load_from(wayper_lib::paths::data_dir())
+    }
+
+    /// Load the library from `dir` instead of the data directory
+    pub fn load_from(dir: PathBuf) -> Self {
+        let favorites = read_list(&dir.join(FAVORITES_FILE));
+        let bans = read_list(&dir.join(BANS_FILE));
+        tracing::debug!(
+            "loaded {} favorites and {} bans",
+            favorites.len(),
+            bans.len()
+        );
+        Self {
+            dir,
+            favorites,
+            bans,
+        }
+    }
+
+    /// Mark an image as a favorite. Returns false if it already was one.
+    pub fn add_favorite(&mut self, image: PathBuf) -> Result<bool> {
+        if !self.favorites.insert(image) {
+            return Ok(false);
+        }
+        write_list(&self.dir.join(FAVORITES_FILE), &self.favorites)?;
+        Ok(true)
+    }
+
+    /// Ban an image, it is never shown again. A banned favorite stops being one. Returns false
+    /// if it already was banned.
+    pub fn add_ban(&mut self, image: PathBuf) -> Result<bool> {
+        if self.favorites.shift_remove(&image) {
+            write_list(&self.dir.join(FAVORITES_FILE), &self.favorites)?;
+        }
+        if !self.bans.insert(image) {
+            return Ok(false);
+        }
+        write_list(&self.dir.join(BANS_FILE), &self.bans)?;
+        Ok(true)
+    }
+
+    pub fn is_banned(&self, image: &Path) -> bool {
+        self.bans.contains(image)
+    }
+
+    /// Favorites that still exist, in the order they were added
+    pub fn favorites(&self) -> impl Iterator<Item = &PathBuf> {
+        self.favorites.iter().filter(|image| archive::exists(image))
+    }
+}
+
+/// Read a newline separated list of paths, skipping blank lines
+fn read_list(path: &Path) -> IndexSet<PathBuf> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(PathBuf::from)
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => IndexSet::new(),
+        Err(e) => {
+            tracing::error!("unable to read {}: {e}", path.display());
+            IndexSet::new()
+        }
+    }
+}
+
+fn write_list(path: &Path, list: &IndexSet<PathBuf>) -> Result<()> {
+    let mut content = String::new();
+    for entry in list {
+        content.push_str(&entry.display().to_string());
+        content.push('\n');
+    }
+    write_atomic(path, content).wrap_err_with(|| format!("writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ban_removes_favorite() {
+        let dir = std::env::temp_dir().join(format!("wayper-library-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.png"), dir.join("b.png"));
+        for image in [&a, &b] {
+            std::fs::write(image, "").unwrap();
+        }
+
+        let mut library = Library::load_from(dir.clone());
+        assert!(library.add_favorite(a.clone()).unwrap());
+        assert!(library.add_favorite(b.clone()).unwrap());
+        assert!(!library.add_favorite(a.clone()).unwrap());
+
+        // on disk too
+        assert!(library.add_ban(b.clone()).unwrap());
+        assert!(!library.add_ban(b.clone()).unwrap());
+        let library = Library::load_from(dir.clone());
+        assert!(library.is_banned(&b));
+        assert_eq!(library.favorites().collect::<Vec<_>>(), vec![&a]);
+
+        // favorites that are gone are left out
+        std::fs::remove_file(&a).unwrap();
+        assert_eq!(library.favorites().count(), 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
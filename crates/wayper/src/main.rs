@@ -5,7 +5,7 @@ use std::{collections::HashMap, ops::Deref, path::Path, sync::mpsc::SyncSender,
 use clap::{Parser, ValueEnum};
 use color_eyre::Result;
 #[cfg(target_os = "linux")]
-use handlers::Wayper;
+use handlers::{Mark, Palettes, QueueEdit, Wayper};
 #[cfg(target_os = "linux")]
 use smithay_client_toolkit::{
     compositor::CompositorState,
@@ -31,28 +31,80 @@ use tracing_subscriber::{Layer as TLayer, fmt, prelude::__tracing_subscriber_Sub
 use wayper_lib::{
     config::Config,
     socket::{
-        OutputWallpaper, SocketCommand, SocketError, SocketOutput, WayperSocket, get_socket_path,
+        OutputPalette, OutputQueue, OutputWallpaper, ShowTrigger, SocketCommand, SocketError,
+        SocketOutput, WayperSocket, get_socket_path,
     },
 };
 
 #[cfg(target_os = "linux")]
 use crate::{
+    exec_source::{ExecSources, SourceEvent},
+    history::History,
+    hooks::HookRunner,
+    http_source::{HttpEvent, HttpSources},
+    library::Library,
     map::{OutputKey, OutputMap},
+    notifications::{NotificationAction, Notifier},
     output::OutputRepr,
-    wgpu_renderer::{RenderCommand, WgpuRenderer},
+    state::StateStore,
+    templates::TemplateRenderer,
+    watcher::DirWatcher,
+    wgpu_renderer::{RenderCommand, RenderEvent, WgpuRenderer},
 };
 
+#[cfg(target_os = "linux")]
+mod animation;
+#[cfg(target_os = "linux")]
+mod archive;
+#[cfg(target_os = "linux")]
+mod backgrounds;
+#[cfg(target_os = "linux")]
+mod command;
+#[cfg(target_os = "linux")]
+mod exec_source;
+#[cfg(target_os = "linux")]
+mod fills;
+#[cfg(target_os = "linux")]
+mod history;
+#[cfg(target_os = "linux")]
+mod hooks;
+#[cfg(target_os = "linux")]
+mod http_source;
+#[cfg(target_os = "linux")]
+mod library;
 #[cfg(target_os = "linux")]
 mod map;
 #[cfg(target_os = "linux")]
 mod metered_cache;
 #[cfg(target_os = "linux")]
+mod motion;
+#[cfg(target_os = "linux")]
+mod notifications;
+#[cfg(target_os = "linux")]
 mod output;
+#[cfg(target_os = "linux")]
+mod process_group;
+#[cfg(target_os = "linux")]
+mod quarantine;
+#[cfg(target_os = "linux")]
+mod rotation;
 // mod render_server;
 #[cfg(target_os = "linux")]
 mod handlers;
 mod scene;
 #[cfg(target_os = "linux")]
+mod shader_wallpaper;
+#[cfg(target_os = "linux")]
+mod state;
+#[cfg(target_os = "linux")]
+mod templates;
+#[cfg(target_os = "linux")]
+mod transitions;
+#[cfg(target_os = "linux")]
+mod video;
+#[cfg(target_os = "linux")]
+mod watcher;
+#[cfg(target_os = "linux")]
 mod wgpu_renderer;
 
 #[cfg(not(target_os = "linux"))]
@@ -79,6 +131,13 @@ fn main() -> Result<()> {
     };
 
     let config = Config::load_file(&config_path)?;
+    let state = StateStore::load(cli.fresh);
+    let current_profile = match state.active_profile() {
+        Some(profile) if config.profiles.profiles().iter().any(|p| p == profile) => {
+            profile.to_string()
+        }
+        _ => config.default_profile.clone(),
+    };
 
     // Get the wayland details from the env, initiate the wayland event source
     let conn = Connection::connect_to_env().expect("in a wayland session");
@@ -144,7 +203,51 @@ fn main() -> Result<()> {
         color_eyre::eyre::eyre!(e.to_string())
     })?;
 
-    let (renderer_tx, wgpu_instance) = WgpuRenderer::new();
+    // events coming back from the renderer thread
+    let (render_event_tx, render_event_channel) = calloop::channel::channel::<RenderEvent>();
+    event_loop
+        .handle()
+        .insert_source(render_event_channel, |ev, _, shared_data| match ev {
+            Event::Msg(render_event) => shared_data.handle_render_event(render_event),
+            Event::Closed => tracing::error!("renderer event channel is closed!"),
+        })
+        .unwrap();
+
+    // actions clicked on desktop notifications
+    let (notification_action_tx, notification_action_channel) =
+        calloop::channel::channel::<NotificationAction>();
+    event_loop
+        .handle()
+        .insert_source(notification_action_channel, |ev, _, shared_data| {
+            if let Event::Msg(action) = ev {
+                shared_data.handle_notification_action(action);
+            }
+        })
+        .unwrap();
+
+    // images picked by source commands
+    let (source_tx, source_channel) = calloop::channel::channel::<SourceEvent>();
+    event_loop
+        .handle()
+        .insert_source(source_channel, |ev, _, shared_data| {
+            if let Event::Msg(source_event) = ev {
+                shared_data.handle_source_event(source_event);
+            }
+        })
+        .unwrap();
+
+    // refreshes of images downloaded from URLs
+    let (http_tx, http_channel) = calloop::channel::channel::<HttpEvent>();
+    event_loop
+        .handle()
+        .insert_source(http_channel, |ev, _, shared_data| {
+            if let Event::Msg(http_event) = ev {
+                shared_data.handle_http_event(http_event);
+            }
+        })
+        .unwrap();
+
+    let (renderer_tx, wgpu_instance) = WgpuRenderer::new(render_event_tx, config.animation.clone());
 
     let mut data = handlers::Wayper {
         compositor_state: compositor,
@@ -152,8 +255,27 @@ fn main() -> Result<()> {
         output_state,
         layer_shell,
         shm,
-        current_profile: config.default_profile.clone(),
+        current_profile,
         outputs: output_map,
+        notifier: Notifier::new(config.notifications.clone(), notification_action_tx),
+        library: Library::load(),
+        hooks: HookRunner::new(),
+        palettes: Default::default(),
+        animations: HashMap::new(),
+        shader_errors: HashMap::new(),
+        history: History::load(),
+        quarantine: Default::default(),
+        templates: TemplateRenderer::new(),
+        state,
+        watcher: match DirWatcher::new(&event_loop.handle()) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                tracing::error!("unable to watch wallpaper directories: {e}");
+                None
+            }
+        },
+        sources: ExecSources::new(source_tx),
+        http_sources: HttpSources::new(http_tx),
         config,
         c_queue_handle: event_loop.handle(),
         draw_tokens: HashMap::new(),
@@ -275,6 +397,230 @@ fn handle_command(
         SocketCommand::Profiles => {
             socket_responses.push(SocketOutput::Profiles(wayper.config.profiles.profiles()))
         }
+        SocketCommand::Hooks { limit } => {
+            socket_responses.push(SocketOutput::HookReports(wayper.hooks.reports(limit)))
+        }
+        SocketCommand::Set { image, output_name } => {
+            if !archive::exists(&image) {
+                socket_responses.push(SocketError::ImageNotFound { path: image }.into());
+            } else if let Some(output_name) = output_name.as_ref()
+                && !outputs.contains_key(OutputKey::OutputName(output_name.clone()))
+            {
+                socket_responses.push(
+                    SocketError::UnindentifiedOutput {
+                        output_name: output_name.clone(),
+                    }
+                    .into(),
+                );
+            } else {
+                let mut set = vec![];
+                for value in outputs.iter() {
+                    let mut output = value.lock().unwrap();
+                    if output_name
+                        .as_ref()
+                        .is_some_and(|name| *name != output.output_name)
+                    {
+                        continue;
+                    }
+                    output.rotation.pending_override = Some(image.clone());
+                    output.next_trigger = Some(ShowTrigger::Set);
+                    match output.ping_draw.as_ref() {
+                        Some(ping_draw) => ping_draw.ping(),
+                        None => {
+                            tracing::error!("ping draw does not exist for {}", output.output_name)
+                        }
+                    }
+                    set.push(output.output_name.clone());
+                }
+                socket_responses.push(SocketOutput::Message(format!(
+                    "Showing {} on {}",
+                    image.display(),
+                    set.join(", ")
+                )));
+            }
+        }
+        SocketCommand::Rescan { output_name } => match output_name {
+            Some(output_name)
+                if !outputs.contains_key(OutputKey::OutputName(output_name.clone())) =>
+            {
+                socket_responses.push(SocketError::UnindentifiedOutput { output_name }.into())
+            }
+            output_name => {
+                let summary = wayper.rescan(|output| {
+                    output_name
+                        .as_ref()
+                        .is_none_or(|name| *name == output.output_name)
+                });
+                socket_responses.push(SocketOutput::Message(summary.join("\n")));
+            }
+        },
+        SocketCommand::Palette { output_name } => {
+            /// Get the palette of the current image for the output
+            fn get_output_palette(
+                output: &OutputRepr,
+                palettes: &Palettes,
+            ) -> Result<OutputPalette, SocketError> {
+                let output_name = &output.output_name;
+                let image = output
+                    .current_img()
+                    .ok_or_else(|| SocketError::NoCurrentImage {
+                        output: output_name.to_string(),
+                    })?;
+                match palettes.get(&image) {
+                    Some(Some(palette)) => Ok(OutputPalette {
+                        output_name: output_name.to_string(),
+                        wallpaper: image.display().to_string(),
+                        palette: palette.clone(),
+                    }),
+                    _ => Err(SocketError::PaletteNotReady {
+                        output: output_name.to_string(),
+                    }),
+                }
+            }
+
+            if let Some(output_name) = output_name {
+                match outputs.get(OutputKey::OutputName(output_name.clone())) {
+                    Some(output) => {
+                        match get_output_palette(output.lock().unwrap().deref(), &wayper.palettes) {
+                            Ok(palette) => {
+                                socket_responses.push(SocketOutput::Palettes(vec![palette]))
+                            }
+                            Err(error) => socket_responses.push(error.into()),
+                        }
+                    }
+                    None => socket_responses
+                        .push(SocketError::UnindentifiedOutput { output_name }.into()),
+                }
+            } else {
+                let mut palettes = vec![];
+                let mut errors = vec![];
+                for value in outputs.iter() {
+                    let output = value.lock().unwrap();
+                    match get_output_palette(output.deref(), &wayper.palettes) {
+                        Ok(palette) => palettes.push(palette),
+                        Err(error) => errors.push(error),
+                    }
+                }
+                socket_responses.push(SocketOutput::Palettes(palettes));
+                if !errors.is_empty() {
+                    socket_responses.push(SocketOutput::MultipleErrors(errors));
+                }
+            }
+        }
+        SocketCommand::Favorite { output_name } => {
+            socket_responses.push(match wayper.mark_current(output_name, Mark::Favorite) {
+                Ok(message) => SocketOutput::Message(message),
+                Err(error) => error.into(),
+            })
+        }
+        SocketCommand::Ban { output_name } => {
+            socket_responses.push(match wayper.mark_current(output_name, Mark::Ban) {
+                Ok(message) => SocketOutput::Message(message),
+                Err(error) => error.into(),
+            })
+        }
+        SocketCommand::Trash { output_name } => {
+            socket_responses.push(match wayper.mark_current(output_name, Mark::Trash) {
+                Ok(message) => SocketOutput::Message(message),
+                Err(error) => error.into(),
+            })
+        }
+        SocketCommand::Previous { output_name } => match output_name {
+            Some(output_name)
+                if !outputs.contains_key(OutputKey::OutputName(output_name.clone())) =>
+            {
+                socket_responses.push(SocketError::UnindentifiedOutput { output_name }.into())
+            }
+            output_name => {
+                let mut shown = vec![];
+                let mut errors = vec![];
+                for value in outputs.iter() {
+                    let mut output = value.lock().unwrap();
+                    if output_name
+                        .as_ref()
+                        .is_some_and(|name| *name != output.output_name)
+                    {
+                        continue;
+                    }
+                    let Some(image) = wayper.history.previous(&output.output_name) else {
+                        errors.push(SocketError::NoPreviousImage {
+                            output: output.output_name.clone(),
+                        });
+                        continue;
+                    };
+                    shown.push(format!("{}: {}", output.output_name, image.display()));
+                    output.rotation.pending_override = Some(image);
+                    output.next_trigger = Some(ShowTrigger::Previous);
+                    match output.ping_draw.as_ref() {
+                        Some(ping_draw) => ping_draw.ping(),
+                        None => {
+                            tracing::error!("ping draw does not exist for {}", output.output_name)
+                        }
+                    }
+                }
+                if !shown.is_empty() {
+                    socket_responses.push(SocketOutput::Message(shown.join("\n")));
+                }
+                if !errors.is_empty() {
+                    socket_responses.push(SocketOutput::MultipleErrors(errors));
+                }
+            }
+        },
+        SocketCommand::History { output_name, limit } => socket_responses.push(
+            SocketOutput::History(wayper.history.entries(output_name.as_deref(), limit)),
+        ),
+        SocketCommand::Stats { limit } => {
+            let images = outputs
+                .iter()
+                .flat_map(|output| output.lock().unwrap().rotation.img_list.clone())
+                .collect::<Vec<_>>();
+            socket_responses.push(SocketOutput::Stats(wayper.history.stats(&images, limit)))
+        }
+        SocketCommand::Queue { output_name, limit } => match output_name {
+            Some(output_name)
+                if !outputs.contains_key(OutputKey::OutputName(output_name.clone())) =>
+            {
+                socket_responses.push(SocketError::UnindentifiedOutput { output_name }.into())
+            }
+            output_name => {
+                let queues = outputs
+                    .iter()
+                    .filter_map(|output| {
+                        let output = output.lock().unwrap();
+                        output_name
+                            .as_ref()
+                            .is_none_or(|name| *name == output.output_name)
+                            .then(|| OutputQueue {
+                                output_name: output.output_name.clone(),
+                                upcoming: output.upcoming(limit, &wayper.quarantine),
+                            })
+                    })
+                    .collect();
+                socket_responses.push(SocketOutput::Queue(queues))
+            }
+        },
+        SocketCommand::Enqueue {
+            images,
+            output_name,
+        } => socket_responses.push(
+            match wayper.edit_queue(output_name, QueueEdit::Enqueue(images)) {
+                Ok(summary) => SocketOutput::Message(summary.join("\n")),
+                Err(error) => error.into(),
+            },
+        ),
+        SocketCommand::Dequeue { image, output_name } => socket_responses.push(
+            match wayper.edit_queue(output_name, QueueEdit::Dequeue(image)) {
+                Ok(summary) => SocketOutput::Message(summary.join("\n")),
+                Err(error) => error.into(),
+            },
+        ),
+        SocketCommand::Shuffle { output_name } => {
+            socket_responses.push(match wayper.edit_queue(output_name, QueueEdit::Shuffle) {
+                Ok(summary) => SocketOutput::Message(summary.join("\n")),
+                Err(error) => error.into(),
+            })
+        }
+        SocketCommand::Problems => socket_responses.push(SocketOutput::Problems(wayper.problems())),
         SocketCommand::GpuMetrics => {
             let (tx, rx) = oneshot::channel();
             wayper
@@ -390,6 +736,10 @@ struct WayperCli {
     /// Log level for file.
     #[arg(short, long, value_enum, default_value_t = LogLevel::Info)]
     log_level: LogLevel,
+
+    /// Ignore the saved rotation state and start over
+    #[arg(long)]
+    fresh: bool,
 }
 
 #[cfg(target_os = "linux")]
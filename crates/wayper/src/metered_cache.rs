@@ -88,18 +88,18 @@ impl<K: Hash + Eq + Clone + std::fmt::Debug, V> MeteredCache<K, V> {
         self.misses.fetch_add(1, Ordering::Relaxed);
 
         // Check if we're at capacity and will evict
-        if self.cache.len() >= self.cache.cap().get() {
-            if let Some((evicted_key, _)) = self.cache.peek_lru() {
-                let evicted_key = evicted_key.clone();
-                if let Some(evicted_size) = self.sizes.remove(&evicted_key) {
-                    self.evictions.fetch_add(1, Ordering::Relaxed);
-                    self.total_bytes.fetch_sub(evicted_size, Ordering::Relaxed);
-                    tracing::debug!(
-                        key = ?evicted_key,
-                        size_mb = evicted_size as f64 / 1_048_576.0,
-                        "Cache eviction"
-                    );
-                }
+        if self.cache.len() >= self.cache.cap().get()
+            && let Some((evicted_key, _)) = self.cache.peek_lru()
+        {
+            let evicted_key = evicted_key.clone();
+            if let Some(evicted_size) = self.sizes.remove(&evicted_key) {
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+                self.total_bytes.fetch_sub(evicted_size, Ordering::Relaxed);
+                tracing::debug!(
+                    key = ?evicted_key,
+                    size_mb = evicted_size as f64 / 1_048_576.0,
+                    "Cache eviction"
+                );
             }
         }
 
@@ -111,6 +111,15 @@ impl<K: Hash + Eq + Clone + std::fmt::Debug, V> MeteredCache<K, V> {
         self.cache.get_or_insert(key, f)
     }
 
+    /// Remove an entry, e.g. when it went stale
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.cache.pop(key)?;
+        if let Some(size) = self.sizes.remove(key) {
+            self.total_bytes.fetch_sub(size, Ordering::Relaxed);
+        }
+        Some(value)
+    }
+
     pub fn metrics(&self) -> CacheMetrics {
         CacheMetrics {
             hits: self.hits.load(Ordering::Relaxed),
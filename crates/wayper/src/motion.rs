@@ -0,0 +1,171 @@
+//! Ken Burns motion, a slow pan and zoom across an image for as long as it is shown. It moves
+//! the rect the image is drawn in, so the fit, backgrounds and transitions stay as they are.
+
+use std::{path::PathBuf, time::Duration};
+
+use rand::Rng;
+use wayper_lib::easing::Easing;
+
+use crate::{animation::AnimationClock, scene::Rect};
+
+/// Part of an image shown: zoomed in by `zoom`, with the point at `anchor` kept in place
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct View {
+    zoom: f32,
+    /// In fractions of the output
+    anchor: [f32; 2],
+}
+
+impl View {
+    const FULL: View = View {
+        zoom: 1.0,
+        anchor: [0.5, 0.5],
+    };
+
+    fn lerp(&self, to: &View, t: f32) -> View {
+        let mix = |from: f32, to: f32| from + (to - from) * t;
+        View {
+            zoom: mix(self.zoom, to.zoom),
+            anchor: [
+                mix(self.anchor[0], to.anchor[0]),
+                mix(self.anchor[1], to.anchor[1]),
+            ],
+        }
+    }
+
+    /// The rect to draw the image in, it always covers the output
+    fn rect(&self, output_size: (u32, u32)) -> Rect {
+        let (output_width, output_height) = (output_size.0 as f32, output_size.1 as f32);
+        let (width, height) = (output_width * self.zoom, output_height * self.zoom);
+        Rect {
+            x: (output_width - width) * self.anchor[0],
+            y: (output_height - height) * self.anchor[1],
+            width,
+            height,
+        }
+    }
+}
+
+/// A pan and zoom between two views of an image, over the time it is shown
+#[derive(Debug, Clone)]
+pub struct KenBurns {
+    pub clock: AnimationClock,
+    from: View,
+    to: View,
+    duration: Duration,
+    easing: Easing,
+}
+
+impl KenBurns {
+    /// Zoom in on `focus` if the image has one, otherwise move between two random views
+    pub fn new(
+        image: PathBuf,
+        amplitude: f32,
+        focus: Option<[f32; 2]>,
+        duration: Duration,
+        easing: Easing,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let zoomed = 1.0 + amplitude.max(0.0);
+        let (from, to) = match focus {
+            Some(anchor) => (
+                View::FULL,
+                View {
+                    zoom: zoomed,
+                    anchor,
+                },
+            ),
+            None => {
+                let mut anchor = || [rng.random::<f32>(), rng.random::<f32>()];
+                let close = View {
+                    zoom: zoomed,
+                    anchor: anchor(),
+                };
+                let wide = View {
+                    zoom: 1.0 + (zoomed - 1.0) * 0.5,
+                    anchor: anchor(),
+                };
+                match rng.random_bool(0.5) {
+                    true => (wide, close),
+                    false => (close, wide),
+                }
+            }
+        };
+        Self {
+            clock: AnimationClock::video(image),
+            from,
+            to,
+            duration,
+            easing,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.clock.position >= self.duration
+    }
+
+    /// The rect to draw the image in now
+    pub fn rect(&self, output_size: (u32, u32)) -> Rect {
+        let t = match self.duration.is_zero() {
+            true => 1.0,
+            false => (self.clock.position.as_secs_f32() / self.duration.as_secs_f32()).min(1.0),
+        };
+        // views past either end could zoom out of the output, curves overshooting stop at them
+        self.from
+            .lerp(&self.to, self.easing.apply(t).clamp(0.0, 1.0))
+            .rect(output_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wayper_lib::easing::Curve;
+
+    use super::*;
+
+    #[test]
+    fn test_ken_burns_rects() {
+        let output_size = (1000, 500);
+        let mut motion = KenBurns::new(
+            PathBuf::from("a.jpg"),
+            0.2,
+            Some([1.0, 0.0]),
+            Duration::from_secs(10),
+            Easing::Linear,
+            &mut rand::rng(),
+        );
+        let start = motion.rect(output_size);
+        assert_eq!(start.as_array(), [0.0, 0.0, 1000.0, 500.0]);
+
+        // zoomed in on the top right corner at the end, and held there
+        motion.clock.position = Duration::from_secs(12);
+        assert!(motion.is_done());
+        let end = motion.rect(output_size);
+        assert_eq!(end.as_array(), [-200.0, 0.0, 1200.0, 600.0]);
+
+        // random views still cover the output all the way, also with curves that overshoot
+        for easing in [
+            Easing::Linear,
+            Easing::In(Curve::Back),
+            Easing::Out(Curve::Elastic),
+        ] {
+            for focus in [None, Some([0.0, 1.0])] {
+                let mut motion = KenBurns::new(
+                    PathBuf::from("a.jpg"),
+                    0.5,
+                    focus,
+                    Duration::from_secs(10),
+                    easing,
+                    &mut rand::rng(),
+                );
+                for tenths in 0..=100 {
+                    motion.clock.position = Duration::from_millis(tenths * 100);
+                    let rect = motion.rect(output_size);
+                    assert!(rect.x <= 1e-3 && rect.y <= 1e-3, "{easing:?} {rect:?}");
+                    assert!(rect.x + rect.width >= 1000.0 - 1e-3, "{easing:?} {rect:?}");
+                    assert!(rect.y + rect.height >= 500.0 - 1e-3, "{easing:?} {rect:?}");
+                }
+            }
+        }
+    }
+}
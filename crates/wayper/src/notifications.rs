@@ -0,0 +1,236 @@
+//! Desktop notifications through `org.freedesktop.Notifications`.
+//!
+//! Sending blocks on D-Bus, and waiting for an action blocks until the notification is closed,
+//! so every notification gets its own short lived thread. Clicked actions are sent back into the
+//! event loop through a calloop channel.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use notify_rust::{Notification, Timeout, Urgency};
+use smithay_client_toolkit::reexports::calloop::channel::Sender;
+use wayper_lib::{config::NotificationsConfig, fill::Fill};
+
+use crate::archive;
+
+const APP_NAME: &str = "wayper";
+const ACTION_NEXT: &str = "next";
+const ACTION_FAVORITE: &str = "favorite";
+
+/// Events a notification can be sent for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Event {
+    ProfileChange,
+    ImageChange,
+    Error,
+}
+
+/// Actions clicked by the user on a notification
+#[derive(Debug)]
+pub enum NotificationAction {
+    /// Advance the output to its next image
+    Next { output_name: String },
+    /// Add the image to the favorites
+    Favorite { output_name: String, image: PathBuf },
+}
+
+pub struct Notifier {
+    config: NotificationsConfig,
+    action_tx: Sender<NotificationAction>,
+    /// Last image change notification per output, for rate limiting
+    last_image_change: HashMap<String, Instant>,
+    /// Errors already reported this session, so a broken image doesn't spam on every cycle
+    reported_errors: HashSet<String>,
+}
+
+impl Notifier {
+    pub fn new(config: NotificationsConfig, action_tx: Sender<NotificationAction>) -> Self {
+        Self {
+            config,
+            action_tx,
+            last_image_change: HashMap::new(),
+            reported_errors: HashSet::new(),
+        }
+    }
+
+    /// The active profile has changed
+    pub fn profile_changed(&self, profile: &str) {
+        if !self.wants(Event::ProfileChange) {
+            return;
+        }
+
+        let mut notification = self.base();
+        notification
+            .summary("Wallpaper profile changed")
+            .body(&format!("Now using profile \"{profile}\""));
+        send(notification, None);
+    }
+
+    /// An output is now showing a new image. Rate limited per output.
+    pub fn image_changed(&mut self, output_name: &str, image: &Path) {
+        if !self.wants(Event::ImageChange) || self.rate_limited(output_name) {
+            return;
+        }
+
+        let file_name = image
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| image.display().to_string());
+
+        let mut notification = self.base();
+        notification
+            .summary(&format!("New wallpaper on {output_name}"))
+            .body(&file_name);
+        // the notification server can't open images inside archives, and fills aren't files
+        if archive::split(image).is_none() && !Fill::is_fill(image) {
+            notification.image_path(&image.display().to_string());
+        }
+
+        if !self.config.actions {
+            send(notification, None);
+            return;
+        }
+
+        notification
+            .action(ACTION_NEXT, "Next")
+            .action(ACTION_FAVORITE, "Favorite");
+
+        let action_tx = self.action_tx.clone();
+        let output_name = output_name.to_string();
+        let image = image.to_path_buf();
+        send(
+            notification,
+            Some(Box::new(move |action: &str| {
+                let action = match action {
+                    ACTION_NEXT => NotificationAction::Next { output_name },
+                    ACTION_FAVORITE => NotificationAction::Favorite { output_name, image },
+                    // closed or dismissed
+                    _ => return,
+                };
+                if let Err(e) = action_tx.send(action) {
+                    tracing::error!("unable to forward notification action: {e}");
+                }
+            })),
+        );
+    }
+
+    /// Something went wrong that the user should know about. Each distinct error is only
+    /// reported once per session.
+    pub fn error(&mut self, summary: &str, body: &str) {
+        if !self.wants(Event::Error) {
+            return;
+        }
+
+        if !self.reported_errors.insert(format!("{summary}\n{body}")) {
+            return;
+        }
+
+        let mut notification = self.base();
+        notification
+            .summary(summary)
+            .body(body)
+            .urgency(Urgency::Critical);
+        send(notification, None);
+    }
+
+    /// Whether notifications are enabled for `event`
+    fn wants(&self, event: Event) -> bool {
+        self.config.enabled
+            && match event {
+                Event::ProfileChange => self.config.on_profile_change,
+                Event::ImageChange => self.config.on_image_change,
+                Event::Error => self.config.on_error,
+            }
+    }
+
+    /// Whether an image change on `output_name` was notified too recently. Starts the interval
+    /// over if it wasn't.
+    fn rate_limited(&mut self, output_name: &str) -> bool {
+        let interval = Duration::from_secs(self.config.image_change_interval);
+        if let Some(last) = self.last_image_change.get(output_name)
+            && last.elapsed() < interval
+        {
+            tracing::trace!("image change notification for {output_name} rate limited");
+            return true;
+        }
+        self.last_image_change
+            .insert(output_name.to_string(), Instant::now());
+        false
+    }
+
+    /// A notification with the common fields set
+    fn base(&self) -> Notification {
+        let mut notification = Notification::new();
+        notification
+            .appname(APP_NAME)
+            .icon("preferences-desktop-wallpaper");
+        if let Some(timeout_ms) = self.config.timeout_ms {
+            notification.timeout(Timeout::Milliseconds(timeout_ms));
+        }
+        notification
+    }
+}
+
+type ActionCallback = Box<dyn FnOnce(&str) + Send>;
+
+/// Show the notification off the event loop thread, and wait for an action if a callback is given
+fn send(notification: Notification, on_action: Option<ActionCallback>) {
+    std::thread::spawn(move || match notification.show() {
+        Ok(handle) => {
+            if let Some(on_action) = on_action {
+                handle.wait_for_action(on_action);
+            }
+        }
+        Err(e) => tracing::warn!("unable to send desktop notification: {e}"),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay_client_toolkit::reexports::calloop::channel::channel;
+    use wayper_lib::config::Config;
+
+    use super::*;
+
+    fn notifier_for(config: &str) -> Notifier {
+        let config = Config::new(&format!(
+            "{config}\n[eDP-1]\npath = \"/home/user/wallpapers\"\n"
+        ))
+        .unwrap();
+        let (action_tx, _action_rx) = channel();
+        Notifier::new(config.notifications, action_tx)
+    }
+
+    #[test]
+    fn test_enabled_events() {
+        // opt-in, nothing is sent without the section
+        let notifier = notifier_for("");
+        assert!(!notifier.wants(Event::ProfileChange));
+        assert!(!notifier.wants(Event::ImageChange));
+        assert!(!notifier.wants(Event::Error));
+
+        let notifier = notifier_for("[notifications]\nenabled = true\non_image_change = false");
+        assert!(notifier.wants(Event::ProfileChange));
+        assert!(!notifier.wants(Event::ImageChange));
+        assert!(notifier.wants(Event::Error));
+
+        let notifier = notifier_for("[notifications]\non_error = true");
+        assert!(!notifier.wants(Event::Error));
+    }
+
+    #[test]
+    fn test_image_changes_rate_limited_per_output() {
+        let mut notifier = notifier_for("[notifications]\nenabled = true");
+        assert!(!notifier.rate_limited("eDP-1"));
+        assert!(notifier.rate_limited("eDP-1"));
+        assert!(!notifier.rate_limited("HDMI-A-1"));
+
+        let mut notifier =
+            notifier_for("[notifications]\nenabled = true\nimage_change_interval = 0");
+        assert!(!notifier.rate_limited("eDP-1"));
+        assert!(!notifier.rate_limited("eDP-1"));
+    }
+}
@@ -1,6 +1,11 @@
 //! Output. Data and processing happens here
 
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use smithay_client_toolkit::{
     output::OutputInfo,
@@ -12,7 +17,29 @@ use smithay_client_toolkit::{
     shm::slot::Buffer,
 };
 
-use wayper_lib::config::{OutputConfig, TransitionTypeEnum};
+use wayper_lib::{
+    config::{Config, Fallback, FitMode, OutputConfig, TransitionConfig, TransitionTypeEnum},
+    easing::Easing,
+    fill::{Background, Fill},
+    palette::Palette,
+    playlist::PlaylistEntry,
+    sidecar::Sidecar,
+    socket::{ShowTrigger, UpcomingImage},
+};
+
+use crate::{
+    animation::AnimationClock,
+    archive,
+    motion::KenBurns,
+    quarantine::Quarantine,
+    rotation::Rotation,
+    scene::{ImageNode, Placement, Rect, Scene, SceneNode, ShaderNode, TransitionEffect},
+    video,
+};
+
+/// Zoom of an image is kept between these
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 10.0;
 
 // TODO: maybe all pub is not a good idea
 
@@ -32,12 +59,32 @@ pub struct OutputRepr {
     pub _surface: Option<WlSurface>,
     pub _layer: LayerSurface,
 
-    pub index: usize,
-    pub img_list: Vec<PathBuf>,
+    /// The images and where the output is in them
+    pub rotation: Rotation,
+    /// Settings of the images of a playlist, per listing of an image
+    pub playlist: HashMap<PathBuf, Vec<PlaylistEntry>>,
+    /// Sidecars of the image shown and the one shown before it
+    pub sidecars: HashMap<PathBuf, Sidecar>,
+    /// Why the next advance happens, for the history. The rotation timer if unset.
+    pub next_trigger: Option<ShowTrigger>,
     pub visible: bool,
     pub should_next: bool,
     pub last_render_instant: std::time::Instant,
     pub transition: Option<TransitionData>,
+    /// Where the output is in the image shown, if it is animated
+    pub animation: Option<AnimationClock>,
+    /// The animation of the image shown before, kept playing while transitioning out of it
+    pub previous_animation: Option<AnimationClock>,
+    /// Pan and zoom across the image shown, if the output has a motion
+    pub motion: Option<KenBurns>,
+    /// The motion of the image shown before, kept moving while transitioning out of it
+    pub previous_motion: Option<KenBurns>,
+    /// The fallback is shown because there is no image that can be shown
+    pub fallback: bool,
+    /// Play time of the shader drawn instead of the images, if there is one
+    pub shader_clock: Option<AnimationClock>,
+    /// Palette of the image handed to the shader
+    pub palette: Option<Palette>,
 
     /// When this output was created/added
     pub created_at: std::time::Instant,
@@ -64,68 +111,380 @@ impl OutputRepr {
         // }
     }
 
-    /// Increment the index and give the image. If its the first configure, it uses
-    /// an index of 0
-    pub fn next(&mut self) -> PathBuf {
-        let img_list = &self.img_list;
-        tracing::debug!("Current index is {}", self.index);
-        self.index = self.get_next_index();
+    /// Increment the index and give the image, skipping quarantined images. If its the first
+    /// configure, it starts at the current index. A pending override or queued image is shown
+    /// without touching the index. None if there is no image that can be shown.
+    pub fn next(&mut self, quarantine: &mut Quarantine) -> Option<PathBuf> {
+        let previous = self.current_img();
+        let next = self
+            .rotation
+            .advance(&self.output_name, self.first_configure, quarantine);
+        if let Some(next) = &next {
+            self.fallback = false;
+            self.load_sidecar(previous.as_deref(), next);
+        }
+        next
+    }
 
-        tracing::debug!("new index is {}", self.index);
-        img_list[self.index].clone()
+    /// Read the sidecar of the image about to be shown, keeping the one of the image shown before
+    /// for the transition out of it
+    fn load_sidecar(&mut self, previous: Option<&Path>, image: &Path) {
+        self.sidecars
+            .retain(|path, _| Some(path.as_path()) == previous);
+        match Sidecar::load(image) {
+            Ok(Some(sidecar)) => {
+                self.sidecars.insert(image.to_path_buf(), sidecar);
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!("{e:#}"),
+        }
     }
 
     /// get the next image, without incrementing the index
-    pub fn peek_next_img(&self) -> PathBuf {
-        self.img_list[self.get_next_index()].clone()
+    pub fn peek_next_img(&self, quarantine: &Quarantine) -> Option<PathBuf> {
+        self.upcoming(1, quarantine)
+            .into_iter()
+            .next()
+            .map(|upcoming| upcoming.image)
     }
 
-    /// Get the next index, but does not modify the original index. Accounts
-    /// for the length of the image vec
-    fn get_next_index(&self) -> usize {
-        if self.img_list.is_empty() {
-            tracing::warn!("no images configured for {}", self.output_name);
-            return 0;
-        }
-        let mut index = self.index;
+    /// The next `limit` images, at most one cycle of the rotation after the queued ones
+    pub fn upcoming(&self, limit: usize, quarantine: &Quarantine) -> Vec<UpcomingImage> {
+        self.rotation
+            .upcoming(&self.output_name, self.first_configure, limit, quarantine)
+    }
+
+    /// Shuffle the images not shown yet in this cycle of the rotation
+    pub fn shuffle_remaining(&mut self) {
+        self.rotation.shuffle_remaining(self.first_configure);
+    }
 
-        // the first render should use the first entry
-        if self.first_configure {
-            return 0;
+    /// Gives the current image, if any
+    pub fn current_img(&self) -> Option<PathBuf> {
+        if self.fallback {
+            return None;
         }
+        self.rotation.current_img()
+    }
+
+    /// Start the animation and the motion of the image now shown, keeping the ones of the image
+    /// before for the transition out of it
+    pub fn start_animation(
+        &mut self,
+        animations: &HashMap<PathBuf, Arc<[Duration]>>,
+        config: &Config,
+    ) {
+        self.previous_animation = self.animation.take();
+        self.animation = self.current_img().and_then(|image| {
+            if video::is_video(&image) {
+                return Some(AnimationClock::video(image));
+            }
+            let delays = animations.get(&image)?.clone();
+            Some(AnimationClock::new(image, delays))
+        });
 
-        // compute the next index
-        match index.cmp(&(self.img_list.len() - 1)) {
-            std::cmp::Ordering::Less => index += 1,
-            std::cmp::Ordering::Equal => index = 0,
-            std::cmp::Ordering::Greater => {
-                panic!("index cannot be greated than the reference buffer")
+        self.previous_motion = self.motion.take();
+        self.motion = self.current_img().and_then(|image| {
+            let output_config = self.output_config.as_ref()?;
+            let motion = output_config.motion?;
+            if Fill::is_fill(&image) || output_config.shader_content().is_some() {
+                return None;
             }
+            // it moves from the start of the transition to it until the end of the one out of it
+            let transition = self
+                .transition_config(&image, config)
+                .map_or(Duration::ZERO, |transition| {
+                    Duration::from_millis(transition.duration_ms as u64)
+                });
+            let duration = self.duration(Some(&image)) + transition;
+            let focus = self.placement(&image).focus;
+            Some(KenBurns::new(
+                image,
+                motion.amplitude,
+                focus,
+                duration,
+                motion.easing,
+                &mut rand::rng(),
+            ))
+        });
+    }
+
+    /// Move the animations on, or stop them where they are while hidden. Returns true if the
+    /// frame of the image shown changed.
+    pub fn tick_animations(&mut self, now: std::time::Instant) -> bool {
+        let motions = [&mut self.motion, &mut self.previous_motion]
+            .into_iter()
+            .flatten()
+            .map(|motion| &mut motion.clock);
+        if !self.visible {
+            self.animation.iter_mut().for_each(AnimationClock::pause);
+            self.previous_animation
+                .iter_mut()
+                .for_each(AnimationClock::pause);
+            motions.for_each(AnimationClock::pause);
+            return false;
+        }
+        for clock in motions {
+            clock.tick(now);
         }
-        index
+        if let Some(previous) = self.previous_animation.as_mut() {
+            previous.tick(now);
+        }
+        self.animation.as_mut().is_some_and(|clock| clock.tick(now))
     }
 
-    /// Gives the current image, if any
-    pub fn current_img(&self) -> Option<PathBuf> {
-        self.img_list.get(self.index).cloned()
+    /// Whether a frame of the motion of the image shown is due, at most at its fps
+    pub fn motion_due(&self, now: std::time::Instant) -> bool {
+        let Some(fps) = self
+            .output_config
+            .as_ref()
+            .and_then(|output_config| Some(output_config.motion?.fps))
+        else {
+            return false;
+        };
+        let frame_time = Duration::from_secs_f64(1.0 / fps.max(1) as f64);
+        self.visible
+            && self.motion.as_ref().is_some_and(|motion| !motion.is_done())
+            && now.saturating_duration_since(self.last_render_instant) >= frame_time
     }
 
-    /// Get the previous image for transitions.
-    /// Returns None on first render (based on self.first_configure).
-    /// Otherwise returns the image at the previous index (with wrapping).
-    pub fn previous_img(&self) -> Option<PathBuf> {
-        if self.first_configure {
-            return None;
+    /// The rect to draw an image in, moved by its motion
+    pub fn rect(&self, image: &Path) -> Rect {
+        let output_size = self.dimensions.unwrap_or((0, 0));
+        [&self.motion, &self.previous_motion]
+            .into_iter()
+            .flatten()
+            .find(|motion| motion.clock.image == image)
+            .map_or(
+                Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: output_size.0 as f32,
+                    height: output_size.1 as f32,
+                },
+                |motion| motion.rect(output_size),
+            )
+    }
+
+    /// The frame of an image to show, 0 for still images
+    pub fn frame(&self, image: &Path) -> usize {
+        self.clock(image).map_or(0, |clock| clock.frame)
+    }
+
+    /// How far a video has played
+    pub fn position(&self, image: &Path) -> Duration {
+        self.clock(image)
+            .map_or(Duration::ZERO, |clock| clock.position)
+    }
+
+    /// Move the shader on, at most at its fps, or stop it where it is while hidden. Returns true
+    /// if a frame of it is due.
+    pub fn tick_shader(&mut self, now: std::time::Instant) -> bool {
+        let Some(shader) = self
+            .output_config
+            .as_ref()
+            .and_then(OutputConfig::shader_content)
+        else {
+            return false;
+        };
+        let frame_time = Duration::from_secs_f64(1.0 / shader.fps.max(1) as f64);
+        let clock = self
+            .shader_clock
+            .get_or_insert_with(|| AnimationClock::video(shader.shader.clone()));
+        if !self.visible {
+            clock.pause();
+            return false;
         }
+        if now.saturating_duration_since(self.last_render_instant) < frame_time {
+            return false;
+        }
+        clock.tick(now);
+        self.last_render_instant = now;
+        true
+    }
 
-        let prev_index = if self.index == 0 {
-            // Wrap around: if currently at 0, previous is last image
-            self.img_list.len() - 1
-        } else {
-            self.index - 1
+    fn clock(&self, image: &Path) -> Option<&AnimationClock> {
+        [&self.animation, &self.previous_animation]
+            .into_iter()
+            .flatten()
+            .find(|clock| clock.image == image)
+    }
+
+    /// How long to show an image
+    pub fn duration(&self, image: Option<&Path>) -> Duration {
+        let seconds = image
+            .and_then(|image| self.playlist_entry(image))
+            .and_then(|entry| entry.duration)
+            .or_else(|| self.output_config.as_ref()?.duration)
+            .unwrap_or(60);
+        Duration::from_secs(seconds)
+    }
+
+    /// The playlist entry of an image, the one of its listing in the rotation when it is listed
+    /// more than once
+    fn playlist_entry(&self, image: &Path) -> Option<&PlaylistEntry> {
+        let entries = self.playlist.get(image)?;
+        entries
+            .get(self.rotation.occurrence(image))
+            .or(entries.first())
+    }
+
+    pub fn fit(&self, image: &Path) -> FitMode {
+        self.playlist_entry(image)
+            .and_then(|entry| entry.fit)
+            .or_else(|| self.sidecars.get(image)?.fit)
+            .or_else(|| Some(self.output_config.as_ref()?.fit))
+            .unwrap_or_default()
+    }
+
+    /// Where an image sits on the output on top of its fit, from its sidecar or the output
+    pub fn placement(&self, image: &Path) -> Placement {
+        let sidecar = self.sidecars.get(image);
+        let output_config = self.output_config.as_ref();
+        Placement {
+            align: sidecar
+                .and_then(|sidecar| sidecar.align)
+                .or_else(|| Some(output_config?.align))
+                .unwrap_or_default()
+                .fractions(),
+            focus: sidecar
+                .and_then(|sidecar| sidecar.focus)
+                .map(|focus| focus.map(|f| f.clamp(0.0, 1.0))),
+            zoom: sidecar
+                .and_then(|sidecar| sidecar.zoom)
+                .or_else(|| output_config?.zoom)
+                .unwrap_or(1.0)
+                .clamp(MIN_ZOOM, MAX_ZOOM),
+            rotation: sidecar.map_or(0, |sidecar| sidecar.rotation.quarter_turns()),
+        }
+    }
+
+    /// What is shown around an image, from its playlist entry or the output
+    fn background_config(&self, image: &Path) -> Option<&Background> {
+        self.playlist_entry(image)
+            .and_then(|entry| entry.background.as_ref())
+            .or_else(|| self.output_config.as_ref()?.background.as_ref())
+    }
+
+    /// The color around an image that doesn't cover the output, black behind anything else
+    pub fn background(&self, image: &Path) -> [f32; 4] {
+        let color = match self.background_config(image) {
+            Some(Background::Fill(Fill::Color(color))) => *color,
+            _ => Default::default(),
         };
+        let [r, g, b] = color.to_linear();
+        [r, g, b, 1.0]
+    }
 
-        self.img_list.get(prev_index).cloned()
+    /// What is drawn around an image that doesn't cover the output instead of a flat color,
+    /// None if the image is fit to cover it anyway
+    pub fn backdrop(&self, image: &Path) -> Option<Background> {
+        let covered = match self.fit(image) {
+            FitMode::Tile => true,
+            FitMode::Stretch | FitMode::Cover => self.placement(image).zoom >= 1.0,
+            FitMode::Contain | FitMode::Center => false,
+        };
+        if covered {
+            return None;
+        }
+        self.background_config(image)
+            .filter(|background| !matches!(background, Background::Fill(Fill::Color(_))))
+            .cloned()
+    }
+
+    /// The transition to an image, None to show it right away
+    pub fn transition_config(&self, image: &Path, config: &Config) -> Option<TransitionConfig> {
+        let output_config = self.output_config.as_ref()?;
+        // a shader picks the new image up on its own
+        if output_config.shader_content().is_some() {
+            return None;
+        }
+        match self.playlist_entry(image) {
+            Some(entry) => entry.get_transition_config(output_config, config),
+            None => output_config
+                .get_transition_config(config)
+                .filter(|_| output_config.is_transitions_enabled(config))
+                .cloned(),
+        }
+    }
+
+    /// A scene drawing the shader of the output with `image` bound to it, None if the output
+    /// shows its images itself
+    pub fn shader_scene(&self, image: Option<PathBuf>) -> Option<Scene> {
+        let shader = self.output_config.as_ref()?.shader_content()?;
+        let palette = self.palette.as_ref().map_or([[0.0; 4]; 4], |palette| {
+            palette.roles().map(|(_, color)| {
+                let [r, g, b] = color.to_linear();
+                [r, g, b, 1.0]
+            })
+        });
+        Some(Scene {
+            background: [0.0, 0.0, 0.0, 1.0],
+            nodes: vec![SceneNode::Shader(ShaderNode {
+                shader_path: shader.shader.clone(),
+                time: self
+                    .shader_clock
+                    .as_ref()
+                    .map_or(Duration::ZERO, |clock| clock.position),
+                image,
+                palette,
+            })],
+        })
+    }
+
+    /// A scene showing `image` without any transition
+    pub fn static_scene(&self, image: PathBuf) -> Scene {
+        if let Some(scene) = self.shader_scene(Some(image.clone())) {
+            return scene;
+        }
+        // a flat color is only a clear
+        if let Some(Fill::Color(color)) = Fill::from_path(&image) {
+            let [r, g, b] = color.to_linear();
+            return Scene {
+                background: [r, g, b, 1.0],
+                nodes: vec![],
+            };
+        }
+        let output_size = self.dimensions.unwrap_or((0, 0));
+        Scene {
+            background: self.background(&image),
+            nodes: vec![SceneNode::Image(
+                ImageNode::fullscreen(image.clone(), output_size, self.fit(&image))
+                    .with_frame(self.frame(&image))
+                    .with_position(self.position(&image))
+                    .with_backdrop(self.backdrop(&image))
+                    .with_placement(self.placement(&image))
+                    .with_rect(self.rect(&image)),
+            )],
+        }
+    }
+
+    /// A scene showing the fallback of the output
+    pub fn fallback_scene(&self, fallback: Fallback, quarantine: &Quarantine) -> Scene {
+        if let Some(scene) = self.shader_scene(None) {
+            return scene;
+        }
+        match fallback {
+            Fallback::Image(image)
+                if archive::exists(&image) && !quarantine.skips(&image, &self.output_name) =>
+            {
+                self.static_scene(image)
+            }
+            Fallback::Image(image) => {
+                tracing::warn!("fallback image {} can't be shown", image.display());
+                Scene {
+                    background: [0.0, 0.0, 0.0, 1.0],
+                    nodes: vec![],
+                }
+            }
+            Fallback::Color(color) => {
+                let [r, g, b] = color.to_linear();
+                Scene {
+                    background: [r, g, b, 1.0],
+                    nodes: vec![],
+                }
+            }
+        }
     }
 
     /// Toggle the visibility state
@@ -143,6 +502,9 @@ pub struct TransitionData {
     pub target_fps: u16,
     pub last_frame_time: std::time::Instant,
     pub direction: [f32; 2],
+    /// The shader of the transition, None for the ones moving whole images
+    pub effect: Option<TransitionEffect>,
+    pub easing: Easing,
 }
 
 impl TransitionData {
@@ -153,6 +515,8 @@ impl TransitionData {
         duration_ms: u32,
         target_fps: u16,
         direction: [f32; 2],
+        effect: Option<TransitionEffect>,
+        easing: Easing,
     ) -> Self {
         Self {
             transition_type,
@@ -161,6 +525,8 @@ impl TransitionData {
             target_fps,
             last_frame_time: std::time::Instant::now(),
             direction,
+            effect,
+            easing,
         }
     }
 
@@ -181,14 +547,9 @@ impl TransitionData {
         (elapsed / duration).min(1.0) // Clamp to 1.0
     }
 
-    /// Calculate eased progress for smoother animations (ease in-out cubic)
+    /// Calculate eased progress for smoother animations
     pub fn eased_progress(&self) -> f32 {
-        let t = self.progress();
-        if t < 0.5 {
-            4.0 * t * t * t
-        } else {
-            1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
-        }
+        self.easing.apply(self.progress())
     }
 
     /// Check if animation is complete
@@ -201,8 +562,7 @@ impl TransitionData {
 
     /// Check if we should render a new frame based on target FPS
     pub fn should_render_frame(&mut self) -> bool {
-        let target_frame_time =
-            std::time::Duration::from_secs_f64(1.0 / self.target_fps as f64);
+        let target_frame_time = std::time::Duration::from_secs_f64(1.0 / self.target_fps as f64);
         let now = std::time::Instant::now();
 
         if now.duration_since(self.last_frame_time) >= target_frame_time {
@@ -0,0 +1,22 @@
+//! Commands started in a process group of their own. Hooks and command sources are often
+//! shell pipelines, and killing only the shell would leave the rest of the pipeline running.
+
+use std::{
+    io,
+    os::unix::process::CommandExt,
+    process::{Child, Command},
+};
+
+/// Start `command` as the leader of a new process group
+pub fn spawn(command: &mut Command) -> io::Result<Child> {
+    command.process_group(0).spawn()
+}
+
+/// Kill every process in the group led by `pid`
+pub fn kill(pid: u32) -> io::Result<()> {
+    // a negative pid signals the whole group
+    match unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
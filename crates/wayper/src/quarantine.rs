@@ -0,0 +1,138 @@
+//! Images that failed to load during this session.
+//!
+//! A failed image is skipped in the rotation. It gets one more try on the next cycle of an output,
+//! in case it was still being written, and is skipped until restart if that fails too. An image
+//! that loads again, or changes on disk, is released.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use wayper_lib::socket::Problem;
+
+#[derive(Debug, Clone)]
+struct QuarantinedImage {
+    error: String,
+    failures: u32,
+    /// Outputs whose rotation wrapped around since the failure, they try it again when its turn
+    /// comes
+    due: BTreeSet<String>,
+    /// The retry failed too, skipped until restart
+    done: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Quarantine {
+    images: BTreeMap<PathBuf, QuarantinedImage>,
+}
+
+impl Quarantine {
+    /// Record a failed load. Returns true if the image wasn't quarantined yet.
+    pub fn record(&mut self, image: &Path, error: String) -> bool {
+        match self.images.get_mut(image) {
+            Some(quarantined) => {
+                // failures while waiting are the same attempt seen again
+                if !quarantined.done && !quarantined.due.is_empty() {
+                    quarantined.failures += 1;
+                    quarantined.done = true;
+                    quarantined.due.clear();
+                }
+                quarantined.error = error;
+                false
+            }
+            None => {
+                self.images.insert(
+                    image.to_path_buf(),
+                    QuarantinedImage {
+                        error,
+                        failures: 1,
+                        due: BTreeSet::new(),
+                        done: false,
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// Release an image, because it loaded or changed. Returns true if it was quarantined.
+    pub fn release(&mut self, image: &Path) -> bool {
+        self.images.remove(image).is_some()
+    }
+
+    pub fn images(&self) -> impl Iterator<Item = &Path> {
+        self.images.keys().map(PathBuf::as_path)
+    }
+
+    /// The rotation of an output wrapped around, images waiting for their retry get it there
+    pub fn new_cycle(&mut self, output_name: &str) {
+        for quarantined in self.images.values_mut() {
+            if !quarantined.done {
+                quarantined.due.insert(output_name.to_string());
+            }
+        }
+    }
+
+    /// The image should be skipped in the rotation of an output
+    pub fn skips(&self, image: &Path, output_name: &str) -> bool {
+        self.images
+            .get(image)
+            .is_some_and(|quarantined| quarantined.done || !quarantined.due.contains(output_name))
+    }
+
+    pub fn problems(&self) -> Vec<Problem> {
+        self.images
+            .iter()
+            .map(|(image, quarantined)| Problem::Quarantined {
+                image: image.clone(),
+                error: quarantined.error.clone(),
+                failures: quarantined.failures,
+                retry: !quarantined.done,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_once() {
+        let image = Path::new("broken.png");
+        let mut quarantine = Quarantine::default();
+        assert!(!quarantine.skips(image, "eDP-1"));
+
+        assert!(quarantine.record(image, "bad header".to_string()));
+        assert!(quarantine.skips(image, "eDP-1"));
+        // the same attempt reported twice
+        assert!(!quarantine.record(image, "bad header".to_string()));
+
+        // only the output that wrapped around tries it again
+        quarantine.new_cycle("eDP-1");
+        assert!(!quarantine.skips(image, "eDP-1"));
+        assert!(quarantine.skips(image, "HDMI-A-1"));
+        quarantine.record(image, "still bad".to_string());
+        assert!(quarantine.skips(image, "eDP-1"));
+
+        // no more retries
+        quarantine.new_cycle("eDP-1");
+        quarantine.new_cycle("HDMI-A-1");
+        assert!(quarantine.skips(image, "eDP-1"));
+        assert!(quarantine.skips(image, "HDMI-A-1"));
+        assert_eq!(
+            quarantine.problems(),
+            vec![Problem::Quarantined {
+                image: image.to_path_buf(),
+                error: "still bad".to_string(),
+                failures: 2,
+                retry: false,
+            }]
+        );
+
+        assert!(quarantine.release(image));
+        assert!(!quarantine.skips(image, "eDP-1"));
+        assert!(quarantine.problems().is_empty());
+    }
+}
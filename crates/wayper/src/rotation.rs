@@ -0,0 +1,379 @@
+//! What an output shows and in which order: its image list, the one-off images shown instead of
+//! it, and the queue. Kept apart from the Wayland side of an output.
+
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+};
+
+use rand::seq::SliceRandom;
+use wayper_lib::{fill::Fill, socket::UpcomingImage};
+
+use crate::{archive, quarantine::Quarantine, state::RotationState};
+
+/// Whether an image can be shown as far as the disk goes: a fill, or a file that exists
+pub fn can_show(image: &Path) -> bool {
+    Fill::is_fill(image) || archive::exists(image)
+}
+
+#[derive(Debug, Default)]
+pub struct Rotation {
+    pub index: usize,
+    pub img_list: Vec<PathBuf>,
+    /// A one-off image shown instead of `img_list[index]`, cleared on the next advance
+    pub override_img: Option<PathBuf>,
+    /// A one-off image to show on the next advance
+    pub pending_override: Option<PathBuf>,
+    /// Images queued by hand, shown as one-off images before the rotation continues
+    pub queue: VecDeque<PathBuf>,
+    /// The image picked by the source command, shown on the next advance
+    pub source_next: Option<PathBuf>,
+}
+
+impl Rotation {
+    /// Continue from a saved rotation when the daemon starts, showing the image it showed
+    pub fn restored(rotation: RotationState) -> Self {
+        Self {
+            index: rotation.index,
+            img_list: rotation.order,
+            override_img: rotation.override_img,
+            pending_override: None,
+            queue: rotation.queue.into(),
+            source_next: None,
+        }
+    }
+
+    /// Continue from a saved rotation on an output already showing something else. The saved
+    /// one-off image is shown on the next advance, without moving past the saved index.
+    pub fn restore(&mut self, rotation: RotationState) {
+        let mut restored = Self::restored(rotation);
+        restored.pending_override = restored.override_img.take();
+        *self = restored;
+    }
+
+    /// Where the rotation is, to be saved
+    pub fn state(&self) -> RotationState {
+        RotationState {
+            order: self.img_list.clone(),
+            index: self.index,
+            override_img: self.override_img.clone().or(self.pending_override.clone()),
+            queue: self.queue.iter().cloned().collect(),
+        }
+    }
+
+    /// Move on and give the image to show, skipping the images quarantined on the output. When
+    /// `starting`, the image at the current index is shown rather than the one after it. A pending
+    /// override or queued image is shown without touching the index. None if there is no image
+    /// that can be shown.
+    pub fn advance(
+        &mut self,
+        output_name: &str,
+        starting: bool,
+        quarantine: &mut Quarantine,
+    ) -> Option<PathBuf> {
+        let pending_override = self
+            .pending_override
+            .take()
+            .or_else(|| {
+                std::iter::from_fn(|| self.queue.pop_front())
+                    .find(|image| Self::can_queue(image, output_name, quarantine))
+            })
+            .or_else(|| self.source_next.take());
+        if let Some(pending_override) = pending_override {
+            tracing::debug!("showing override {}", pending_override.display());
+            self.override_img = Some(pending_override.clone());
+            return Some(pending_override);
+        }
+        self.override_img = None;
+
+        tracing::debug!("Current index is {}", self.index);
+        let len = self.img_list.len();
+        let mut previous = self.index;
+        let mut index = self.next_index(starting);
+        let mut starting = starting;
+        for _ in 0..len {
+            if index <= previous && !starting {
+                quarantine.new_cycle(output_name);
+            }
+            starting = false;
+            if quarantine.skips(&self.img_list[index], output_name) {
+                tracing::debug!("skipping quarantined {}", self.img_list[index].display());
+                previous = index;
+                index = (index + 1) % len;
+                continue;
+            }
+
+            tracing::debug!("new index is {}", index);
+            self.index = index;
+            return Some(self.img_list[index].clone());
+        }
+        None
+    }
+
+    /// The image shown, if any
+    pub fn current_img(&self) -> Option<PathBuf> {
+        self.override_img
+            .clone()
+            .or_else(|| self.img_list.get(self.index).cloned())
+    }
+
+    /// The next `limit` images, at most one cycle of the rotation after the queued ones
+    pub fn upcoming(
+        &self,
+        output_name: &str,
+        starting: bool,
+        limit: usize,
+        quarantine: &Quarantine,
+    ) -> Vec<UpcomingImage> {
+        let queued = self
+            .pending_override
+            .iter()
+            .chain(
+                self.queue
+                    .iter()
+                    .filter(|image| Self::can_queue(image, output_name, quarantine)),
+            )
+            .map(|image| UpcomingImage {
+                image: image.clone(),
+                queued: true,
+            });
+        let picked = self.source_next.iter().map(|image| UpcomingImage {
+            image: image.clone(),
+            queued: false,
+        });
+        let len = self.img_list.len();
+        let rotation = (0..len)
+            .map(|offset| &self.img_list[(self.next_index(starting) + offset) % len])
+            .filter(|image| !quarantine.skips(image, output_name))
+            .map(|image| UpcomingImage {
+                image: image.clone(),
+                queued: false,
+            });
+        queued.chain(picked).chain(rotation).take(limit).collect()
+    }
+
+    /// Which listing of an image in the image list is meant, counting from 0: the one at the
+    /// index or next to it, where the images shown and about to be shown are
+    pub fn occurrence(&self, image: &Path) -> usize {
+        let len = self.img_list.len();
+        [
+            self.index,
+            self.index + 1,
+            self.index + len.saturating_sub(1),
+        ]
+        .into_iter()
+        .map(|index| index % len.max(1))
+        .find(|index| {
+            self.img_list
+                .get(*index)
+                .is_some_and(|listed| listed == image)
+        })
+        .map_or(0, |index| {
+            self.img_list[..index]
+                .iter()
+                .filter(|listed| *listed == image)
+                .count()
+        })
+    }
+
+    /// Queued images that were removed or failed to load are dropped when their turn comes
+    fn can_queue(image: &Path, output_name: &str, quarantine: &Quarantine) -> bool {
+        can_show(image) && !quarantine.skips(image, output_name)
+    }
+
+    /// Shuffle the images not shown yet in this cycle of the rotation
+    pub fn shuffle_remaining(&mut self, starting: bool) {
+        let start = match starting {
+            true => self.index,
+            false => self.index + 1,
+        };
+        if let Some(remaining) = self.img_list.get_mut(start..) {
+            remaining.shuffle(&mut rand::rng());
+        }
+    }
+
+    /// Get the next index, but does not modify the original index. Accounts
+    /// for the length of the image vec
+    fn next_index(&self, starting: bool) -> usize {
+        if self.img_list.is_empty() {
+            return 0;
+        }
+        let mut index = self.index;
+
+        // the first render should use the current entry, which may have been restored
+        if starting {
+            return self.index.min(self.img_list.len() - 1);
+        }
+
+        // compute the next index
+        match index.cmp(&(self.img_list.len() - 1)) {
+            std::cmp::Ordering::Less => index += 1,
+            std::cmp::Ordering::Equal => index = 0,
+            std::cmp::Ordering::Greater => {
+                panic!("index cannot be greated than the reference buffer")
+            }
+        }
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved(override_img: Option<&str>) -> RotationState {
+        RotationState {
+            order: vec!["a".into(), "b".into(), "c".into()],
+            index: 1,
+            override_img: override_img.map(PathBuf::from),
+            queue: vec![],
+        }
+    }
+
+    #[test]
+    fn test_restored_override_is_shown() {
+        let mut quarantine = Quarantine::default();
+
+        // on startup the one-off image is shown first, the rotation continues after it
+        let rotation = Rotation::restored(saved(Some("fill:#1e1e2e")));
+        assert_eq!(rotation.current_img(), Some("fill:#1e1e2e".into()));
+        assert_eq!(rotation.state(), saved(Some("fill:#1e1e2e")));
+
+        // on a running output it is shown by the advance the restore triggers
+        let mut rotation = Rotation::default();
+        rotation.restore(saved(Some("fill:#1e1e2e")));
+        assert_eq!(rotation.state(), saved(Some("fill:#1e1e2e")));
+        assert_eq!(
+            rotation.advance("eDP-1", false, &mut quarantine),
+            Some("fill:#1e1e2e".into())
+        );
+        assert_eq!(rotation.index, 1);
+        assert_eq!(
+            rotation.advance("eDP-1", false, &mut quarantine),
+            Some("c".into())
+        );
+        assert_eq!(
+            rotation.state(),
+            RotationState {
+                index: 2,
+                ..saved(None)
+            }
+        );
+    }
+
+    #[test]
+    fn test_occurrence() {
+        let mut rotation = Rotation::restored(RotationState {
+            order: vec![
+                "logo".into(),
+                "a".into(),
+                "logo".into(),
+                "b".into(),
+                "c".into(),
+            ],
+            index: 0,
+            ..Default::default()
+        });
+        let logo = Path::new("logo");
+        assert_eq!(rotation.occurrence(logo), 0);
+        rotation.index = 1;
+        // the next image
+        assert_eq!(rotation.occurrence(logo), 1);
+        rotation.index = 2;
+        assert_eq!(rotation.occurrence(logo), 1);
+        // the previous image
+        rotation.index = 3;
+        assert_eq!(rotation.occurrence(logo), 1);
+        assert_eq!(rotation.occurrence(Path::new("b")), 0);
+        assert_eq!(Rotation::default().occurrence(logo), 0);
+    }
+
+    fn images(rotation: &[UpcomingImage]) -> Vec<(&str, bool)> {
+        rotation
+            .iter()
+            .map(|upcoming| (upcoming.image.to_str().unwrap(), upcoming.queued))
+            .collect()
+    }
+
+    #[test]
+    fn test_queue_before_rotation() {
+        let mut quarantine = Quarantine::default();
+        let image = std::env::temp_dir().join(format!("wayper-queue-{}.png", std::process::id()));
+        std::fs::write(&image, "").unwrap();
+        let broken = image.with_extension("jpg");
+        std::fs::write(&broken, "").unwrap();
+        quarantine.record(&broken, "invalid image".to_string());
+
+        let mut rotation = Rotation::restored(saved(None));
+        rotation.queue = VecDeque::from([
+            "/does/not/exist.png".into(),
+            broken.clone(),
+            "fill:#1e1e2e".into(),
+            image.clone(),
+        ]);
+        rotation.pending_override = Some(image.clone());
+
+        // the override, then the queue without missing or quarantined images, then the
+        // rotation after the current image
+        let queued = image.to_str().unwrap();
+        assert_eq!(
+            images(&rotation.upcoming("eDP-1", false, 6, &quarantine)),
+            vec![
+                (queued, true),
+                ("fill:#1e1e2e", true),
+                (queued, true),
+                ("c", false),
+                ("a", false),
+                ("b", false),
+            ]
+        );
+
+        let mut shown = vec![];
+        for _ in 0..4 {
+            shown.push(rotation.advance("eDP-1", false, &mut quarantine).unwrap());
+        }
+        assert_eq!(
+            shown,
+            vec![
+                image.clone(),
+                "fill:#1e1e2e".into(),
+                image.clone(),
+                PathBuf::from("c")
+            ]
+        );
+        assert!(rotation.queue.is_empty());
+        assert_eq!(rotation.override_img, None);
+        assert_eq!(rotation.index, 2);
+
+        std::fs::remove_file(&image).unwrap();
+        std::fs::remove_file(&broken).unwrap();
+    }
+
+    #[test]
+    fn test_shuffle_only_unshown() {
+        let order = (0..50)
+            .map(|i| PathBuf::from(i.to_string()))
+            .collect::<Vec<_>>();
+        let mut rotation = Rotation::restored(RotationState {
+            order: order.clone(),
+            index: 10,
+            ..Default::default()
+        });
+
+        rotation.shuffle_remaining(false);
+        assert_eq!(rotation.img_list[..=10], order[..=10]);
+        assert_ne!(rotation.img_list[11..], order[11..]);
+        let mut shuffled = rotation.img_list.clone();
+        shuffled.sort_by_key(|image| image.to_str().unwrap().parse::<u32>().unwrap());
+        assert_eq!(shuffled, order);
+
+        // the image at the index isn't shown yet when starting
+        let mut rotation = Rotation::restored(RotationState {
+            order: order.clone(),
+            index: 10,
+            ..Default::default()
+        });
+        rotation.shuffle_remaining(true);
+        assert_eq!(rotation.img_list[..10], order[..10]);
+    }
+}
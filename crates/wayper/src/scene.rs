@@ -1,8 +1,14 @@
 //! Declaration of scene
 
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
 
-use wayper_lib::config::FitMode;
+use rand::Rng;
+use wayper_lib::{
+    config::{FitMode, TransitionConfig, TransitionTypeEnum},
+    fill::Background,
+};
+
+use crate::wgpu_renderer::WgpuRenderer;
 
 #[derive(Debug, Clone)]
 pub struct Scene {
@@ -13,6 +19,8 @@ pub struct Scene {
 #[derive(Debug, Clone)]
 pub enum SceneNode {
     Image(ImageNode),
+    Shader(ShaderNode),
+    Transition(TransitionNode),
 }
 
 #[derive(Debug, Clone)]
@@ -21,6 +29,13 @@ pub struct ImageNode {
     pub opacity: f32,
     pub rect: Rect,
     pub fit: FitMode,
+    /// Frame of an animated image
+    pub frame: usize,
+    /// Play time of a video
+    pub position: Duration,
+    /// Drawn behind the image, instead of the background of the scene
+    pub backdrop: Option<Background>,
+    pub placement: Placement,
 }
 
 impl ImageNode {
@@ -36,6 +51,10 @@ impl ImageNode {
                 height: output_size.1 as f32,
             },
             fit,
+            frame: 0,
+            position: Duration::ZERO,
+            backdrop: None,
+            placement: Placement::default(),
         }
     }
 
@@ -45,6 +64,30 @@ impl ImageNode {
         self
     }
 
+    /// frame setter, wraps around the frames of the image
+    pub fn with_frame(mut self, frame: usize) -> Self {
+        self.frame = frame;
+        self
+    }
+
+    /// position setter, how far a video has played
+    pub fn with_position(mut self, position: Duration) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// backdrop setter, a gradient or a background made from the image to show around it
+    pub fn with_backdrop(mut self, backdrop: Option<Background>) -> Self {
+        self.backdrop = backdrop;
+        self
+    }
+
+    /// placement setter, where the image sits in its rect on top of its fit
+    pub fn with_placement(mut self, placement: Placement) -> Self {
+        self.placement = placement;
+        self
+    }
+
     /// rect setter, represents position on the screen
     pub fn with_rect(mut self, rect: Rect) -> Self {
         self.rect = rect;
@@ -52,6 +95,150 @@ impl ImageNode {
     }
 }
 
+/// Where an image sits in its rect on top of its fit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    /// Fractions of the free or cropped space left of and above the image
+    pub align: [f32; 2],
+    /// Point of the image kept in view when it is cropped, in fractions of its size
+    pub focus: Option<[f32; 2]>,
+    pub zoom: f32,
+    /// Clockwise quarter turns of the image
+    pub rotation: u32,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Self {
+            align: [0.5, 0.5],
+            focus: None,
+            zoom: 1.0,
+            rotation: 0,
+        }
+    }
+}
+
+/// A fullscreen shader given by the user
+#[derive(Debug, Clone)]
+pub struct ShaderNode {
+    pub shader_path: PathBuf,
+    /// Play time of the shader
+    pub time: Duration,
+    /// Image of the rotation bound to the shader
+    pub image: Option<PathBuf>,
+    /// Dominant, accent, light and dark colors of the image, linear RGBA
+    pub palette: [[f32; 4]; 4],
+}
+
+/// Two images blended by a transition shader
+#[derive(Debug, Clone)]
+pub struct TransitionNode {
+    /// None on the first image of an output, the background is shown instead
+    pub from: Option<ImageNode>,
+    pub to: ImageNode,
+    pub effect: TransitionEffect,
+    /// Eased progress, 0 shows `from` and 1 shows `to`
+    pub progress: f32,
+}
+
+impl TransitionNode {
+    /// The same transition as a crossfade, for images the shader can't draw
+    pub fn crossfade(self) -> Vec<SceneNode> {
+        let from = self.from.map(|from| from.with_opacity(1.0));
+        let to = self.to.with_opacity(self.progress);
+        from.into_iter().chain([to]).map(SceneNode::Image).collect()
+    }
+}
+
+/// A transition shader with its parameters, resolved once per transition
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransitionEffect {
+    Wipe {
+        direction: [f32; 2],
+        softness: f32,
+    },
+    Circle {
+        center: [f32; 2],
+        softness: f32,
+    },
+    Dissolve {
+        seed: u32,
+        scale: f32,
+        softness: f32,
+    },
+    Zoom {
+        scale: f32,
+    },
+    Blur {
+        radius: f32,
+    },
+    Pixelate {
+        size: f32,
+    },
+    /// A `transition` function given by the user, with the values of its `params`
+    Shader {
+        shader: PathBuf,
+        params: BTreeMap<String, f32>,
+        /// The version of the file and the names of the params, taken when the transition
+        /// starts. The shader is compiled again when it differs.
+        version: String,
+    },
+}
+
+impl TransitionEffect {
+    /// The effect of a transition type, None for the ones moving whole image nodes. Random
+    /// centers and seeds are picked here, so they hold for the whole transition.
+    pub fn new(
+        transition_type: &TransitionTypeEnum,
+        config: &TransitionConfig,
+        rng: &mut impl Rng,
+    ) -> Option<Self> {
+        let effect = match transition_type {
+            TransitionTypeEnum::Crossfade | TransitionTypeEnum::Slide => return None,
+            TransitionTypeEnum::Wipe => TransitionEffect::Wipe {
+                direction: config.wipe.direction.as_vec2(),
+                softness: config.wipe.softness.max(0.0),
+            },
+            TransitionTypeEnum::Circle => TransitionEffect::Circle {
+                center: config
+                    .circle
+                    .center
+                    .unwrap_or_else(|| [rng.random(), rng.random()]),
+                softness: config.circle.softness.max(0.0),
+            },
+            TransitionTypeEnum::Dissolve => TransitionEffect::Dissolve {
+                seed: config.dissolve.seed.unwrap_or_else(|| rng.random()),
+                scale: config.dissolve.scale.max(1.0),
+                softness: config.dissolve.softness.max(0.0),
+            },
+            TransitionTypeEnum::Zoom => TransitionEffect::Zoom {
+                scale: config.zoom.scale.max(1.0),
+            },
+            TransitionTypeEnum::Blur => TransitionEffect::Blur {
+                radius: config.blur.radius.max(0.0),
+            },
+            TransitionTypeEnum::Pixelate => TransitionEffect::Pixelate {
+                size: config.pixelate.size.max(1.0),
+            },
+            TransitionTypeEnum::Shader(shader) => TransitionEffect::Shader {
+                shader: shader.clone(),
+                params: config.params.clone(),
+                version: format!(
+                    "{}[{}]",
+                    WgpuRenderer::cache_key(shader),
+                    config
+                        .params
+                        .keys()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                ),
+            },
+        };
+        Some(effect)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Rect {
     pub x: f32,
@@ -65,3 +252,57 @@ impl Rect {
         [self.x, self.y, self.width, self.height]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transition_effects() {
+        let config = wayper_lib::config::Config::new(
+            r#"
+            [transition]
+            type = "circle"
+            dissolve = { seed = 7 }
+            "#,
+        )
+        .unwrap()
+        .transition
+        .unwrap();
+        let mut rng = rand::rng();
+
+        // nodes are moved for these, there is no shader
+        assert_eq!(
+            TransitionEffect::new(&TransitionTypeEnum::Slide, &config, &mut rng),
+            None
+        );
+        let Some(TransitionEffect::Circle { center, .. }) =
+            TransitionEffect::new(&TransitionTypeEnum::Circle, &config, &mut rng)
+        else {
+            panic!("expected a circle");
+        };
+        assert!(center.iter().all(|c| (0.0..1.0).contains(c)));
+        assert!(matches!(
+            TransitionEffect::new(&TransitionTypeEnum::Dissolve, &config, &mut rng),
+            Some(TransitionEffect::Dissolve { seed: 7, .. })
+        ));
+
+        let image =
+            |path: &str| ImageNode::fullscreen(PathBuf::from(path), (100, 100), FitMode::Cover);
+        let transition = TransitionNode {
+            from: Some(image("a.jpg").with_opacity(0.5)),
+            to: image("b.mp4"),
+            effect: TransitionEffect::Zoom { scale: 1.5 },
+            progress: 0.25,
+        };
+        let opacities = transition
+            .crossfade()
+            .iter()
+            .map(|node| match node {
+                SceneNode::Image(image) => image.opacity,
+                _ => panic!("expected images"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(opacities, [1.0, 0.25]);
+    }
+}
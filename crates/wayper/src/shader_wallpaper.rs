@@ -0,0 +1,389 @@
+//! Shader wallpapers. The user writes a fullscreen `fs_main` fragment shader in WGSL, wayper
+//! appends the bindings and the vertex stage to it and draws it every frame. The file is compiled
+//! again when the directory watcher sees it change, a shader that fails to compile keeps the last
+//! version that did.
+
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{OptionExt, bail};
+use smithay_client_toolkit::reexports::calloop;
+use wgpu::naga;
+
+use crate::{
+    scene::ShaderNode,
+    wgpu_renderer::{RenderEvent, WgpuRenderer},
+};
+
+/// Declarations every shader wallpaper can use
+const PRELUDE: &str = include_str!("../shaders/shader_wallpaper.wgsl");
+
+/// The `Wayper` struct of the prelude
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShaderUniforms {
+    resolution: [f32; 2],
+    time: f32,
+    output_hash: u32,
+    palette: [[f32; 4]; 4],
+    has_image: u32,
+    _padding: [u32; 3],
+}
+
+/// A compiled shader, with the version of the file it was compiled from
+struct Program {
+    version: String,
+    pipeline: wgpu::RenderPipeline,
+}
+
+/// Uniforms of an output, and the bind group with the image it was made for
+struct Binding {
+    buffer: wgpu::Buffer,
+    bind_group: Option<(String, wgpu::BindGroup)>,
+}
+
+/// The shaders drawn on the outputs
+pub struct ShaderWallpapers {
+    programs: HashMap<PathBuf, Program>,
+    /// The version of shader files that failed to compile, tried again once the file changes
+    failed: HashMap<PathBuf, String>,
+    /// Shader files changed on disk since they were compiled
+    changed: HashSet<PathBuf>,
+    bindings: HashMap<String, Binding>,
+    bind_group_layout: Option<wgpu::BindGroupLayout>,
+    /// Bound to shaders without an image
+    placeholder: Option<wgpu::Texture>,
+    event_tx: calloop::channel::Sender<RenderEvent>,
+}
+
+impl ShaderWallpapers {
+    pub fn new(event_tx: calloop::channel::Sender<RenderEvent>) -> Self {
+        Self {
+            programs: HashMap::new(),
+            failed: HashMap::new(),
+            changed: HashSet::new(),
+            bindings: HashMap::new(),
+            bind_group_layout: None,
+            placeholder: None,
+            event_tx,
+        }
+    }
+
+    fn init(&mut self, device: &wgpu::Device) {
+        self.bind_group_layout = Some(device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Shader Wallpaper Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        ));
+        self.placeholder = Some(device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("shader wallpaper placeholder texture"),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        }));
+    }
+
+    /// Compile the shader of a node the first time it is drawn or after its file changed, and
+    /// write its uniforms for an output.
+    /// `image` is the texture of the image of the node, with the key it was loaded with.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface_format: wgpu::TextureFormat,
+        output_name: &str,
+        node: &ShaderNode,
+        resolution: (u32, u32),
+        image: Option<(&str, &wgpu::Texture)>,
+        sampler: &wgpu::Sampler,
+    ) -> color_eyre::Result<()> {
+        if self.bind_group_layout.is_none() {
+            self.init(device);
+        }
+        let shader = &node.shader_path;
+        let known = self.programs.contains_key(shader) || self.failed.contains_key(shader);
+        if !known || self.changed.remove(shader) {
+            self.compile(device, surface_format, shader);
+        }
+        if !self.programs.contains_key(shader) {
+            bail!("shader {} never compiled", shader.display());
+        }
+        let layout = self
+            .bind_group_layout
+            .as_ref()
+            .ok_or_eyre("shader bind group layout not initialized")?;
+
+        let mut hasher = DefaultHasher::new();
+        output_name.hash(&mut hasher);
+        let uniforms = ShaderUniforms {
+            resolution: [resolution.0 as f32, resolution.1 as f32],
+            time: node.time.as_secs_f32(),
+            output_hash: hasher.finish() as u32,
+            palette: node.palette,
+            has_image: image.is_some() as u32,
+            _padding: [0; 3],
+        };
+        let binding = self
+            .bindings
+            .entry(output_name.to_string())
+            .or_insert_with(|| Binding {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("shader wallpaper uniforms"),
+                    size: std::mem::size_of::<ShaderUniforms>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                bind_group: None,
+            });
+        queue.write_buffer(&binding.buffer, 0, bytemuck::bytes_of(&uniforms));
+
+        let image_key = image.map_or("", |(key, _)| key);
+        if binding
+            .bind_group
+            .as_ref()
+            .is_none_or(|(key, _)| key != image_key)
+        {
+            let texture = match image {
+                Some((_, texture)) => texture,
+                None => self
+                    .placeholder
+                    .as_ref()
+                    .ok_or_eyre("shader placeholder texture not initialized")?,
+            };
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("shader wallpaper bg"),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: binding.buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    },
+                ],
+            });
+            binding.bind_group = Some((image_key.to_string(), bind_group));
+        }
+        Ok(())
+    }
+
+    /// Compile a shader unless this version of its file was compiled or failed already
+    fn compile(
+        &mut self,
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        shader: &Path,
+    ) {
+        let Some(layout) = self.bind_group_layout.as_ref() else {
+            return;
+        };
+        let version = WgpuRenderer::cache_key(shader);
+        if self
+            .programs
+            .get(shader)
+            .is_some_and(|program| program.version == version)
+            || self.failed.get(shader) == Some(&version)
+        {
+            return;
+        }
+        let event = match compile(device, layout, surface_format, shader) {
+            Ok(pipeline) => {
+                tracing::info!("compiled shader {}", shader.display());
+                self.programs
+                    .insert(shader.to_path_buf(), Program { version, pipeline });
+                self.failed.remove(shader);
+                RenderEvent::ShaderLoaded {
+                    shader: shader.to_path_buf(),
+                }
+            }
+            Err(error) => {
+                tracing::warn!("shader {} failed: {error}", shader.display());
+                self.failed.insert(shader.to_path_buf(), version);
+                RenderEvent::ShaderFailed {
+                    shader: shader.to_path_buf(),
+                    error,
+                }
+            }
+        };
+        if let Err(e) = self.event_tx.send(event) {
+            tracing::error!("unable to send render event: {e}");
+        }
+    }
+
+    /// A shader file changed on disk, it is compiled again the next time it is drawn
+    pub fn reload(&mut self, shader: &Path) {
+        if self.programs.contains_key(shader) || self.failed.contains_key(shader) {
+            self.changed.insert(shader.to_path_buf());
+        }
+    }
+
+    pub fn pipeline(&self, shader: &Path) -> Option<&wgpu::RenderPipeline> {
+        self.programs.get(shader).map(|program| &program.pipeline)
+    }
+
+    pub fn bind_group(&self, output_name: &str) -> Option<&wgpu::BindGroup> {
+        self.bindings
+            .get(output_name)
+            .and_then(|binding| binding.bind_group.as_ref())
+            .map(|(_, bind_group)| bind_group)
+    }
+
+    /// Drop the uniforms of an output that doesn't draw a shader anymore
+    pub fn forget(&mut self, output_name: &str) {
+        self.bindings.remove(output_name);
+    }
+}
+
+/// The WGSL of a shader wallpaper with the prelude appended
+fn with_prelude(source: &str) -> String {
+    format!("{source}\n{PRELUDE}")
+}
+
+/// Check a shader with naga, so errors point at lines of the file the user wrote
+pub fn validate(source: &str, path: &Path) -> Result<(), String> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| e.emit_to_string_with_path(source, path))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::default(),
+    )
+    .validate(&module)
+    .map_err(|e| e.emit_to_string_with_path(source, &path.display().to_string()))?;
+
+    let has_fs_main = module
+        .entry_points
+        .iter()
+        .any(|entry| entry.name == "fs_main" && entry.stage == naga::ShaderStage::Fragment);
+    match has_fs_main {
+        true => Ok(()),
+        false => Err(format!(
+            "{}: no `@fragment fn fs_main` to draw with",
+            path.display()
+        )),
+    }
+}
+
+fn compile(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    surface_format: wgpu::TextureFormat,
+    path: &Path,
+) -> Result<wgpu::RenderPipeline, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let source = with_prelude(&source);
+    validate(&source, path).map_err(|e| e.trim_end().to_string())?;
+
+    // whatever naga let through is reported by wgpu instead of panicking the renderer
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Shader Wallpaper"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Shader Wallpaper Pipeline Layout"),
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Shader Wallpaper Render Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("wayper_vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    });
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(error.to_string()),
+        None => Ok(pipeline),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let path = Path::new("waves.wgsl");
+        let waves = r#"
+@fragment
+fn fs_main(in: WayperInput) -> @location(0) vec4<f32> {
+    let wave = sin(in.uv.x * 10.0 + wayper.time) * 0.5 + 0.5;
+    return mix(wayper.palette[3], wayper.palette[1], wave);
+}
+"#;
+        assert_eq!(validate(&with_prelude(waves), path), Ok(()));
+
+        // errors point at the line of the file
+        let typo = "\n@fragment\nfn fs_main() -> @location(0) vec4<f32> {\n    return vec4<f32>(wayper.tme);\n}\n";
+        let error = validate(&with_prelude(typo), path).unwrap_err();
+        assert!(error.contains("waves.wgsl:4:"), "{error}");
+
+        let no_fs_main = "fn main() {}\n";
+        assert!(validate(&with_prelude(no_fs_main), path).is_err());
+    }
+}
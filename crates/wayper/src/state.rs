@@ -0,0 +1,375 @@
+//! Rotation state that survives daemon restarts, stored as `state.json` in the state directory.
+//!
+//! The state is written on every change. Image lists are reconciled against the files on disk
+//! when restored, so images added or removed while the daemon was down are handled.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use wayper_lib::{config::ImageOrder, paths::write_atomic};
+
+use crate::rotation;
+
+const STATE_FILE: &str = "state.json";
+
+/// Where an output is in its rotation
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RotationState {
+    /// The image list in the order it is shown
+    pub order: Vec<PathBuf>,
+    pub index: usize,
+    /// A one-off image shown instead of the rotation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub override_img: Option<PathBuf>,
+    /// Images queued by hand
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub queue: Vec<PathBuf>,
+}
+
+impl RotationState {
+    /// Bring the rotation in line with `images`, the images currently on disk in the order of
+    /// `order`. Images that are gone are dropped and the index keeps pointing at the same image
+    /// when it still exists. With a shuffled order the saved order is kept and new images are
+    /// shuffled into the part of the rotation that hasn't been shown yet, otherwise `images`
+    /// already is in the right order. An image a playlist lists more than once is counted per
+    /// listing, the index stays on the same listing of it.
+    pub fn reconcile(&self, images: Vec<PathBuf>, order: ImageOrder) -> RotationState {
+        let mut on_disk = HashMap::<&PathBuf, usize>::new();
+        for image in &images {
+            *on_disk.entry(image).or_default() += 1;
+        }
+        let kept = self
+            .order
+            .iter()
+            .map(|image| take(&mut on_disk, image))
+            .collect::<Vec<_>>();
+
+        let index = self.index.min(self.order.len());
+        let current = self.order.get(index);
+        let occurrence = self.order[..index]
+            .iter()
+            .filter(|image| Some(*image) == current)
+            .count();
+        let kept_before = kept[..index].iter().filter(|kept| **kept).count();
+        // when the current image is gone, continue with the first remaining one after it
+        let next = (index..self.order.len()).find(|i| kept[*i]).map(|i| {
+            let image = &self.order[i];
+            let occurrence = self.order[..i]
+                .iter()
+                .zip(&kept)
+                .filter(|(other, kept)| **kept && *other == image)
+                .count();
+            (image, occurrence)
+        });
+
+        let order = match order {
+            ImageOrder::Shuffle => {
+                let added = images
+                    .iter()
+                    .filter(|image| take(&mut on_disk, image))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let mut order = self
+                    .order
+                    .iter()
+                    .zip(&kept)
+                    .filter(|(_, kept)| **kept)
+                    .map(|(image, _)| image.clone())
+                    .collect::<Vec<_>>();
+                let mut rng = rand::rng();
+                for image in added {
+                    // somewhere after the current image
+                    let start = (kept_before + 1).min(order.len());
+                    order.insert(rng.random_range(start..=order.len()), image);
+                }
+                order
+            }
+            _ => images,
+        };
+
+        let position = |image: &PathBuf, occurrence: usize| {
+            order
+                .iter()
+                .enumerate()
+                .filter(|(_, other)| *other == image)
+                .nth(occurrence)
+                .map(|(index, _)| index)
+        };
+        let index = current
+            .and_then(|current| position(current, occurrence))
+            .or_else(|| next.and_then(|(image, occurrence)| position(image, occurrence)))
+            .unwrap_or(0);
+
+        RotationState {
+            order,
+            index,
+            override_img: self
+                .override_img
+                .clone()
+                .filter(|override_img| rotation::can_show(override_img)),
+            queue: self
+                .queue
+                .iter()
+                .filter(|image| rotation::can_show(image))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// Take one listing of `image` from the listings still left, false if none are left
+fn take(listings: &mut HashMap<&PathBuf, usize>, image: &PathBuf) -> bool {
+    match listings.get_mut(image) {
+        Some(count) if *count > 0 => {
+            *count -= 1;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// How many images were added and removed to get from `before` to `after`. A playlist can list
+/// an image more than once, it is counted once.
+pub fn image_changes(before: &[PathBuf], after: &[PathBuf]) -> (usize, usize) {
+    let before = before.iter().collect::<HashSet<_>>();
+    let after = after.iter().collect::<HashSet<_>>();
+    (
+        after.difference(&before).count(),
+        before.difference(&after).count(),
+    )
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct State {
+    active_profile: Option<String>,
+    /// Rotation per profile and output
+    #[serde(default)]
+    profiles: BTreeMap<String, BTreeMap<String, RotationState>>,
+}
+
+/// The state and where it's saved
+#[derive(Debug)]
+pub struct StateStore {
+    path: PathBuf,
+    state: State,
+}
+
+impl StateStore {
+    /// Load the saved state. With `fresh`, the saved state is ignored and will be overwritten.
+    pub fn load(fresh: bool) -> Self {
+        let path = wayper_lib::paths::state_dir().join(STATE_FILE);
+        let state = if fresh {
+            tracing::info!("ignoring saved state");
+            State::default()
+        } else {
+            read_state(&path)
+        };
+        Self { path, state }
+    }
+
+    /// The profile that was active when the daemon stopped
+    pub fn active_profile(&self) -> Option<&str> {
+        self.state.active_profile.as_deref()
+    }
+
+    pub fn set_active_profile(&mut self, profile: &str) {
+        if self.state.active_profile.as_deref() != Some(profile) {
+            self.state.active_profile = Some(profile.to_string());
+            self.save();
+        }
+    }
+
+    /// The saved rotation of an output in a profile, reconciled with `images`. Without a saved
+    /// rotation, `images` is used as is.
+    pub fn restore(
+        &self,
+        profile: &str,
+        output_name: &str,
+        images: Vec<PathBuf>,
+        order: ImageOrder,
+    ) -> RotationState {
+        match self
+            .state
+            .profiles
+            .get(profile)
+            .and_then(|outputs| outputs.get(output_name))
+        {
+            Some(saved) => {
+                let restored = saved.reconcile(images, order);
+                tracing::info!(
+                    "restored rotation of {output_name} in profile \"{profile}\" at index {}",
+                    restored.index
+                );
+                restored
+            }
+            None => RotationState {
+                order: images,
+                index: 0,
+                override_img: None,
+                queue: vec![],
+            },
+        }
+    }
+
+    /// Record the rotation of an output
+    pub fn update(&mut self, profile: &str, output_name: &str, rotation: RotationState) {
+        let outputs = self.state.profiles.entry(profile.to_string()).or_default();
+        if outputs.get(output_name) == Some(&rotation) {
+            return;
+        }
+        outputs.insert(output_name.to_string(), rotation);
+        self.save();
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_vec_pretty(&self.state)
+            .map_err(std::io::Error::other)
+            .and_then(|json| write_atomic(&self.path, json));
+        if let Err(e) = result {
+            tracing::error!("unable to save state to {}: {e}", self.path.display());
+        }
+    }
+}
+
+fn read_state(path: &Path) -> State {
+    let content = match std::fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return State::default(),
+        Err(e) => {
+            tracing::error!("unable to read {}: {e}", path.display());
+            return State::default();
+        }
+    };
+    serde_json::from_slice(&content).unwrap_or_else(|e| {
+        tracing::error!("ignoring invalid state in {}: {e}", path.display());
+        State::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let saved = RotationState {
+            order: paths(&["a", "b", "c", "d"]),
+            index: 2,
+            override_img: None,
+            queue: vec![],
+        };
+        let shuffle = ImageOrder::Shuffle;
+
+        // unchanged
+        let restored = saved.reconcile(paths(&["d", "c", "b", "a"]), shuffle);
+        assert_eq!(restored, saved);
+
+        // removed images before the current one keep the index on the same image, new images
+        // come after it
+        let restored = saved.reconcile(paths(&["a", "c", "d", "e"]), shuffle);
+        assert_eq!(restored.order[..2], paths(&["a", "c"]));
+        assert_eq!(restored.order.len(), 4);
+        assert_eq!(restored.index, 1);
+
+        // the current image is gone, continue after it
+        let restored = saved.reconcile(paths(&["a", "b", "d"]), shuffle);
+        assert_eq!(restored.order, paths(&["a", "b", "d"]));
+        assert_eq!(restored.index, 2);
+
+        // everything after the current image is gone, wrap around
+        let restored = saved.reconcile(paths(&["a", "b"]), shuffle);
+        assert_eq!(restored.index, 0);
+
+        let restored = saved.reconcile(vec![], shuffle);
+        assert!(restored.order.is_empty());
+        assert_eq!(restored.index, 0);
+
+        // sorted orders take the new list as is
+        let restored = saved.reconcile(paths(&["a", "bb", "c", "d"]), ImageOrder::Name);
+        assert_eq!(restored.order, paths(&["a", "bb", "c", "d"]));
+        assert_eq!(restored.index, 2);
+
+        // the current image is gone from a sorted order, the index points at the next one in
+        // the new list
+        let saved = RotationState {
+            order: paths(&["b", "c", "d"]),
+            index: 1,
+            override_img: None,
+            queue: vec![],
+        };
+        let restored = saved.reconcile(paths(&["a", "b", "d"]), ImageOrder::Name);
+        assert_eq!(restored.index, 2);
+        let restored = saved.reconcile(paths(&["a", "b"]), ImageOrder::Name);
+        assert_eq!(restored.index, 0);
+    }
+
+    #[test]
+    fn test_reconcile_repeated_images() {
+        let saved = RotationState {
+            order: paths(&["logo", "a", "logo", "b"]),
+            index: 2,
+            override_img: None,
+            queue: vec![],
+        };
+
+        // the second listing of the image stays current
+        for order in [ImageOrder::Shuffle, ImageOrder::Name] {
+            let restored = saved.reconcile(paths(&["logo", "a", "logo", "b"]), order);
+            assert_eq!(restored, saved);
+        }
+
+        // a new listing of an image already in the rotation is shuffled in after the current
+        // image
+        let restored = saved.reconcile(
+            paths(&["logo", "a", "logo", "b", "logo"]),
+            ImageOrder::Shuffle,
+        );
+        assert_eq!(restored.order[..3], saved.order[..3]);
+        assert_eq!(restored.order.len(), 5);
+        assert_eq!(restored.index, 2);
+
+        // the image is listed once now, continue after it
+        let restored = saved.reconcile(paths(&["logo", "a", "b"]), ImageOrder::Shuffle);
+        assert_eq!(restored.order, paths(&["logo", "a", "b"]));
+        assert_eq!(restored.index, 2);
+    }
+
+    #[test]
+    fn test_reconcile_keeps_fills() {
+        let saved = RotationState {
+            order: paths(&["a", "b"]),
+            index: 0,
+            override_img: Some("fill:#1e1e2e".into()),
+            queue: paths(&[
+                "fill:linear-gradient(#89b4fa, #1e1e2e)",
+                "/does/not/exist.png",
+            ]),
+        };
+        let restored = saved.reconcile(paths(&["a", "b"]), ImageOrder::Name);
+        assert_eq!(restored.override_img, saved.override_img);
+        assert_eq!(restored.queue, saved.queue[..1]);
+    }
+
+    #[test]
+    fn test_image_changes() {
+        let before = paths(&["logo", "a", "logo", "b"]);
+        assert_eq!(image_changes(&before, &before), (0, 0));
+        assert_eq!(image_changes(&before, &paths(&["logo", "a", "b"])), (0, 0));
+        assert_eq!(
+            image_changes(
+                &paths(&["logo", "a", "b"]),
+                &paths(&["logo", "a", "logo", "c"])
+            ),
+            (1, 1)
+        );
+        assert_eq!(image_changes(&before, &[]), (0, 3));
+    }
+}
@@ -0,0 +1,308 @@
+//! Theme templates rendered from the palette of the current wallpaper.
+//!
+//! Templates are plain text files with `{{ expression }}` blocks. An expression is a variable
+//! followed by filters separated by `|`, e.g. `{{ accent | lighten(0.1) | rgb }}`. Colors render
+//! as `#rrggbb` unless a format filter says otherwise.
+//!
+//! Rendering does file IO and runs post commands, so it happens on a worker thread. Only the
+//! newest palette matters, older jobs still waiting are dropped.
+
+use std::{
+    collections::HashMap,
+    process::Command,
+    sync::mpsc::{Receiver, Sender},
+};
+
+use color_eyre::{
+    Result,
+    eyre::{WrapErr, bail, eyre},
+};
+use wayper_lib::{
+    config::TemplateConfig,
+    palette::{Palette, Rgb},
+    paths::write_atomic,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Color { color: Rgb, alpha: f32 },
+    Text(String),
+}
+
+impl Value {
+    fn color(color: Rgb) -> Self {
+        Self::Color { color, alpha: 1.0 }
+    }
+
+    fn render(self) -> String {
+        match self {
+            Value::Color { color, alpha } if alpha < 1.0 => {
+                format!("{}{:02x}", color.to_hex(), (alpha * 255.0).round() as u8)
+            }
+            Value::Color { color, .. } => color.to_hex(),
+            Value::Text(text) => text,
+        }
+    }
+}
+
+/// Variables available to templates
+#[derive(Debug, Clone, Default)]
+pub struct TemplateVars(HashMap<String, Value>);
+
+impl TemplateVars {
+    /// The palette roles, `color0` and up for the swatches, and `primary<tone>` and
+    /// `neutral<tone>` for the tonal palettes
+    pub fn new(palette: &Palette) -> Self {
+        let mut vars = HashMap::new();
+        for (role, color) in palette.roles() {
+            vars.insert(role.to_string(), Value::color(color));
+        }
+        for (i, swatch) in palette.swatches.iter().enumerate() {
+            vars.insert(format!("color{i}"), Value::color(swatch.color));
+        }
+        for (tone, color) in &palette.primary_tones {
+            vars.insert(format!("primary{tone}"), Value::color(*color));
+        }
+        for (tone, color) in &palette.neutral_tones {
+            vars.insert(format!("neutral{tone}"), Value::color(*color));
+        }
+        Self(vars)
+    }
+
+    /// Add a plain text variable
+    pub fn text(mut self, name: &str, value: impl std::fmt::Display) -> Self {
+        self.0
+            .insert(name.to_string(), Value::Text(value.to_string()));
+        self
+    }
+}
+
+/// Render a template. Errors point at the line of the broken expression.
+pub fn render(template: &str, vars: &TemplateVars) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let line = template[..template.len() - rest.len() + start]
+            .matches('\n')
+            .count()
+            + 1;
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| eyre!("line {line}: unclosed {{{{"))?;
+        let value = evaluate(after[..end].trim(), vars).wrap_err_with(|| format!("line {line}"))?;
+        output.push_str(&value.render());
+        rest = &after[end + 2..];
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+fn evaluate(expression: &str, vars: &TemplateVars) -> Result<Value> {
+    let mut parts = expression.split('|').map(str::trim);
+    let name = parts.next().unwrap_or_default();
+    let mut value = vars
+        .0
+        .get(name)
+        .cloned()
+        .ok_or_else(|| eyre!("unknown variable \"{name}\""))?;
+    for filter in parts {
+        value = apply_filter(filter, value)?;
+    }
+    Ok(value)
+}
+
+fn apply_filter(filter: &str, value: Value) -> Result<Value> {
+    let (name, arg) = match filter.split_once('(') {
+        Some((name, arg)) => {
+            let arg = arg
+                .strip_suffix(')')
+                .ok_or_else(|| eyre!("unclosed ( in filter \"{filter}\""))?;
+            let arg = arg
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| eyre!("filter \"{filter}\" expects a number"))?;
+            (name.trim(), Some(arg))
+        }
+        None => (filter, None),
+    };
+
+    let Value::Color { color, alpha } = value else {
+        bail!("filter \"{name}\" needs a color");
+    };
+    let amount = || arg.ok_or_else(|| eyre!("filter \"{name}\" expects an argument"));
+
+    let value = match name {
+        "hex" => Value::Text(Value::Color { color, alpha }.render()),
+        "strip" => Value::Text(
+            Value::Color { color, alpha }
+                .render()
+                .trim_start_matches('#')
+                .to_string(),
+        ),
+        "rgb" if alpha < 1.0 => Value::Text(rgba(color, alpha)),
+        "rgb" => Value::Text(format!("rgb({}, {}, {})", color.r, color.g, color.b)),
+        "rgba" => Value::Text(rgba(color, alpha)),
+        "hsl" => {
+            let (h, s, l) = color.to_hsl();
+            Value::Text(format!(
+                "hsl({:.0}, {:.0}%, {:.0}%)",
+                h,
+                s * 100.0,
+                l * 100.0
+            ))
+        }
+        "lighten" => Value::Color {
+            color: shift_lightness(color, amount()?),
+            alpha,
+        },
+        "darken" => Value::Color {
+            color: shift_lightness(color, -amount()?),
+            alpha,
+        },
+        "alpha" => Value::Color {
+            color,
+            alpha: amount()?.clamp(0.0, 1.0),
+        },
+        _ => bail!("unknown filter \"{name}\""),
+    };
+    Ok(value)
+}
+
+fn rgba(color: Rgb, alpha: f32) -> String {
+    format!("rgba({}, {}, {}, {alpha})", color.r, color.g, color.b)
+}
+
+/// Change the perceptual lightness, `amount` is in 0.0..=1.0
+fn shift_lightness(color: Rgb, amount: f32) -> Rgb {
+    let mut lab = color.to_oklab();
+    lab.l = (lab.l + amount).clamp(0.0, 1.0);
+    lab.to_rgb()
+}
+
+struct TemplateJob {
+    templates: Vec<TemplateConfig>,
+    vars: TemplateVars,
+}
+
+/// Renders templates on a worker thread
+pub struct TemplateRenderer {
+    tx: Sender<TemplateJob>,
+}
+
+impl TemplateRenderer {
+    pub fn new() -> Self {
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || template_worker(rx));
+        Self { tx }
+    }
+
+    pub fn render(&self, templates: Vec<TemplateConfig>, vars: TemplateVars) {
+        if templates.is_empty() {
+            return;
+        }
+        if let Err(e) = self.tx.send(TemplateJob { templates, vars }) {
+            tracing::error!("unable to send job to template worker: {e}");
+        }
+    }
+}
+
+fn template_worker(rx: Receiver<TemplateJob>) {
+    while let Ok(mut job) = rx.recv() {
+        // skip to the newest job
+        while let Ok(newer) = rx.try_recv() {
+            job = newer;
+        }
+
+        for template in &job.templates {
+            match render_template(template, &job.vars) {
+                Ok(()) => tracing::info!("rendered template {}", template.output.display()),
+                Err(e) => tracing::error!(
+                    "unable to render template {}: {e:#}",
+                    template.input.display()
+                ),
+            }
+        }
+    }
+}
+
+fn render_template(template: &TemplateConfig, vars: &TemplateVars) -> Result<()> {
+    let input = std::fs::read_to_string(&template.input)
+        .wrap_err_with(|| format!("reading {}", template.input.display()))?;
+    let output = render(&input, vars)?;
+    write_atomic(&template.output, output)
+        .wrap_err_with(|| format!("writing {}", template.output.display()))?;
+
+    if let Some(post_command) = &template.post_command {
+        run_post_command(post_command)?;
+    }
+    Ok(())
+}
+
+fn run_post_command(command: &str) -> Result<()> {
+    let args = shlex::split(command).ok_or_else(|| eyre!("invalid post_command: {command}"))?;
+    let Some((program, args)) = args.split_first() else {
+        bail!("empty post_command");
+    };
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .wrap_err_with(|| format!("running {command}"))?;
+    if !output.status.success() {
+        bail!(
+            "post_command \"{command}\" failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> TemplateVars {
+        let mut pixels = [20, 20, 60, 255].repeat(3);
+        pixels.extend([240, 120, 20, 255]);
+        TemplateVars::new(&Palette::from_rgba(&pixels).unwrap()).text("output", "eDP-1")
+    }
+
+    #[test]
+    fn test_render_filters() {
+        let vars = vars();
+        let template = "\
+background={{ dominant | strip }}
+accent = {{accent}}
+rgb: {{ accent | rgb }}
+hsl: {{ accent|hsl }}
+dim: {{ accent | alpha(0.5) }} {{ accent | alpha(0.5) | rgb }}
+light: {{ dark | lighten(1) }} dark: {{ light | darken(1) }}
+{{ output }}";
+        assert_eq!(
+            render(template, &vars).unwrap(),
+            "\
+background=14143c
+accent = #f07814
+rgb: rgb(240, 120, 20)
+hsl: hsl(27, 88%, 51%)
+dim: #f0781480 rgba(240, 120, 20, 0.5)
+light: #ffffff dark: #000000
+eDP-1"
+        );
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let vars = vars();
+        let error = render("ok\n{{ nope }}", &vars).unwrap_err();
+        assert_eq!(format!("{error:#}"), "line 2: unknown variable \"nope\"");
+        assert!(render("{{ accent | sparkle }}", &vars).is_err());
+        assert!(render("{{ accent | lighten }}", &vars).is_err());
+        assert!(render("{{ output | rgb }}", &vars).is_err());
+        assert!(render("{{ accent", &vars).is_err());
+    }
+}
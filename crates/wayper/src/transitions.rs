@@ -0,0 +1,426 @@
+//! Transitions drawn by a shader blending the old and the new image, like wipes and dissolves.
+//! Both images are sampled the way they would be drawn on their own, so their fit, placement and
+//! motion carry through the transition.
+//!
+//! Users can write their own in WGSL, a `transition(uv, progress, from, to)` function returning
+//! the color of a pixel. The prelude is appended to it like to shader wallpapers, and `params`
+//! gets a float field for each value in the `params` table of the transition.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use smithay_client_toolkit::reexports::calloop;
+
+use crate::{
+    scene::{Rect, TransitionEffect, TransitionNode},
+    shader_wallpaper,
+    wgpu_renderer::{ImageNodeParams, RenderEvent, scene_node_pipeline},
+};
+
+/// Declarations of the transition shaders, after the `transition` function
+const PRELUDE: &[&str] = &[
+    include_str!("../shaders/sizing.wgsl"),
+    include_str!("../shaders/transition_scene.wgsl"),
+];
+
+/// Values a custom transition can be given, they fill one `params` buffer
+const MAX_PARAMS: usize = 64;
+
+/// Parameters of a transition node, laid out like `TransitionNodeParams` in the shader
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TransitionNodeParams {
+    rect: [f32; 4],
+    output_size: [f32; 2],
+    progress: f32,
+    kind: u32,
+    from: ImageNodeParams,
+    to: ImageNodeParams,
+    direction: [f32; 2],
+    center: [f32; 2],
+    softness: f32,
+    seed: u32,
+    amount: f32,
+    has_from: u32,
+}
+
+impl TransitionNodeParams {
+    /// `from` and `to` are the parameters the images would be drawn with on their own
+    pub fn new(
+        transition: &TransitionNode,
+        output_size: (u32, u32),
+        from: Option<ImageNodeParams>,
+        to: ImageNodeParams,
+    ) -> Self {
+        let mut params = Self {
+            rect: Rect {
+                x: 0.0,
+                y: 0.0,
+                width: output_size.0 as f32,
+                height: output_size.1 as f32,
+            }
+            .as_array(),
+            output_size: [output_size.0 as f32, output_size.1 as f32],
+            progress: transition.progress,
+            kind: 0,
+            from: from.unwrap_or(to),
+            to,
+            direction: [1.0, 0.0],
+            center: [0.5, 0.5],
+            softness: 0.0,
+            seed: 0,
+            amount: 1.0,
+            has_from: from.is_some() as u32,
+        };
+        match transition.effect {
+            TransitionEffect::Wipe {
+                direction,
+                softness,
+            } => {
+                params.kind = 0;
+                params.direction = direction;
+                params.softness = softness;
+            }
+            TransitionEffect::Circle { center, softness } => {
+                params.kind = 1;
+                params.center = center;
+                params.softness = softness;
+            }
+            TransitionEffect::Dissolve {
+                seed,
+                scale,
+                softness,
+            } => {
+                params.kind = 2;
+                params.seed = seed;
+                params.amount = scale;
+                params.softness = softness;
+            }
+            TransitionEffect::Zoom { scale } => {
+                params.kind = 3;
+                params.amount = scale;
+            }
+            TransitionEffect::Blur { radius } => {
+                params.kind = 4;
+                params.amount = radius;
+            }
+            TransitionEffect::Pixelate { size } => {
+                params.kind = 5;
+                params.amount = size;
+            }
+            // custom shaders have their own `params`
+            TransitionEffect::Shader { .. } => params.kind = 6,
+        }
+        params
+    }
+}
+
+/// A compiled custom transition, with the version of the file and the params it was compiled for
+struct Program {
+    version: String,
+    pipeline: wgpu::RenderPipeline,
+}
+
+/// The pipelines drawing transition nodes
+pub struct TransitionPipeline {
+    pipeline: Option<wgpu::RenderPipeline>,
+    bind_group_layout: Option<wgpu::BindGroupLayout>,
+    /// `params` of custom transitions. Outputs share it, each of their frames is submitted on its
+    /// own.
+    params: Option<wgpu::Buffer>,
+    programs: HashMap<PathBuf, Program>,
+    /// The version of custom transitions that failed to compile, tried again once they change
+    failed: HashMap<PathBuf, String>,
+    event_tx: calloop::channel::Sender<RenderEvent>,
+}
+
+impl TransitionPipeline {
+    pub fn new(event_tx: calloop::channel::Sender<RenderEvent>) -> Self {
+        Self {
+            pipeline: None,
+            bind_group_layout: None,
+            params: None,
+            programs: HashMap::new(),
+            failed: HashMap::new(),
+            event_tx,
+        }
+    }
+
+    fn init_pipeline(&mut self, device: &wgpu::Device, surface_format: wgpu::TextureFormat) {
+        let shader_str = format!(
+            "{}\n{}",
+            include_str!("../shaders/transitions.wgsl"),
+            PRELUDE.join("\n"),
+        );
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Scene Transition Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_str.into()),
+        });
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        // the built in transitions leave the params unused
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Scene Transition Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture_entry(1),
+                texture_entry(2),
+                uniform_entry(3),
+                uniform_entry(4),
+            ],
+        });
+
+        self.pipeline = Some(scene_node_pipeline(
+            device,
+            "Scene Transition",
+            &shader,
+            &bind_group_layout,
+            surface_format,
+        ));
+        self.params = Some(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("transition params"),
+            size: (MAX_PARAMS * std::mem::size_of::<f32>()) as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+        self.bind_group_layout = Some(bind_group_layout);
+        tracing::info!("scene transition node pipeline initialized successfully");
+    }
+
+    /// Compile a custom transition if its file or params changed since `version` was compiled.
+    /// False if it doesn't compile, the transition is crossfaded then.
+    pub fn prepare_shader(
+        &mut self,
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        shader: &Path,
+        params: &BTreeMap<String, f32>,
+        version: &str,
+    ) -> bool {
+        if self.pipeline.is_none() {
+            self.init_pipeline(device, surface_format);
+        }
+        let Some(layout) = self.bind_group_layout.as_ref() else {
+            return false;
+        };
+
+        if self
+            .programs
+            .get(shader)
+            .is_some_and(|program| program.version == version)
+        {
+            return true;
+        }
+        if self
+            .failed
+            .get(shader)
+            .is_some_and(|failed| failed == version)
+        {
+            return false;
+        }
+
+        let names = params.keys().map(String::as_str).collect::<Vec<_>>();
+        let event = match compile(device, layout, surface_format, shader, &names) {
+            Ok(pipeline) => {
+                tracing::info!("compiled transition {}", shader.display());
+                self.programs.insert(
+                    shader.to_path_buf(),
+                    Program {
+                        version: version.to_string(),
+                        pipeline,
+                    },
+                );
+                self.failed.remove(shader);
+                RenderEvent::ShaderLoaded {
+                    shader: shader.to_path_buf(),
+                }
+            }
+            Err(error) => {
+                tracing::warn!(
+                    "transition {} failed, crossfading instead: {error}",
+                    shader.display()
+                );
+                self.programs.remove(shader);
+                self.failed
+                    .insert(shader.to_path_buf(), version.to_string());
+                RenderEvent::ShaderFailed {
+                    shader: shader.to_path_buf(),
+                    error,
+                }
+            }
+        };
+        if let Err(e) = self.event_tx.send(event) {
+            tracing::error!("unable to send render event: {e}");
+        }
+        self.programs.contains_key(shader)
+    }
+
+    /// Write the `params` of a custom transition, in the order of their names
+    pub fn write_params(&self, queue: &wgpu::Queue, params: &BTreeMap<String, f32>) {
+        let Some(buffer) = self.params.as_ref() else {
+            return;
+        };
+        let values = params.values().copied().collect::<Vec<_>>();
+        queue.write_buffer(buffer, 0, bytemuck::cast_slice(&values));
+    }
+
+    /// Bind group drawing a transition between two textures, with the parameters in `params`
+    pub fn create_bind_group(
+        &mut self,
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        sampler: &wgpu::Sampler,
+        from: &wgpu::TextureView,
+        to: &wgpu::TextureView,
+        params: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        if self.pipeline.is_none() {
+            self.init_pipeline(device, surface_format);
+        }
+        let layout = self
+            .bind_group_layout
+            .as_ref()
+            .expect("transition pipeline was just initialized");
+        let user_params = self
+            .params
+            .as_ref()
+            .expect("transition pipeline was just initialized");
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("transition node bg"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(from),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(to),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: user_params.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// The pipeline of a custom transition, or of the built in ones
+    pub fn pipeline(&self, shader: Option<&Path>) -> Option<&wgpu::RenderPipeline> {
+        match shader {
+            Some(shader) => self.programs.get(shader).map(|program| &program.pipeline),
+            None => self.pipeline.as_ref(),
+        }
+    }
+}
+
+/// The WGSL of a custom transition with the prelude appended, and a `params` field per name
+fn with_prelude(source: &str, names: &[&str]) -> String {
+    let fields = match names.is_empty() {
+        // structs can't be empty
+        true => "       _unused: f32,\n".to_string(),
+        false => names
+            .iter()
+            .map(|name| format!("       {name}: f32,\n"))
+            .collect(),
+    };
+    format!(
+        "{source}\n{}\nstruct TransitionParams {{\n{fields}}}\n\n@group(0) @binding(4)\nvar<uniform> params: TransitionParams;\n",
+        PRELUDE.join("\n")
+    )
+}
+
+fn compile(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    surface_format: wgpu::TextureFormat,
+    path: &Path,
+    names: &[&str],
+) -> Result<wgpu::RenderPipeline, String> {
+    if names.len() > MAX_PARAMS {
+        return Err(format!(
+            "{} params given, at most {MAX_PARAMS} fit",
+            names.len()
+        ));
+    }
+    let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let source = with_prelude(&source, names);
+    shader_wallpaper::validate(&source, path).map_err(|e| e.trim_end().to_string())?;
+
+    // whatever naga let through is reported by wgpu instead of panicking the renderer
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Custom Transition"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let pipeline = scene_node_pipeline(
+        device,
+        "Custom Transition",
+        &shader,
+        bind_group_layout,
+        surface_format,
+    );
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(error.to_string()),
+        None => Ok(pipeline),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_custom_transitions() {
+        let path = Path::new("ripple.wgsl");
+        let ripple = r#"
+fn transition(uv: vec2<f32>, progress: f32, a: vec4<f32>, b: vec4<f32>) -> vec4<f32> {
+    let offset = sin(length(uv - 0.5) * params.waves - progress * 6.0) * params.amplitude;
+    return mix(from_color(uv + offset * (1.0 - progress)), b, progress);
+}
+"#;
+        let source = with_prelude(ripple, &["amplitude", "waves"]);
+        assert_eq!(shader_wallpaper::validate(&source, path), Ok(()));
+
+        // errors point at the line of the file, params that aren't given are errors too
+        let source = with_prelude(ripple, &["amplitude"]);
+        let error = shader_wallpaper::validate(&source, path).unwrap_err();
+        assert!(error.contains("ripple.wgsl:3:"), "{error}");
+
+        let source = with_prelude("fn main() {}\n", &[]);
+        assert!(shader_wallpaper::validate(&source, path).is_err());
+    }
+}
@@ -0,0 +1,631 @@
+//! Video wallpapers. A video is decoded by ffmpeg on a thread for every output showing it, and
+//! played from the frame callbacks of the output like an animated image. Only the video stream is
+//! read, it loops forever. Frames are uploaded as YUV planes and turned into RGB in the shader.
+//!
+//! Decoding needs the `video` feature. Without it videos aren't picked up as wallpapers, and
+//! opening one fails.
+
+use std::{
+    collections::{HashMap, HashSet, hash_map::Entry},
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, TryRecvError},
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::{OptionExt, bail};
+use image::RgbaImage;
+
+use crate::wgpu_renderer::scene_node_pipeline;
+
+/// Whether a file is a video that can be played, by its extension
+pub fn is_video(path: &Path) -> bool {
+    cfg!(feature = "video")
+        && mime_guess::from_path(path)
+            .iter()
+            .any(|mime| mime.type_() == "video")
+}
+
+struct Plane {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+struct VideoFrame {
+    /// When the frame is due, in play time since the video was opened
+    pts: Duration,
+    /// Y, U and V planes, the chroma planes at half the size
+    planes: [Plane; 3],
+    /// The first frame in RGBA, to extract a palette from
+    preview: Option<RgbaImage>,
+}
+
+/// Turns the timestamps of the frames of a looping video into play time, growing across loops
+#[cfg(feature = "video")]
+#[derive(Debug)]
+struct Timeline {
+    /// Timestamp of the first frame, streams don't have to start at 0
+    start: Option<f64>,
+    /// Play time at the start of the current loop
+    offset: f64,
+    /// Play time of the last frame
+    last: Option<f64>,
+    /// How long the last frame was shown, the guess for frames without a timestamp
+    step: f64,
+}
+
+#[cfg(feature = "video")]
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            start: None,
+            offset: 0.0,
+            last: None,
+            step: 1.0 / 30.0,
+        }
+    }
+}
+
+#[cfg(feature = "video")]
+impl Timeline {
+    /// Play time of the next decoded frame, from its timestamp in seconds
+    fn place(&mut self, timestamp: Option<f64>) -> Duration {
+        let start = *self.start.get_or_insert(timestamp.unwrap_or(0.0));
+        let time = match timestamp.map(|timestamp| self.offset + (timestamp - start).max(0.0)) {
+            Some(time) if self.last.is_none_or(|last| time > last) => time,
+            // missing or out of order, right after the last frame
+            _ => self.last.map_or(self.offset, |last| last + self.step),
+        };
+        if let Some(last) = self.last {
+            self.step = time - last;
+        }
+        self.last = Some(time);
+        Duration::from_secs_f64(time)
+    }
+
+    /// The video starts over, right after its last frame
+    fn restart(&mut self) {
+        if let Some(last) = self.last {
+            self.offset = last + self.step;
+        }
+    }
+}
+
+/// Frames decoded ahead of the one shown
+#[cfg(feature = "video")]
+const QUEUED_FRAMES: usize = 4;
+/// How long a video may take to open, until its first frame
+const OPEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The frames of a video, decoded on a thread that stops once the decoder is dropped
+struct Decoder {
+    frames: Receiver<color_eyre::Result<VideoFrame>>,
+    /// The next frame, decoded before it is due
+    next: Option<VideoFrame>,
+    /// When the video was opened, until its first frame is decoded
+    opening: Option<Instant>,
+}
+
+impl Decoder {
+    /// Start decoding a video on its thread, without waiting for its first frame
+    #[cfg(feature = "video")]
+    fn open(path: &Path) -> color_eyre::Result<Self> {
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(|| {
+            if let Err(e) = ffmpeg_next::init() {
+                tracing::error!("unable to initialize ffmpeg: {e}");
+            }
+        });
+
+        let (frames_tx, frames) = std::sync::mpsc::sync_channel(QUEUED_FRAMES);
+        let thread_path = path.to_path_buf();
+        std::thread::Builder::new()
+            .name("video decoder".to_string())
+            .spawn(move || {
+                if let Err(e) = decode(&thread_path, &frames_tx) {
+                    let _ = frames_tx.send(Err(e));
+                }
+            })?;
+        Ok(Self {
+            frames,
+            next: None,
+            opening: Some(Instant::now()),
+        })
+    }
+
+    #[cfg(not(feature = "video"))]
+    fn open(_path: &Path) -> color_eyre::Result<Self> {
+        bail!("wayper is built without video support")
+    }
+
+    /// The last frame due at `position`, None while the one shown is still current or the
+    /// first one isn't decoded yet. Frames passed over are dropped.
+    fn frame_at(&mut self, position: Duration) -> color_eyre::Result<Option<VideoFrame>> {
+        let mut due: Option<VideoFrame> = None;
+        loop {
+            let mut frame = match self.next.take() {
+                Some(frame) => frame,
+                None => match self.frames.try_recv() {
+                    Ok(frame) => {
+                        self.opening = None;
+                        frame?
+                    }
+                    Err(TryRecvError::Empty) => match self.opening {
+                        Some(opened) if opened.elapsed() > OPEN_TIMEOUT => {
+                            bail!("no frame decoded in {} s", OPEN_TIMEOUT.as_secs())
+                        }
+                        // decoding fell behind, the frame shown stays up meanwhile
+                        _ => break,
+                    },
+                    Err(TryRecvError::Disconnected) => bail!("video decoder stopped"),
+                },
+            };
+            if frame.pts > position {
+                self.next = Some(frame);
+                break;
+            }
+            if let Some(skipped) = due.take() {
+                frame.preview = frame.preview.or(skipped.preview);
+            }
+            due = Some(frame);
+        }
+        Ok(due)
+    }
+}
+
+/// Decode a video over and over, until its frames aren't wanted anymore
+#[cfg(feature = "video")]
+fn decode(
+    path: &Path,
+    frames: &std::sync::mpsc::SyncSender<color_eyre::Result<VideoFrame>>,
+) -> color_eyre::Result<()> {
+    use ffmpeg_next::{codec, format::Pixel, media, software::scaling};
+
+    let mut input = ffmpeg_next::format::input(path)?;
+    let stream = input
+        .streams()
+        .best(media::Type::Video)
+        .ok_or_eyre("no video stream")?;
+    let stream_index = stream.index();
+    let time_base = f64::from(stream.time_base());
+    let mut decoder = codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .video()?;
+    let mut sink = FrameSink {
+        to_yuv: scaling::Context::get(
+            decoder.format(),
+            decoder.width(),
+            decoder.height(),
+            Pixel::YUV420P,
+            decoder.width(),
+            decoder.height(),
+            scaling::Flags::BILINEAR,
+        )?,
+        timeline: Timeline::default(),
+        time_base,
+        preview: true,
+        frames,
+    };
+
+    loop {
+        let decoded_before = sink.timeline.last;
+        for (stream, packet) in input.packets() {
+            if stream.index() != stream_index {
+                continue;
+            }
+            if let Err(e) = decoder.send_packet(&packet) {
+                tracing::debug!("skipping a packet of {}: {e}", path.display());
+                continue;
+            }
+            if !sink.receive(&mut decoder)? {
+                return Ok(());
+            }
+        }
+        decoder.send_eof()?;
+        if !sink.receive(&mut decoder)? {
+            return Ok(());
+        }
+        if sink.timeline.last == decoded_before {
+            bail!("no frames in {}", path.display());
+        }
+
+        // play it again
+        input.seek(0, ..)?;
+        decoder.flush();
+        sink.timeline.restart();
+    }
+}
+
+/// Where decoded frames go
+#[cfg(feature = "video")]
+struct FrameSink<'a> {
+    to_yuv: ffmpeg_next::software::scaling::Context,
+    timeline: Timeline,
+    /// Seconds per timestamp unit
+    time_base: f64,
+    /// The next frame comes with a preview
+    preview: bool,
+    frames: &'a std::sync::mpsc::SyncSender<color_eyre::Result<VideoFrame>>,
+}
+
+#[cfg(feature = "video")]
+impl FrameSink<'_> {
+    /// Send on the frames the decoder has ready. Returns false once they aren't wanted anymore.
+    fn receive(&mut self, decoder: &mut ffmpeg_next::decoder::Video) -> color_eyre::Result<bool> {
+        let mut decoded = ffmpeg_next::frame::Video::empty();
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            // the shader takes planar YUV 4:2:0, most videos already are
+            let mut yuv = ffmpeg_next::frame::Video::empty();
+            self.to_yuv.run(&decoded, &mut yuv)?;
+            let preview = match std::mem::take(&mut self.preview) {
+                true => Some(rgba(&decoded)?),
+                false => None,
+            };
+            let frame = VideoFrame {
+                pts: self
+                    .timeline
+                    .place(decoded.timestamp().map(|ts| ts as f64 * self.time_base)),
+                planes: [0, 1, 2].map(|plane| Plane {
+                    width: yuv.plane_width(plane),
+                    height: yuv.plane_height(plane),
+                    data: tight_plane(&yuv, plane, yuv.plane_width(plane) as usize),
+                }),
+                preview,
+            };
+            if self.frames.send(Ok(frame)).is_err() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// A plane of a frame without the padding at the end of its rows
+#[cfg(feature = "video")]
+fn tight_plane(frame: &ffmpeg_next::frame::Video, plane: usize, row_bytes: usize) -> Vec<u8> {
+    frame
+        .data(plane)
+        .chunks(frame.stride(plane))
+        .take(frame.plane_height(plane) as usize)
+        .flat_map(|row| &row[..row_bytes])
+        .copied()
+        .collect()
+}
+
+/// A frame in RGBA
+#[cfg(feature = "video")]
+fn rgba(frame: &ffmpeg_next::frame::Video) -> color_eyre::Result<RgbaImage> {
+    use ffmpeg_next::{format::Pixel, software::scaling};
+
+    let (width, height) = (frame.width(), frame.height());
+    let mut rgba = ffmpeg_next::frame::Video::empty();
+    scaling::Context::get(
+        frame.format(),
+        width,
+        height,
+        Pixel::RGBA,
+        width,
+        height,
+        scaling::Flags::BILINEAR,
+    )?
+    .run(frame, &mut rgba)?;
+    RgbaImage::from_raw(width, height, tight_plane(&rgba, 0, width as usize * 4))
+        .ok_or_eyre("frame has the wrong size")
+}
+
+struct Player {
+    /// Tells the textures of players apart in bind group keys
+    id: u64,
+    decoder: Decoder,
+    /// Y, U and V textures, empty until the first frame is uploaded
+    planes: Vec<wgpu::Texture>,
+}
+
+/// The videos shown on every output, with the pipeline drawing them
+#[derive(Default)]
+pub struct VideoPlayers {
+    players: HashMap<(String, PathBuf), Player>,
+    /// Videos that failed to play, left alone until a retry
+    failed: HashSet<PathBuf>,
+    next_id: u64,
+    pipeline: Option<wgpu::RenderPipeline>,
+    bind_group_layout: Option<wgpu::BindGroupLayout>,
+}
+
+impl VideoPlayers {
+    fn init_pipeline(&mut self, device: &wgpu::Device, surface_format: wgpu::TextureFormat) {
+        let shader_str = format!(
+            "{}\n{}",
+            include_str!("../shaders/sizing.wgsl"),
+            include_str!("../shaders/video_scene.wgsl")
+        );
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Scene Video Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_str.into()),
+        });
+
+        let plane = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        // laid out like the image bind group, with the chroma planes after it
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Scene Video Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                plane(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                plane(3),
+                plane(4),
+            ],
+        });
+
+        self.pipeline = Some(scene_node_pipeline(
+            device,
+            "Scene Video",
+            &shader,
+            &bind_group_layout,
+            surface_format,
+        ));
+        self.bind_group_layout = Some(bind_group_layout);
+        tracing::info!("scene video node pipeline initialized successfully");
+    }
+
+    /// Upload the frame of a video due at `position` on an output, opening the video if the
+    /// output just started showing it. The video has no textures until its first frame is
+    /// decoded. Returns the first frame in RGBA once it is shown.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface_format: wgpu::TextureFormat,
+        output_name: &str,
+        video: &Path,
+        position: Duration,
+    ) -> color_eyre::Result<Option<RgbaImage>> {
+        if self.pipeline.is_none() {
+            self.init_pipeline(device, surface_format);
+        }
+
+        let key = (output_name.to_string(), video.to_path_buf());
+        let player = match self.players.entry(key.clone()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(_) if self.failed.contains(video) => return Ok(None),
+            Entry::Vacant(entry) => {
+                tracing::debug!("opening video {} on {output_name}", video.display());
+                let decoder = match Decoder::open(video) {
+                    Ok(decoder) => decoder,
+                    Err(e) => {
+                        self.failed.insert(video.to_path_buf());
+                        return Err(e);
+                    }
+                };
+                entry.insert(Player {
+                    id: 0,
+                    decoder,
+                    planes: vec![],
+                })
+            }
+        };
+        let frame = match player.decoder.frame_at(position) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                self.players.remove(&key);
+                self.failed.insert(video.to_path_buf());
+                return Err(e);
+            }
+        };
+
+        let same_size = player.planes.len() == frame.planes.len()
+            && player
+                .planes
+                .iter()
+                .zip(&frame.planes)
+                .all(|(texture, plane)| {
+                    (texture.width(), texture.height()) == (plane.width, plane.height)
+                });
+        if !same_size {
+            self.next_id += 1;
+            player.id = self.next_id;
+            player.planes = frame
+                .planes
+                .iter()
+                .map(|plane| {
+                    device.create_texture(&wgpu::TextureDescriptor {
+                        label: Some("video plane texture"),
+                        size: wgpu::Extent3d {
+                            width: plane.width,
+                            height: plane.height,
+                            depth_or_array_layers: 1,
+                        },
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format: wgpu::TextureFormat::R8Unorm,
+                        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                        view_formats: &[],
+                    })
+                })
+                .collect();
+        }
+
+        for (texture, plane) in player.planes.iter().zip(&frame.planes) {
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &plane.data,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(plane.width),
+                    rows_per_image: Some(plane.height),
+                },
+                texture.size(),
+            );
+        }
+        Ok(frame.preview)
+    }
+
+    /// Key of the textures a video is drawn from on an output, None until it has a frame
+    pub fn bind_group_key(&self, output_name: &str, video: &Path) -> Option<String> {
+        self.players
+            .get(&(output_name.to_string(), video.to_path_buf()))
+            .filter(|player| !player.planes.is_empty())
+            .map(|player| format!("video#{}", player.id))
+    }
+
+    /// Bind group drawing a video on an output, with the parameters of its node
+    pub fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        output_name: &str,
+        video: &Path,
+        sampler: &wgpu::Sampler,
+        params: &wgpu::Buffer,
+    ) -> color_eyre::Result<wgpu::BindGroup> {
+        let player = self
+            .players
+            .get(&(output_name.to_string(), video.to_path_buf()))
+            .ok_or_eyre("video not opened")?;
+        let layout = self
+            .bind_group_layout
+            .as_ref()
+            .ok_or_eyre("video bind group layout not initialized")?;
+        let views = player
+            .planes
+            .iter()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+            .collect::<Vec<_>>();
+        let [y, u, v] = views.as_slice() else {
+            bail!("video has no frame yet");
+        };
+        Ok(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("video node bg"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(y),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(u),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(v),
+                },
+            ],
+        }))
+    }
+
+    pub fn pipeline(&self) -> Option<&wgpu::RenderPipeline> {
+        self.pipeline.as_ref()
+    }
+
+    /// Try a video that failed to play again the next time it is shown
+    pub fn retry(&mut self, video: &Path) {
+        self.failed.remove(video);
+    }
+
+    /// Stop decoding the videos an output doesn't show anymore
+    pub fn retain(&mut self, output_name: &str, shown: &[&Path]) {
+        self.players
+            .retain(|(output, video), _| output != output_name || shown.contains(&video.as_path()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "video")]
+    #[test]
+    fn test_timeline() {
+        let mut timeline = Timeline::default();
+        let secs = |secs: f64| Duration::from_secs_f64(secs);
+
+        // streams can start late, play time starts at 0
+        assert_eq!(timeline.place(Some(10.0)), secs(0.0));
+        assert_eq!(timeline.place(Some(10.25)), secs(0.25));
+        // frames without a timestamp, or out of order, come right after the last one
+        assert_eq!(timeline.place(None), secs(0.5));
+        assert_eq!(timeline.place(Some(10.125)), secs(0.75));
+
+        // the next loop follows on after the last frame
+        timeline.restart();
+        assert_eq!(timeline.place(Some(10.0)), secs(1.0));
+        assert_eq!(timeline.place(Some(10.25)), secs(1.25));
+    }
+
+    #[test]
+    fn test_decoder_opens_without_waiting() {
+        let frame = |secs| VideoFrame {
+            pts: Duration::from_secs(secs),
+            planes: [(); 3].map(|_| Plane {
+                width: 0,
+                height: 0,
+                data: vec![],
+            }),
+            preview: None,
+        };
+        let (frames_tx, frames) = std::sync::mpsc::sync_channel(2);
+        let mut decoder = Decoder {
+            frames,
+            next: None,
+            opening: Some(Instant::now()),
+        };
+
+        // nothing to show until the first frame is decoded
+        assert!(decoder.frame_at(Duration::ZERO).unwrap().is_none());
+        frames_tx.send(Ok(frame(0))).unwrap();
+        frames_tx.send(Ok(frame(1))).unwrap();
+        let shown = decoder.frame_at(Duration::ZERO).unwrap().unwrap();
+        assert_eq!(shown.pts, Duration::ZERO);
+        assert!(decoder.opening.is_none());
+        // decoding fell behind, not a failure once the video is open
+        assert!(decoder.frame_at(Duration::from_secs(5)).unwrap().is_some());
+        assert!(decoder.frame_at(Duration::from_secs(5)).unwrap().is_none());
+
+        // a video that never comes up
+        let (_frames_tx, frames) = std::sync::mpsc::sync_channel(2);
+        let mut decoder = Decoder {
+            frames,
+            next: None,
+            opening: Instant::now().checked_sub(OPEN_TIMEOUT * 2),
+        };
+        assert!(decoder.frame_at(Duration::ZERO).is_err());
+    }
+}
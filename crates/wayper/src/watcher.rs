@@ -0,0 +1,258 @@
+//! Watches the image directories of the outputs with inotify, so image lists follow files being
+//! added and removed without a restart. Events are debounced so a bulk copy results in a single
+//! rescan.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    os::fd::AsFd,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use color_eyre::{Result, eyre::eyre};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
+use smithay_client_toolkit::reexports::calloop::{
+    Interest, LoopHandle, Mode, PostAction,
+    generic::Generic,
+    timer::{TimeoutAction, Timer},
+};
+use walkdir::WalkDir;
+
+use crate::handlers::Wayper;
+
+/// Quiet period after the last event before the directories are rescanned
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+pub struct DirWatcher {
+    watches: Watches,
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    /// Paths changed since the last rescan
+    changed: HashSet<PathBuf>,
+    last_event: Instant,
+    /// A debounce timer is pending
+    debouncing: bool,
+}
+
+impl DirWatcher {
+    /// Create the watcher and insert its inotify source into the event loop
+    pub fn new(handle: &LoopHandle<'static, Wayper>) -> Result<Self> {
+        let mut inotify = Inotify::init()?;
+        let watcher = Self::with_watches(inotify.watches());
+        let fd = inotify.as_fd().try_clone_to_owned()?;
+
+        handle
+            .insert_source(
+                Generic::new(fd, Interest::READ, Mode::Level),
+                move |_, _, wayper| {
+                    let mut buffer = [0; 4096];
+                    loop {
+                        match inotify.read_events(&mut buffer) {
+                            Ok(events) => {
+                                let Some(watcher) = wayper.watcher.as_mut() else {
+                                    continue;
+                                };
+                                for event in events {
+                                    watcher.record(&event.wd, event.mask, event.name);
+                                }
+                            }
+                            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                            Err(e) => {
+                                tracing::error!("unable to read inotify events: {e}");
+                                break;
+                            }
+                        }
+                    }
+                    schedule_rescan(wayper);
+                    Ok(PostAction::Continue)
+                },
+            )
+            .map_err(|e| eyre!("unable to insert the directory watcher: {e}"))?;
+
+        Ok(watcher)
+    }
+
+    fn with_watches(watches: Watches) -> Self {
+        Self {
+            watches,
+            dirs: HashMap::new(),
+            changed: HashSet::new(),
+            last_event: Instant::now(),
+            debouncing: false,
+        }
+    }
+
+    /// Watch `roots` and every directory below them, and stop watching anything else. For a
+    /// file, its directory is watched so changes to the file are seen.
+    pub fn watch<'a>(&mut self, roots: impl IntoIterator<Item = &'a Path>) {
+        let wanted = roots
+            .into_iter()
+            .filter_map(|root| {
+                if root.is_file() {
+                    root.parent()
+                } else {
+                    Some(root)
+                }
+            })
+            .filter(|root| root.is_dir())
+            .flat_map(|root| {
+                WalkDir::new(root)
+                    .into_iter()
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.file_type().is_dir())
+                    .map(|entry| entry.into_path())
+            })
+            .collect::<HashSet<_>>();
+
+        let unwanted = self
+            .dirs
+            .iter()
+            .filter(|(_, dir)| !wanted.contains(*dir))
+            .map(|(wd, _)| wd.clone())
+            .collect::<Vec<_>>();
+        for wd in unwanted {
+            if let Some(dir) = self.dirs.remove(&wd) {
+                tracing::debug!("no longer watching {}", dir.display());
+            }
+            // fails if the directory is already gone, which is fine
+            let _ = self.watches.remove(wd);
+        }
+
+        let watched = self.dirs.values().cloned().collect::<HashSet<_>>();
+        for dir in wanted.difference(&watched) {
+            let mask = WatchMask::CREATE
+                | WatchMask::DELETE
+                | WatchMask::CLOSE_WRITE
+                | WatchMask::MOVED_FROM
+                | WatchMask::MOVED_TO
+                | WatchMask::DELETE_SELF
+                | WatchMask::MOVE_SELF;
+            match self.watches.add(dir, mask) {
+                Ok(wd) => {
+                    tracing::debug!("watching {}", dir.display());
+                    self.dirs.insert(wd, dir.clone());
+                }
+                Err(e) => tracing::warn!("unable to watch {}: {e}", dir.display()),
+            }
+        }
+    }
+
+    fn record(&mut self, wd: &WatchDescriptor, mask: EventMask, name: Option<&OsStr>) {
+        let Some(dir) = self.dirs.get(wd) else {
+            return;
+        };
+        let path = match name {
+            Some(name) => dir.join(name),
+            None => dir.clone(),
+        };
+        tracing::trace!("{mask:?} on {}", path.display());
+
+        if mask.contains(EventMask::IGNORED) {
+            // the directory is gone, the kernel dropped the watch
+            self.dirs.remove(wd);
+        }
+        self.changed.insert(path);
+        self.last_event = Instant::now();
+    }
+
+    /// The paths changed once nothing happened for the debounce period, otherwise how much
+    /// longer to wait
+    fn settled(&mut self) -> Result<HashSet<PathBuf>, Duration> {
+        let quiet = self.last_event.elapsed();
+        if quiet < DEBOUNCE {
+            return Err(DEBOUNCE - quiet);
+        }
+        self.debouncing = false;
+        Ok(std::mem::take(&mut self.changed))
+    }
+}
+
+/// Start the debounce timer if there are changes and it isn't running yet
+fn schedule_rescan(wayper: &mut Wayper) {
+    let Some(watcher) = wayper.watcher.as_mut() else {
+        return;
+    };
+    if watcher.changed.is_empty() || watcher.debouncing {
+        return;
+    }
+    watcher.debouncing = true;
+
+    let timer = Timer::from_duration(DEBOUNCE);
+    let result = wayper
+        .c_queue_handle
+        .insert_source(timer, |_, _, wayper: &mut Wayper| {
+            let Some(watcher) = wayper.watcher.as_mut() else {
+                return TimeoutAction::Drop;
+            };
+            match watcher.settled() {
+                Ok(changed) => {
+                    wayper.rescan_changed(&changed);
+                    TimeoutAction::Drop
+                }
+                // still settling, wait for the rest
+                Err(remaining) => TimeoutAction::ToDuration(remaining),
+            }
+        });
+    if let Err(e) = result {
+        tracing::error!("unable to schedule rescan: {e}");
+        if let Some(watcher) = wayper.watcher.as_mut() {
+            watcher.debouncing = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Record the events read so far, like the event loop does
+    fn read(inotify: &mut Inotify, watcher: &mut DirWatcher) {
+        let mut buffer = [0; 4096];
+        loop {
+            match inotify.read_events(&mut buffer) {
+                Ok(events) => {
+                    for event in events {
+                        watcher.record(&event.wd, event.mask, event.name);
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => panic!("unable to read inotify events: {e}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_debounced_changes() {
+        let dir = std::env::temp_dir().join(format!("wayper-watcher-{}", std::process::id()));
+        let (first, second) = (dir.join("first"), dir.join("second"));
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+
+        let mut inotify = Inotify::init().unwrap();
+        let mut watcher = DirWatcher::with_watches(inotify.watches());
+        watcher.watch([first.as_path()]);
+
+        let image = first.join("a.png");
+        std::fs::write(&image, "").unwrap();
+        std::fs::remove_file(&image).unwrap();
+        read(&mut inotify, &mut watcher);
+
+        // nothing until the events settled
+        let remaining = watcher.settled().unwrap_err();
+        assert!(remaining <= DEBOUNCE);
+        std::thread::sleep(remaining);
+        assert_eq!(watcher.settled(), Ok(HashSet::from([image.clone()])));
+        assert_eq!(watcher.settled(), Ok(HashSet::new()));
+
+        // only the new root is watched after the roots changed
+        watcher.watch([second.as_path()]);
+        std::fs::write(&image, "").unwrap();
+        let other = second.join("b.png");
+        std::fs::write(&other, "").unwrap();
+        read(&mut inotify, &mut watcher);
+        std::thread::sleep(DEBOUNCE);
+        assert_eq!(watcher.settled(), Ok(HashSet::from([other])));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
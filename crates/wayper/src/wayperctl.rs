@@ -42,6 +42,24 @@ fn main() -> Result<()> {
         )
     })?;
 
+    // the daemon runs elsewhere, so relative paths have to be resolved here
+    let images = match cli.command {
+        Commands::Socket(SocketCommand::Set { ref mut image, .. }) => vec![image],
+        Commands::Socket(SocketCommand::Enqueue { ref mut images, .. }) => {
+            images.iter_mut().collect()
+        }
+        Commands::Socket(SocketCommand::Dequeue {
+            image: Some(ref mut image),
+            ..
+        }) => vec![image],
+        _ => vec![],
+    };
+    for image in images {
+        if let Ok(absolute) = std::path::absolute(&*image) {
+            *image = absolute;
+        }
+    }
+
     match cli.command {
         Commands::Socket(ref command) => {
             command.write_to_socket(&mut stream)?;
@@ -162,6 +180,126 @@ fn main() -> Result<()> {
                         }
                     }
                 }
+                SocketCommand::Hooks { .. } => {
+                    let replies = SocketOutput::from_socket(&mut stream)?;
+
+                    for reply in replies {
+                        handle_error_from_daemon(&cli, &reply)?;
+                        if let SocketOutput::HookReports(ref reports) = reply {
+                            if cli.json {
+                                println!("{}", reply.to_json()?);
+                            } else if reports.is_empty() {
+                                println!("No hooks have run yet");
+                            } else {
+                                println!("{reply}");
+                            }
+                        } else {
+                            failed_to_get_response()?;
+                        }
+                    }
+                }
+                SocketCommand::Problems => {
+                    let replies = SocketOutput::from_socket(&mut stream)?;
+
+                    for reply in replies {
+                        handle_error_from_daemon(&cli, &reply)?;
+                        if let SocketOutput::Problems(ref problems) = reply {
+                            if cli.json {
+                                println!("{}", reply.to_json()?);
+                            } else if problems.is_empty() {
+                                println!("No problems");
+                            } else {
+                                println!("{reply}");
+                            }
+                        } else {
+                            failed_to_get_response()?;
+                        }
+                    }
+                }
+                SocketCommand::Previous { .. } => {
+                    let replies = SocketOutput::from_socket(&mut stream)?;
+
+                    for reply in replies {
+                        handle_error_from_daemon(&cli, &reply)?;
+                        match reply {
+                            SocketOutput::Message(_) | SocketOutput::MultipleErrors(_)
+                                if cli.json =>
+                            {
+                                println!("{}", reply.to_json()?)
+                            }
+                            SocketOutput::Message(ref msg) => println!("{msg}"),
+                            SocketOutput::MultipleErrors(_) => eprintln!("{reply}"),
+                            _ => failed_to_get_response()?,
+                        }
+                    }
+                }
+                SocketCommand::History { .. } => {
+                    let replies = SocketOutput::from_socket(&mut stream)?;
+
+                    for reply in replies {
+                        handle_error_from_daemon(&cli, &reply)?;
+                        if let SocketOutput::History(ref entries) = reply {
+                            if cli.json {
+                                println!("{}", reply.to_json()?);
+                            } else if entries.is_empty() {
+                                println!("No history yet");
+                            } else {
+                                println!("{reply}");
+                            }
+                        } else {
+                            failed_to_get_response()?;
+                        }
+                    }
+                }
+                SocketCommand::Stats { .. } => {
+                    let replies = SocketOutput::from_socket(&mut stream)?;
+
+                    for reply in replies {
+                        handle_error_from_daemon(&cli, &reply)?;
+                        if let SocketOutput::Stats(_) = reply {
+                            if cli.json {
+                                println!("{}", reply.to_json()?);
+                            } else {
+                                println!("{reply}");
+                            }
+                        } else {
+                            failed_to_get_response()?;
+                        }
+                    }
+                }
+                SocketCommand::Queue { .. } => {
+                    let replies = SocketOutput::from_socket(&mut stream)?;
+
+                    for reply in replies {
+                        handle_error_from_daemon(&cli, &reply)?;
+                        if let SocketOutput::Queue(_) = reply {
+                            if cli.json {
+                                println!("{}", reply.to_json()?);
+                            } else {
+                                println!("{reply}");
+                            }
+                        } else {
+                            failed_to_get_response()?;
+                        }
+                    }
+                }
+                SocketCommand::Palette { .. } => {
+                    let replies = SocketOutput::from_socket(&mut stream)?;
+
+                    for reply in replies {
+                        handle_error_from_daemon(&cli, &reply)?;
+                        match reply {
+                            SocketOutput::Palettes(_) | SocketOutput::MultipleErrors(_)
+                                if cli.json =>
+                            {
+                                println!("{}", reply.to_json()?)
+                            }
+                            SocketOutput::Palettes(_) => println!("{reply}"),
+                            SocketOutput::MultipleErrors(_) => eprintln!("{reply}"),
+                            _ => failed_to_get_response()?,
+                        }
+                    }
+                }
                 // this is also a template for handling commands
                 ref command => {
                     let replies = SocketOutput::from_socket(&mut stream)?;
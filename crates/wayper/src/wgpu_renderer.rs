@@ -1,27 +1,40 @@
 //! The wgpu renderer
 //! TODO: heavily comment this arcane piece of shit
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
     ptr::NonNull,
     sync::{
         atomic::{AtomicU64, Ordering},
         mpsc::{Receiver, Sender},
     },
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use color_eyre::eyre::{OptionExt, eyre};
-use image::{GenericImageView, imageops::FilterType::Lanczos3};
+use image::imageops::FilterType::Lanczos3;
 use raw_window_handle::{
     RawDisplayHandle, RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle,
 };
-use wayper_lib::socket::GpuMetricsData;
+use smithay_client_toolkit::reexports::calloop;
+use wayper_lib::{
+    config::{AnimationConfig, FitMode},
+    fill::{Background, Fill},
+    palette::Palette,
+    socket::GpuMetricsData,
+};
 use wgpu::{naga::FastHashMap, util::DeviceExt};
 
 use crate::{
+    animation::{self, Frame},
+    archive,
+    backgrounds::{self, BackgroundBlur},
+    fills::{FillNodeParams, FillPipeline},
     metered_cache::MeteredCache,
-    scene::{Scene, SceneNode},
+    scene::{ImageNode, Placement, Rect, Scene, SceneNode, TransitionEffect, TransitionNode},
+    shader_wallpaper::ShaderWallpapers,
+    transitions::{TransitionNodeParams, TransitionPipeline},
+    video::{self, VideoPlayers},
 };
 
 #[expect(unused)]
@@ -53,9 +66,12 @@ impl Drop for GpuOperationTimer {
     }
 }
 
-/// A cached texture, with its height and width encoded.
+/// A cached texture, with its height and width encoded. Animated images have a texture per
+/// frame.
 pub struct CachedTexture {
-    texture: wgpu::Texture,
+    frames: Vec<wgpu::Texture>,
+    /// Average color of the edges of the first frame, linear RGBA
+    edge_color: [f32; 4],
     _width: u32,
     _height: u32,
 }
@@ -76,6 +92,25 @@ pub struct WgpuRenderer {
     texture_loader_tx: Sender<TextureLoadRequest>,
     texture_loader_rx: Receiver<TextureLoadResult>,
     in_flight_texture_loads: HashSet<String>,
+    /// The cache key of the images with a texture cached, loading or failed, to evict textures
+    /// of files changed on disk
+    texture_keys: HashMap<PathBuf, String>,
+    /// Cache keys of images that failed to load. Scenes skip them until a load is requested
+    /// again.
+    failed_texture_loads: HashSet<String>,
+    animation_limits: AnimationConfig,
+    /// Videos playing on the outputs
+    videos: VideoPlayers,
+    /// Shaders drawn instead of images
+    shaders: ShaderWallpapers,
+    /// Colors and gradients
+    fills: FillPipeline,
+    /// Shaders blending two images while transitioning between them
+    transitions: TransitionPipeline,
+    /// Blurred copies of images shown around them
+    background_blur: BackgroundBlur,
+    /// Events for the main event loop
+    event_tx: calloop::channel::Sender<RenderEvent>,
 
     /// Total frames rendered for metrics
     pub total_frames_rendered: AtomicU64,
@@ -87,8 +122,12 @@ pub struct WgpuRenderer {
 }
 
 impl WgpuRenderer {
-    /// Create a new instance of the renderer
-    pub fn new() -> (Sender<RenderCommand>, wgpu::Instance) {
+    /// Create a new instance of the renderer. Events from the renderer are sent to `event_tx`.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(
+        event_tx: calloop::channel::Sender<RenderEvent>,
+        animation_limits: AnimationConfig,
+    ) -> (Sender<RenderCommand>, wgpu::Instance) {
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
             // TODO: support for other platforms via winit for debugging
             backends: wgpu::Backends::all(),
@@ -100,8 +139,10 @@ impl WgpuRenderer {
 
         let (command_tx, command_rx) = std::sync::mpsc::channel();
 
+        let loader_event_tx = event_tx.clone();
+        let loader_limits = animation_limits.clone();
         std::thread::spawn(move || {
-            texture_loader_worker(load_rx, result_tx);
+            texture_loader_worker(load_rx, result_tx, loader_event_tx, loader_limits);
         });
 
         // send the instance into a seperate thread
@@ -122,6 +163,15 @@ impl WgpuRenderer {
                 texture_loader_tx: load_tx,
                 texture_loader_rx: result_rx,
                 in_flight_texture_loads: Default::default(),
+                texture_keys: Default::default(),
+                failed_texture_loads: Default::default(),
+                animation_limits,
+                videos: VideoPlayers::default(),
+                shaders: ShaderWallpapers::new(event_tx.clone()),
+                fills: FillPipeline::default(),
+                transitions: TransitionPipeline::new(event_tx.clone()),
+                background_blur: BackgroundBlur::default(),
+                event_tx,
                 total_frames_rendered: AtomicU64::new(0),
                 scene_image_pipeline: None,
                 scene_bind_group_layout: None,
@@ -150,6 +200,7 @@ impl WgpuRenderer {
                     "Processed pre-loaded textures"
                 );
             }
+            self.prune_texture_keys();
 
             // TODO: error handling
             match self.handle_command(command) {
@@ -183,6 +234,9 @@ impl WgpuRenderer {
             } => {
                 self.request_texture_load(image_path.as_path(), output_name)?;
             }
+            RenderCommand::ShaderChanged { shader } => {
+                self.shaders.reload(&shader);
+            }
             RenderCommand::RenderScene { output_name, scene } => {
                 self.render_scene(&output_name, scene)?;
             }
@@ -206,7 +260,19 @@ impl WgpuRenderer {
         image_path: &Path,
         output_name: String,
     ) -> color_eyre::Result<()> {
-        let cache_key = Self::cache_key(image_path);
+        // videos are opened once they are shown, fills are never loaded
+        if video::is_video(image_path) {
+            // an explicit request is a retry
+            self.videos.retry(image_path);
+            return Ok(());
+        }
+        if Fill::is_fill(image_path) {
+            return Ok(());
+        }
+
+        let cache_key = self.refresh_texture_key(image_path);
+        // an explicit request is a retry
+        self.failed_texture_loads.remove(&cache_key);
 
         if self.texture_cache.contains(&cache_key)
             || self.in_flight_texture_loads.contains(&cache_key)
@@ -221,15 +287,15 @@ impl WgpuRenderer {
             8192
         };
 
-        self.in_flight_texture_loads.insert(cache_key);
+        self.in_flight_texture_loads.insert(cache_key.clone());
 
         if let Err(e) = self.texture_loader_tx.send(TextureLoadRequest {
             image_path: image_path.to_path_buf(),
+            cache_key: cache_key.clone(),
             output_name,
             max_2d,
         }) {
-            self.in_flight_texture_loads
-                .remove(&Self::cache_key(image_path));
+            self.in_flight_texture_loads.remove(&cache_key);
             return Err(e.into());
         }
 
@@ -251,14 +317,16 @@ impl WgpuRenderer {
         let mut count = 0;
 
         while let Ok(result) = self.texture_loader_rx.try_recv() {
-            let (cache_key, image_data, dimensions) = match result {
-                TextureLoadResult::Loaded {
+            let (cache_key, frames) = match result {
+                TextureLoadResult::Loaded { cache_key, frames } => (cache_key, frames),
+                TextureLoadResult::Failed {
                     cache_key,
-                    image_data,
-                    dimensions,
-                } => (cache_key, image_data, dimensions),
-                TextureLoadResult::Failed { cache_key } => {
+                    image_path,
+                    error,
+                } => {
                     self.in_flight_texture_loads.remove(&cache_key);
+                    self.failed_texture_loads.insert(cache_key);
+                    self.send_event(RenderEvent::TextureLoadFailed { image_path, error });
                     continue;
                 }
             };
@@ -269,15 +337,110 @@ impl WgpuRenderer {
                 continue;
             }
 
-            let texture_size = wgpu::Extent3d {
-                width: dimensions.0,
-                height: dimensions.1,
-                depth_or_array_layers: 1,
-            };
+            let (width, height) = frames[0].image.dimensions();
+            let size_bytes = Self::texture_size_bytes(width, height) * frames.len() as u64;
+            let textures = create_frame_textures(device, queue, &frames);
+            let edge_color = backgrounds::edge_color(&frames[0].image);
+            self.texture_cache
+                .get_or_insert(cache_key, size_bytes, || CachedTexture {
+                    frames: textures,
+                    edge_color,
+                    _width: width,
+                    _height: height,
+                });
+            count += 1;
+        }
 
+        Ok(count)
+    }
+}
+
+/// Pipeline drawing a scene node on a quad, with the vertex and fragment entry points of its shader
+pub fn scene_node_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    shader: &wgpu::ShaderModule,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    surface_format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(&format!("{label} Pipeline Layout")),
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    // Create render pipeline
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(&format!("{label} Render Pipeline")),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &[
+                    wgpu::VertexAttribute {
+                        offset: 0,
+                        shader_location: 0,
+                        format: wgpu::VertexFormat::Float32x2,
+                    },
+                    wgpu::VertexAttribute {
+                        offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                        shader_location: 1,
+                        format: wgpu::VertexFormat::Float32x2,
+                    },
+                ],
+            }],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Upload the frames of an image, a texture per frame
+fn create_frame_textures(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    frames: &[Frame],
+) -> Vec<wgpu::Texture> {
+    frames
+        .iter()
+        .map(|frame| {
+            let (width, height) = frame.image.dimensions();
             let texture = device.create_texture(&wgpu::TextureDescriptor {
                 label: Some("image texture"),
-                size: texture_size,
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
@@ -285,21 +448,10 @@ impl WgpuRenderer {
                 usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
                 view_formats: &[],
             });
-
-            write_texture_rgba8_padded(queue, &texture, dimensions.0, dimensions.1, &image_data);
-
-            let size_bytes = Self::texture_size_bytes(dimensions.0, dimensions.1);
-            self.texture_cache
-                .get_or_insert(cache_key, size_bytes, || CachedTexture {
-                    texture,
-                    _width: dimensions.0,
-                    _height: dimensions.1,
-                });
-            count += 1;
-        }
-
-        Ok(count)
-    }
+            write_texture_rgba8_padded(queue, &texture, width, height, &frame.image);
+            texture
+        })
+        .collect()
 }
 
 fn write_texture_rgba8_padded(
@@ -312,7 +464,7 @@ fn write_texture_rgba8_padded(
     let bytes_per_pixel = 4u32;
     let unpadded_bpr = width * bytes_per_pixel;
     let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
-    let padded_bpr = ((unpadded_bpr + align - 1) / align) * align;
+    let padded_bpr = unpadded_bpr.div_ceil(align) * align;
 
     if padded_bpr == unpadded_bpr {
         queue.write_texture(
@@ -368,10 +520,13 @@ fn write_texture_rgba8_padded(
     );
 }
 
-/// Texture loader worker ran in a seperate thread. Results are pushed to the results channel
+/// Texture loader worker ran in a seperate thread. Results are pushed to the results channel,
+/// palettes of the decoded images go straight to the main event loop.
 fn texture_loader_worker(
     load_rx: Receiver<TextureLoadRequest>,
     result_tx: Sender<TextureLoadResult>,
+    event_tx: calloop::channel::Sender<RenderEvent>,
+    animation_limits: AnimationConfig,
 ) {
     while let Ok(request) = load_rx.recv() {
         let span = tracing::span!(
@@ -384,53 +539,104 @@ fn texture_loader_worker(
         let _enter = span.enter();
 
         let load_start = Instant::now();
-        match load_image_rgba(&request.image_path, request.max_2d) {
-            Ok((image_data, dimensions)) => {
+        match load_image_frames(&request.image_path, request.max_2d, &animation_limits) {
+            Ok(frames) => {
                 let load_time = load_start.elapsed();
+                let (width, height) = frames[0].image.dimensions();
                 tracing::debug!(
                     time_ms = load_time.as_millis(),
-                    dimensions = format!("{}x{}", dimensions.0, dimensions.1),
+                    dimensions = format!("{width}x{height}"),
+                    frames = frames.len(),
                     "Image loaded"
                 );
-                let cache_key = WgpuRenderer::cache_key(&request.image_path);
+                for event in decoded_events(&request.image_path, &frames) {
+                    if let Err(e) = event_tx.send(event) {
+                        tracing::error!("unable to send render event: {e}");
+                    }
+                }
 
                 let _ = result_tx.send(TextureLoadResult::Loaded {
-                    cache_key,
-                    image_data,
-                    dimensions,
+                    cache_key: request.cache_key,
+                    frames,
                 });
             }
             Err(e) => {
                 tracing::error!("Failed to load image: {}", e);
                 let _ = result_tx.send(TextureLoadResult::Failed {
-                    cache_key: WgpuRenderer::cache_key(&request.image_path),
+                    cache_key: request.cache_key,
+                    error: e.to_string(),
+                    image_path: request.image_path,
                 });
             }
         }
     }
 }
 
-/// Load an image from a path as RGBA pixels at its original dimensions.
-fn load_image_rgba(
+/// The events for the main event loop about a freshly decoded image: its palette, from the first
+/// frame, and the delays of its frames if it is animated
+fn decoded_events(image_path: &Path, frames: &[Frame]) -> Vec<RenderEvent> {
+    let start = Instant::now();
+    let palette = Palette::from_rgba(frames[0].image.as_raw());
+    tracing::debug!(
+        time_ms = start.elapsed().as_millis(),
+        accent = ?palette.as_ref().map(|palette| palette.accent.to_hex()),
+        "Palette extracted"
+    );
+    let mut events = vec![RenderEvent::PaletteReady {
+        image_path: image_path.to_path_buf(),
+        palette,
+    }];
+    if frames.len() > 1 {
+        events.push(RenderEvent::AnimationLoaded {
+            image_path: image_path.to_path_buf(),
+            delays: frames.iter().map(|frame| frame.delay).collect(),
+        });
+    }
+    events
+}
+
+/// Load an image from a path as RGBA pixels at its original dimensions, every frame of it if it
+/// is animated.
+fn load_image_frames(
     image_path: &Path,
     max_2d: u32,
-) -> color_eyre::Result<(image::RgbaImage, (u32, u32))> {
-    let img = {
-        let mut img = image::open(image_path)?;
-        let dim = img.dimensions();
-        if dim.0 > max_2d || dim.1 > max_2d {
-            img = img.resize(max_2d, max_2d, Lanczos3);
-            tracing::debug!(
-                "image {} is too big, resized to bounds {}",
-                image_path.display(),
-                max_2d
-            );
+    animation_limits: &AnimationConfig,
+) -> color_eyre::Result<Vec<Frame>> {
+    let bytes = match archive::split(image_path) {
+        Some(_) => archive::read(image_path)?,
+        None => std::fs::read(image_path)?,
+    };
+    let frames = match animation::decode_frames(image_path, &bytes, animation_limits)? {
+        Some(frames) => frames,
+        None => {
+            // by content, the extension may be missing or wrong
+            let image = image::ImageReader::new(std::io::Cursor::new(&bytes))
+                .with_guessed_format()?
+                .decode()?;
+            vec![Frame {
+                image: image.to_rgba8(),
+                delay: Duration::ZERO,
+            }]
         }
-        img
     };
-    let rgba = img.to_rgba8();
-    let dimensions = rgba.dimensions();
-    Ok((rgba, dimensions))
+
+    Ok(frames
+        .into_iter()
+        .map(|mut frame| {
+            let (width, height) = frame.image.dimensions();
+            if width > max_2d || height > max_2d {
+                frame.image = image::DynamicImage::ImageRgba8(frame.image)
+                    .resize(max_2d, max_2d, Lanczos3)
+                    .to_rgba8();
+                tracing::debug!(
+                    "image {} is too big, resized to bounds {}",
+                    image_path.display(),
+                    max_2d
+                );
+            }
+            frame
+        })
+        .collect())
 }
 
 impl WgpuRenderer {
@@ -515,66 +721,13 @@ impl WgpuRenderer {
             ],
         });
 
-        // Create pipeline layout
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Scene Image Pipeline Layout"),
-            bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
-        });
-
-        // Create render pipeline
-        let image_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Scene Image Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &[
-                        wgpu::VertexAttribute {
-                            offset: 0,
-                            shader_location: 0,
-                            format: wgpu::VertexFormat::Float32x2,
-                        },
-                        wgpu::VertexAttribute {
-                            offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
-                            shader_location: 1,
-                            format: wgpu::VertexFormat::Float32x2,
-                        },
-                    ],
-                }],
-                compilation_options: Default::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: Default::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
-        });
+        let image_pipeline = scene_node_pipeline(
+            device,
+            "Scene Image",
+            &shader,
+            &bind_group_layout,
+            surface_format,
+        );
 
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -620,9 +773,56 @@ impl WgpuRenderer {
         Ok(())
     }
 
-    /// Generate cache key from image path and target size
-    fn cache_key(image_path: &Path) -> String {
-        format!("{}", image_path.display(),)
+    /// Generate cache key from the image path and the file's modification time and size, so a
+    /// file changed in place gets a new key. Images in an archive use those of the archive.
+    pub fn cache_key(image_path: &Path) -> String {
+        match std::fs::metadata(archive::on_disk(image_path)) {
+            Ok(metadata) => {
+                let modified = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|modified| modified.as_nanos())
+                    .unwrap_or_default();
+                format!("{}@{modified}:{}", image_path.display(), metadata.len())
+            }
+            Err(_) => image_path.display().to_string(),
+        }
+    }
+
+    /// The cache key of an image, looked up on disk the first time. A file changed on disk gets
+    /// its new key when a load of it is requested.
+    fn texture_key(&mut self, image_path: &Path) -> String {
+        match self.texture_keys.get(image_path) {
+            Some(cache_key) => cache_key.clone(),
+            None => self.refresh_texture_key(image_path),
+        }
+    }
+
+    /// The cache key of an image from the file on disk. If the file changed since the last time,
+    /// its stale texture is evicted.
+    fn refresh_texture_key(&mut self, image_path: &Path) -> String {
+        let cache_key = Self::cache_key(image_path);
+        if let Some(stale) = self
+            .texture_keys
+            .insert(image_path.to_path_buf(), cache_key.clone())
+            && stale != cache_key
+        {
+            tracing::info!("{} changed on disk", image_path.display());
+            self.texture_cache.remove(&stale);
+            self.failed_texture_loads.remove(&stale);
+        }
+        cache_key
+    }
+
+    /// Forget the keys of images evicted from the texture cache, so there are never more of them
+    /// than textures cached, loading or failed
+    fn prune_texture_keys(&mut self) {
+        self.texture_keys.retain(|_, cache_key| {
+            self.texture_cache.contains(cache_key)
+                || self.in_flight_texture_loads.contains(cache_key)
+                || self.failed_texture_loads.contains(cache_key)
+        });
     }
 
     /// Calculate memory size of a texture in bytes (RGBA8 = 4 bytes per pixel)
@@ -638,6 +838,8 @@ impl WgpuRenderer {
         image_path: &Path,
         target_size: (u32, u32),
     ) -> color_eyre::Result<&CachedTexture> {
+        let cache_key = self.texture_key(image_path);
+
         let device = self
             .device
             .as_ref()
@@ -647,8 +849,6 @@ impl WgpuRenderer {
             .as_ref()
             .ok_or_else(|| color_eyre::eyre::eyre!("Queue not initialized"))?;
 
-        let cache_key = Self::cache_key(image_path);
-
         // Check if texture is already cached
         if self.texture_cache.contains(&cache_key) {
             tracing::trace!("Texture cache hit");
@@ -656,56 +856,52 @@ impl WgpuRenderer {
             return Ok(self.texture_cache.get(&cache_key).unwrap());
         }
 
+        // reported already, don't decode it again on every frame
+        if self.failed_texture_loads.contains(&cache_key) {
+            return Err(eyre!("{} failed to load", image_path.display()));
+        }
+
         tracing::trace!("Texture cache miss - loading from disk");
 
         let max_2d = device.limits().max_texture_dimension_2d;
-        let (rgba, (img_width, img_height)) = load_image_rgba(image_path, max_2d)?;
-
-        let texture_size = wgpu::Extent3d {
-            width: img_width,
-            height: img_height,
-            depth_or_array_layers: 1,
+        let frames = match load_image_frames(image_path, max_2d, &self.animation_limits) {
+            Ok(frames) => frames,
+            Err(e) => {
+                self.failed_texture_loads.insert(cache_key);
+                self.send_event(RenderEvent::TextureLoadFailed {
+                    image_path: image_path.to_path_buf(),
+                    error: e.to_string(),
+                });
+                return Err(e);
+            }
         };
+        for event in decoded_events(image_path, &frames) {
+            self.send_event(event);
+        }
 
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Image Texture"),
-            size: texture_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-
-        // Write the image data to the texture
-        queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &rgba,
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * img_width),
-                rows_per_image: Some(img_height),
-            },
-            texture_size,
-        );
+        let (img_width, img_height) = frames[0].image.dimensions();
+        let textures = create_frame_textures(device, queue, &frames);
+        let edge_color = backgrounds::edge_color(&frames[0].image);
 
         // Cache the texture with size tracking
-        let size_bytes = Self::texture_size_bytes(img_width, img_height);
+        let size_bytes = Self::texture_size_bytes(img_width, img_height) * frames.len() as u64;
         Ok(self
             .texture_cache
             .get_or_insert(cache_key, size_bytes, || CachedTexture {
-                texture,
+                frames: textures,
+                edge_color,
                 _width: img_width,
                 _height: img_height,
             }))
     }
 
+    /// Send an event to the main event loop
+    fn send_event(&self, event: RenderEvent) {
+        if let Err(e) = self.event_tx.send(event) {
+            tracing::error!("unable to send render event: {e}");
+        }
+    }
+
     /// Log cache metrics for monitoring and debugging
     fn log_cache_metrics(&self) {
         let tex_metrics = self.texture_cache.metrics();
@@ -771,8 +967,131 @@ impl WgpuRenderer {
         Ok(surface_format)
     }
 
-    /// Render a scene
-    fn render_scene(&mut self, output_name: &str, scene: Scene) -> color_eyre::Result<()> {
+    /// Upload the frames due of the videos in a scene, and stop the ones the output doesn't show
+    /// anymore. Videos that fail to play are left out.
+    fn prepare_videos(&mut self, output_name: &str, scene: &mut Scene) -> color_eyre::Result<()> {
+        let device = self.device.as_ref().ok_or_eyre("Device not initialized")?;
+        let queue = self.queue.as_ref().ok_or_eyre("Queue not initialized")?;
+        let surface_format = self
+            .surface_configs
+            .get(output_name)
+            .ok_or_else(|| eyre!("Surface config not found for output: {output_name}"))?
+            .format;
+
+        let mut left_out = vec![];
+        for node in &scene.nodes {
+            let SceneNode::Image(image_node) = node else {
+                continue;
+            };
+            if !video::is_video(&image_node.image_path) {
+                continue;
+            }
+            let prepared = self.videos.prepare(
+                device,
+                queue,
+                surface_format,
+                output_name,
+                &image_node.image_path,
+                image_node.position,
+            );
+            match prepared {
+                Ok(Some(preview)) => self.send_event(RenderEvent::PaletteReady {
+                    image_path: image_node.image_path.clone(),
+                    palette: Palette::from_rgba(preview.as_raw()),
+                }),
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::debug!("leaving out video node: {e}");
+                    self.send_event(RenderEvent::TextureLoadFailed {
+                        image_path: image_node.image_path.clone(),
+                        error: e.to_string(),
+                    });
+                }
+            }
+            // nothing to draw until the first frame is decoded, or after the video failed
+            if self
+                .videos
+                .bind_group_key(output_name, &image_node.image_path)
+                .is_none()
+            {
+                left_out.push(image_node.image_path.clone());
+            }
+        }
+        scene.nodes.retain(|node| match node {
+            SceneNode::Image(image_node) => !left_out.contains(&image_node.image_path),
+            SceneNode::Shader(_) | SceneNode::Transition(_) => true,
+        });
+
+        let shown = scene
+            .nodes
+            .iter()
+            .filter_map(|node| match node {
+                SceneNode::Image(image_node) => Some(image_node.image_path.as_path()),
+                SceneNode::Shader(_) | SceneNode::Transition(_) => None,
+            })
+            .collect::<Vec<_>>();
+        self.videos.retain(output_name, &shown);
+        Ok(())
+    }
+
+    /// Whether a transition shader can draw an image. It needs its texture, and no gradient or
+    /// blurred background behind it.
+    fn can_blend(&mut self, image_node: &ImageNode, target_size: (u32, u32)) -> bool {
+        if video::is_video(&image_node.image_path)
+            || Fill::is_fill(&image_node.image_path)
+            || matches!(
+                image_node.backdrop,
+                Some(Background::Fill(_) | Background::Blur)
+            )
+        {
+            return false;
+        }
+        match self.load_image_texture(&image_node.image_path, target_size) {
+            Ok(_) => true,
+            Err(e) => {
+                tracing::debug!("crossfading instead: {e}");
+                false
+            }
+        }
+    }
+
+    /// Whether a transition shader can draw a transition, with its images and its custom shader
+    fn can_draw_transition(
+        &mut self,
+        output_name: &str,
+        transition: &TransitionNode,
+        target_size: (u32, u32),
+    ) -> bool {
+        let images = transition
+            .from
+            .iter()
+            .chain([&transition.to])
+            .all(|image_node| self.can_blend(image_node, target_size));
+        let TransitionEffect::Shader {
+            shader,
+            params,
+            version,
+        } = &transition.effect
+        else {
+            return images;
+        };
+        let (Some(device), Some(surface_config)) =
+            (self.device.as_ref(), self.surface_configs.get(output_name))
+        else {
+            return false;
+        };
+        images
+            && self.transitions.prepare_shader(
+                device,
+                surface_config.format,
+                shader,
+                params,
+                version,
+            )
+    }
+
+    /// Render a scene. Image nodes that fail to load are left out.
+    fn render_scene(&mut self, output_name: &str, mut scene: Scene) -> color_eyre::Result<()> {
         // TODO: pain
 
         // get the target size
@@ -783,14 +1102,59 @@ impl WgpuRenderer {
             (surface_config.width, surface_config.height)
         };
 
-        // ensure required textures are loaded
-        for node in &scene.nodes {
+        // transitions the shader can't draw fall back to a crossfade
+        let mut nodes = Vec::with_capacity(scene.nodes.len());
+        for node in std::mem::take(&mut scene.nodes) {
             match node {
-                SceneNode::Image(image_node) => {
-                    self.load_image_texture(&image_node.image_path, target_size)?;
+                SceneNode::Transition(transition)
+                    if !self.can_draw_transition(output_name, &transition, target_size) =>
+                {
+                    nodes.extend(transition.crossfade());
                 }
+                node => nodes.push(node),
             }
         }
+        scene.nodes = nodes;
+
+        self.prepare_videos(output_name, &mut scene)?;
+        if !scene
+            .nodes
+            .iter()
+            .any(|node| matches!(node, SceneNode::Shader(_)))
+        {
+            self.shaders.forget(output_name);
+        }
+
+        // ensure required textures are loaded
+        scene.nodes.retain(|node| match node {
+            SceneNode::Image(image_node)
+                if video::is_video(&image_node.image_path)
+                    || Fill::is_fill(&image_node.image_path) =>
+            {
+                true
+            }
+            SceneNode::Image(image_node) => {
+                match self.load_image_texture(&image_node.image_path, target_size) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        tracing::debug!("leaving out image node: {e}");
+                        false
+                    }
+                }
+            }
+            // a shader is drawn without its image until it loads, videos aren't handed to it
+            SceneNode::Shader(shader_node) => {
+                if let Some(image_path) = &shader_node.image
+                    && !video::is_video(image_path)
+                    && let Err(e) = self.load_image_texture(image_path, target_size)
+                {
+                    tracing::debug!("drawing shader without its image: {e}");
+                }
+                true
+            }
+            // its images were loaded above
+            SceneNode::Transition(_) => true,
+        });
 
         let device = self.device.as_ref().ok_or_eyre("Device not initialized")?;
         let surface = self
@@ -815,57 +1179,285 @@ impl WgpuRenderer {
             .create_view(&wgpu::TextureViewDescriptor::default());
 
         let queue = self.queue.as_ref().ok_or_eyre("Queue not initialized")?;
+        let surface_format = self
+            .surface_configs
+            .get(output_name)
+            .ok_or_else(|| eyre!("Surface config not found for output: {output_name}"))?
+            .format;
 
-        // prepare the draw
+        // prepare the draw, every draw but shaders takes the next buffer slot
         let mut prepared_draws = vec![];
-        for (i, node) in scene.nodes.iter().enumerate() {
+        for node in &scene.nodes {
+            let i = prepared_draws.len();
+            // a gradient or blurred background is drawn behind the image, its bars are left clear
+            let mut background = scene.background;
+            match node {
+                SceneNode::Image(ImageNode {
+                    backdrop: Some(Background::Fill(backdrop)),
+                    rect,
+                    opacity,
+                    ..
+                }) => {
+                    prepare_fill_slot(
+                        self.scene_buffer_pool.get_buffer_mut(device, i),
+                        &mut self.fills,
+                        device,
+                        queue,
+                        surface_format,
+                        &FillNodeParams::new(backdrop, *rect, target_size, *opacity),
+                    );
+                    prepared_draws.push(PreparedDraw::Fill { slot_index: i });
+                    background = [0.0; 4];
+                }
+                // videos and fills have no texture to take a background from
+                SceneNode::Image(
+                    image_node @ ImageNode {
+                        backdrop: Some(backdrop @ (Background::Auto | Background::Blur)),
+                        ..
+                    },
+                ) => {
+                    let texture =
+                        self.texture_keys
+                            .get(&image_node.image_path)
+                            .and_then(|cache_key| {
+                                Some((cache_key, self.texture_cache.peek(cache_key)?))
+                            });
+                    match (backdrop, texture) {
+                        (Background::Auto, Some((_, texture))) => {
+                            background = texture.edge_color;
+                        }
+                        (Background::Blur, Some((cache_key, texture))) => {
+                            let blurred = self.background_blur.blurred(
+                                device,
+                                queue,
+                                sampler,
+                                cache_key,
+                                &texture.frames[0],
+                                target_size,
+                                image_node.placement.rotation,
+                            );
+                            let slot = self.scene_buffer_pool.get_buffer_mut(device, i);
+                            let blur_params = ImageNodeParams::new(
+                                image_node.rect,
+                                target_size,
+                                image_node.opacity,
+                                FitMode::Stretch,
+                                Placement::default(),
+                                [0.0; 4],
+                            );
+                            queue.write_buffer(&slot.buffer, 0, bytemuck::bytes_of(&blur_params));
+                            let blur_key = format!(
+                                "{cache_key}#blur{}x{}@{}",
+                                target_size.0, target_size.1, image_node.placement.rotation
+                            );
+                            if slot.cache_key.as_ref() != Some(&blur_key) {
+                                let blurred_view =
+                                    blurred.create_view(&wgpu::TextureViewDescriptor::default());
+                                slot.bind_group = Some(create_image_bind_group(
+                                    device,
+                                    image_bg_layout,
+                                    sampler,
+                                    &blurred_view,
+                                    &slot.buffer,
+                                ));
+                                slot.cache_key = Some(blur_key);
+                            }
+                            prepared_draws.push(PreparedDraw::Image { slot_index: i });
+                            background = [0.0; 4];
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+            let i = prepared_draws.len();
+
             match node {
+                SceneNode::Image(image_node) if Fill::is_fill(&image_node.image_path) => {
+                    let Some(fill) = Fill::from_path(&image_node.image_path) else {
+                        tracing::debug!(
+                            "leaving out invalid fill {}",
+                            image_node.image_path.display()
+                        );
+                        continue;
+                    };
+                    prepare_fill_slot(
+                        self.scene_buffer_pool.get_buffer_mut(device, i),
+                        &mut self.fills,
+                        device,
+                        queue,
+                        surface_format,
+                        &FillNodeParams::new(
+                            &fill,
+                            image_node.rect,
+                            target_size,
+                            image_node.opacity,
+                        ),
+                    );
+                    prepared_draws.push(PreparedDraw::Fill { slot_index: i });
+                }
+                SceneNode::Image(image_node) if video::is_video(&image_node.image_path) => {
+                    let slot = self.scene_buffer_pool.get_buffer_mut(device, i);
+                    let video_params = ImageNodeParams::new(
+                        image_node.rect,
+                        target_size,
+                        image_node.opacity,
+                        image_node.fit,
+                        image_node.placement,
+                        background,
+                    );
+                    queue.write_buffer(&slot.buffer, 0, bytemuck::bytes_of(&video_params));
+
+                    let key = self
+                        .videos
+                        .bind_group_key(output_name, &image_node.image_path);
+                    if slot.cache_key != key {
+                        slot.bind_group = Some(self.videos.create_bind_group(
+                            device,
+                            output_name,
+                            &image_node.image_path,
+                            sampler,
+                            &slot.buffer,
+                        )?);
+                        slot.cache_key = key;
+                    }
+
+                    prepared_draws.push(PreparedDraw::Video { slot_index: i });
+                }
                 SceneNode::Image(image_node) => {
-                    let cache_key = Self::cache_key(&image_node.image_path);
+                    // the key the texture was just loaded with
+                    let cache_key = self
+                        .texture_keys
+                        .get(&image_node.image_path)
+                        .cloned()
+                        .unwrap_or_else(|| Self::cache_key(&image_node.image_path));
+                    let frames = &self
+                        .texture_cache
+                        .peek(&cache_key)
+                        .ok_or_else(|| eyre!("texture not found in cache: {}", cache_key))?
+                        .frames;
+                    let frame = image_node.frame % frames.len();
+                    let texture_view =
+                        frames[frame].create_view(&wgpu::TextureViewDescriptor::default());
+                    let frame_key = format!("{cache_key}#{frame}");
 
                     let slot = self.scene_buffer_pool.get_buffer_mut(device, i);
-                    let image_params = ImageNodeParams {
-                        rect: image_node.rect.as_array(),
-                        output_size: [target_size.0 as f32, target_size.1 as f32],
-                        opacity: image_node.opacity,
-                        fit_mode: image_node.fit.as_shader_u32(),
-                        background: scene.background,
-                    };
+                    let image_params = ImageNodeParams::new(
+                        image_node.rect,
+                        target_size,
+                        image_node.opacity,
+                        image_node.fit,
+                        image_node.placement,
+                        background,
+                    );
 
                     queue.write_buffer(&slot.buffer, 0, bytemuck::bytes_of(&image_params));
 
-                    if slot.image_path.as_ref() != Some(&image_node.image_path) {
-                        let texture = &self
+                    if slot.cache_key.as_ref() != Some(&frame_key) {
+                        slot.bind_group = Some(create_image_bind_group(
+                            device,
+                            image_bg_layout,
+                            sampler,
+                            &texture_view,
+                            &slot.buffer,
+                        ));
+                        slot.cache_key = Some(frame_key);
+                    }
+
+                    prepared_draws.push(PreparedDraw::Image { slot_index: i });
+                }
+                SceneNode::Shader(shader_node) => {
+                    let image = shader_node.image.as_ref().and_then(|image_path| {
+                        let cache_key = self.texture_keys.get(image_path)?;
+                        let texture = self.texture_cache.peek(cache_key)?;
+                        Some((cache_key.as_str(), &texture.frames[0]))
+                    });
+                    let prepared = self.shaders.prepare(
+                        device,
+                        queue,
+                        surface_format,
+                        output_name,
+                        shader_node,
+                        target_size,
+                        image,
+                        sampler,
+                    );
+                    match prepared {
+                        Ok(()) => prepared_draws.push(PreparedDraw::Shader {
+                            shader_path: shader_node.shader_path.clone(),
+                        }),
+                        Err(e) => tracing::debug!("leaving out shader node: {e}"),
+                    }
+                }
+                SceneNode::Transition(transition) => {
+                    // the frame of an image with the parameters it would be drawn with alone
+                    let texture = |image_node: &ImageNode| {
+                        let cache_key = self
+                            .texture_keys
+                            .get(&image_node.image_path)
+                            .cloned()
+                            .unwrap_or_else(|| Self::cache_key(&image_node.image_path));
+                        let texture = self
                             .texture_cache
                             .peek(&cache_key)
-                            .ok_or_else(|| eyre!("texture not found in cache: {}", cache_key))?
-                            .texture;
-                        let texture_view =
-                            texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-                        slot.bind_group =
-                            Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
-                                label: Some("image node bg"),
-                                layout: image_bg_layout,
-                                entries: &[
-                                    wgpu::BindGroupEntry {
-                                        binding: 0,
-                                        resource: wgpu::BindingResource::Sampler(sampler),
-                                    },
-                                    wgpu::BindGroupEntry {
-                                        binding: 1,
-                                        resource: wgpu::BindingResource::TextureView(&texture_view),
-                                    },
-                                    wgpu::BindGroupEntry {
-                                        binding: 2,
-                                        resource: slot.buffer.as_entire_binding(),
-                                    },
-                                ],
-                            }));
-                        slot.image_path = Some(image_node.image_path.clone());
+                            .ok_or_else(|| eyre!("texture not found in cache: {}", cache_key))?;
+                        let frame = image_node.frame % texture.frames.len();
+                        let background = match image_node.backdrop {
+                            Some(Background::Auto) => texture.edge_color,
+                            _ => scene.background,
+                        };
+                        let params = ImageNodeParams::new(
+                            image_node.rect,
+                            target_size,
+                            1.0,
+                            image_node.fit,
+                            image_node.placement,
+                            background,
+                        );
+                        let view = texture.frames[frame]
+                            .create_view(&wgpu::TextureViewDescriptor::default());
+                        color_eyre::Result::<_>::Ok((format!("{cache_key}#{frame}"), view, params))
+                    };
+                    let (to_key, to_view, to_params) = texture(&transition.to)?;
+                    let from = transition.from.as_ref().map(texture).transpose()?;
+
+                    let slot = self.scene_buffer_pool.get_buffer_mut(device, i);
+                    let transition_params = TransitionNodeParams::new(
+                        transition,
+                        target_size,
+                        from.as_ref().map(|(_, _, params)| *params),
+                        to_params,
+                    );
+                    queue.write_buffer(&slot.buffer, 0, bytemuck::bytes_of(&transition_params));
+                    if let TransitionEffect::Shader { params, .. } = &transition.effect {
+                        self.transitions.write_params(queue, params);
                     }
 
-                    prepared_draws.push(PreparedDraw::Image { slot_index: i });
+                    let key = format!(
+                        "transition:{}|{to_key}",
+                        from.as_ref().map_or("", |(key, _, _)| key.as_str())
+                    );
+                    if slot.cache_key.as_ref() != Some(&key) {
+                        let from_view = from.as_ref().map_or(&to_view, |(_, view, _)| view);
+                        slot.bind_group = Some(self.transitions.create_bind_group(
+                            device,
+                            surface_format,
+                            sampler,
+                            from_view,
+                            &to_view,
+                            &slot.buffer,
+                        ));
+                        slot.cache_key = Some(key);
+                    }
+
+                    let shader = match &transition.effect {
+                        TransitionEffect::Shader { shader, .. } => Some(shader.clone()),
+                        _ => None,
+                    };
+                    prepared_draws.push(PreparedDraw::Transition {
+                        slot_index: i,
+                        shader,
+                    });
                 }
             }
         }
@@ -910,6 +1502,57 @@ impl WgpuRenderer {
 
             for prep in prepared_draws {
                 match prep {
+                    PreparedDraw::Video { slot_index } => {
+                        let slot = self.scene_buffer_pool.get_buffer(slot_index);
+                        let bind_group = slot
+                            .bind_group
+                            .as_ref()
+                            .ok_or_eyre("slot bind group does not exist")?;
+                        let video_pipeline = self
+                            .videos
+                            .pipeline()
+                            .ok_or_eyre("scene video node pipeline not initialized")?;
+                        render_pass.set_pipeline(video_pipeline);
+                        render_pass.set_bind_group(0, bind_group, &[]);
+                        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                        render_pass
+                            .set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                        render_pass.draw_indexed(0..6, 0, 0..1);
+                    }
+                    PreparedDraw::Fill { slot_index } => {
+                        let slot = self.scene_buffer_pool.get_buffer(slot_index);
+                        let bind_group = slot
+                            .bind_group
+                            .as_ref()
+                            .ok_or_eyre("slot bind group does not exist")?;
+                        let fill_pipeline = self
+                            .fills
+                            .pipeline()
+                            .ok_or_eyre("scene fill node pipeline not initialized")?;
+                        render_pass.set_pipeline(fill_pipeline);
+                        render_pass.set_bind_group(0, bind_group, &[]);
+                        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                        render_pass
+                            .set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                        render_pass.draw_indexed(0..6, 0, 0..1);
+                    }
+                    PreparedDraw::Transition { slot_index, shader } => {
+                        let slot = self.scene_buffer_pool.get_buffer(slot_index);
+                        let bind_group = slot
+                            .bind_group
+                            .as_ref()
+                            .ok_or_eyre("slot bind group does not exist")?;
+                        let transition_pipeline = self
+                            .transitions
+                            .pipeline(shader.as_deref())
+                            .ok_or_eyre("scene transition node pipeline not initialized")?;
+                        render_pass.set_pipeline(transition_pipeline);
+                        render_pass.set_bind_group(0, bind_group, &[]);
+                        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                        render_pass
+                            .set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                        render_pass.draw_indexed(0..6, 0, 0..1);
+                    }
                     PreparedDraw::Image { slot_index } => {
                         let slot = self.scene_buffer_pool.get_buffer(slot_index);
                         let bind_group = slot
@@ -923,6 +1566,19 @@ impl WgpuRenderer {
                             .set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
                         render_pass.draw_indexed(0..6, 0, 0..1);
                     }
+                    PreparedDraw::Shader { shader_path } => {
+                        let shader_pipeline = self
+                            .shaders
+                            .pipeline(&shader_path)
+                            .ok_or_eyre("shader pipeline not compiled")?;
+                        let bind_group = self
+                            .shaders
+                            .bind_group(output_name)
+                            .ok_or_eyre("shader bind group does not exist")?;
+                        render_pass.set_pipeline(shader_pipeline);
+                        render_pass.set_bind_group(0, bind_group, &[]);
+                        render_pass.draw(0..3, 0..1);
+                    }
                 }
             }
         }
@@ -954,16 +1610,46 @@ impl WgpuRenderer {
 
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
-struct ImageNodeParams {
+pub struct ImageNodeParams {
     rect: [f32; 4],
     output_size: [f32; 2],
     opacity: f32,
     fit_mode: u32,
     background: [f32; 4],
+    align: [f32; 2],
+    focus: [f32; 2],
+    zoom: f32,
+    rotation: u32,
+    _pad: [f32; 2],
+}
+
+impl ImageNodeParams {
+    pub fn new(
+        rect: Rect,
+        output_size: (u32, u32),
+        opacity: f32,
+        fit: FitMode,
+        placement: Placement,
+        background: [f32; 4],
+    ) -> Self {
+        Self {
+            rect: rect.as_array(),
+            output_size: [output_size.0 as f32, output_size.1 as f32],
+            opacity,
+            fit_mode: fit.as_shader_u32(),
+            background,
+            align: placement.align,
+            focus: placement.focus.unwrap_or([-1.0; 2]),
+            zoom: placement.zoom,
+            rotation: placement.rotation,
+            _pad: [0.0; 2],
+        }
+    }
 }
 
 struct TextureLoadRequest {
     image_path: PathBuf,
+    cache_key: String,
     output_name: String,
     max_2d: u32,
 }
@@ -971,12 +1657,34 @@ struct TextureLoadRequest {
 enum TextureLoadResult {
     Loaded {
         cache_key: String,
-        image_data: image::RgbaImage,
-        dimensions: (u32, u32),
+        frames: Vec<Frame>,
     },
     Failed {
         cache_key: String,
+        image_path: PathBuf,
+        error: String,
+    },
+}
+
+/// Events sent from the renderer back to the main event loop
+#[derive(Debug)]
+pub enum RenderEvent {
+    /// An image could not be loaded into a texture
+    TextureLoadFailed { image_path: PathBuf, error: String },
+    /// The palette of a freshly decoded image, None if it has no visible pixels
+    PaletteReady {
+        image_path: PathBuf,
+        palette: Option<Palette>,
+    },
+    /// An animated image was decoded, with the delay of every frame
+    AnimationLoaded {
+        image_path: PathBuf,
+        delays: Vec<Duration>,
     },
+    /// A shader compiled, after it changed
+    ShaderLoaded { shader: PathBuf },
+    /// A shader failed to compile, it keeps being drawn as it was before
+    ShaderFailed { shader: PathBuf, error: String },
 }
 
 #[derive(strum::Display)]
@@ -996,6 +1704,11 @@ pub enum RenderCommand {
         output_name: String,
     },
 
+    /// A shader wallpaper changed on disk
+    ShaderChanged {
+        shader: PathBuf,
+    },
+
     /// Render a scene
     RenderScene {
         output_name: String,
@@ -1024,7 +1737,8 @@ impl SceneBufferPool {
                 self.buffers.len()
             );
 
-            const SCENE_PARAM_BUFFER_INIT_SIZE: u64 = 64;
+            // fits the parameters of every kind of node
+            const SCENE_PARAM_BUFFER_INIT_SIZE: u64 = 256;
             let buffer = device.create_buffer(&wgpu::BufferDescriptor {
                 label: Some("scene buffer pool"),
                 size: SCENE_PARAM_BUFFER_INIT_SIZE,
@@ -1035,7 +1749,7 @@ impl SceneBufferPool {
             self.buffers.push(SceneBufferSlot {
                 buffer,
                 capacity: SCENE_PARAM_BUFFER_INIT_SIZE,
-                image_path: None,
+                cache_key: None,
                 bind_group: None,
             });
         }
@@ -1067,12 +1781,74 @@ struct SceneBufferSlot {
     buffer: wgpu::Buffer,
     capacity: u64,
 
-    image_path: Option<PathBuf>,
+    /// Key of the texture and frame the bind group was made for
+    cache_key: Option<String>,
     bind_group: Option<wgpu::BindGroup>,
 }
 
+/// Bind group drawing `texture_view` with the image node parameters in `params`
+fn create_image_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    texture_view: &wgpu::TextureView,
+    params: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("image node bg"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: params.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+/// Write the parameters of a fill into a slot, and bind them unless the slot already drew a fill
+fn prepare_fill_slot(
+    slot: &mut SceneBufferSlot,
+    fills: &mut FillPipeline,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    surface_format: wgpu::TextureFormat,
+    params: &FillNodeParams,
+) {
+    const FILL_KEY: &str = "fill";
+    queue.write_buffer(&slot.buffer, 0, bytemuck::bytes_of(params));
+    if slot.cache_key.as_deref() != Some(FILL_KEY) {
+        slot.bind_group = Some(fills.create_bind_group(device, surface_format, &slot.buffer));
+        slot.cache_key = Some(FILL_KEY.to_string());
+    }
+}
+
 enum PreparedDraw {
-    Image { slot_index: usize },
+    Image {
+        slot_index: usize,
+    },
+    Video {
+        slot_index: usize,
+    },
+    Fill {
+        slot_index: usize,
+    },
+    /// `shader` is the file of a custom transition
+    Transition {
+        slot_index: usize,
+        shader: Option<PathBuf>,
+    },
+    Shader {
+        shader_path: PathBuf,
+    },
 }
 
 pub fn create_surface_from_handles(
@@ -1094,3 +1870,35 @@ pub fn create_surface_from_handles(
     }?;
     Ok(surface)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_key_follows_file() {
+        let image =
+            std::env::temp_dir().join(format!("wayper-cache-key-{}.png", std::process::id()));
+        std::fs::write(&image, "a").unwrap();
+        let file = std::fs::File::options().write(true).open(&image).unwrap();
+        let modified = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        file.set_modified(modified).unwrap();
+        let key = WgpuRenderer::cache_key(&image);
+        assert_eq!(WgpuRenderer::cache_key(&image), key);
+
+        // touched
+        file.set_modified(modified + Duration::from_secs(1))
+            .unwrap();
+        let touched = WgpuRenderer::cache_key(&image);
+        assert_ne!(touched, key);
+
+        // rewritten with the same modification time
+        file.set_len(2).unwrap();
+        file.set_modified(modified + Duration::from_secs(1))
+            .unwrap();
+        assert_ne!(WgpuRenderer::cache_key(&image), touched);
+
+        std::fs::remove_file(&image).unwrap();
+        assert_eq!(WgpuRenderer::cache_key(&image), image.display().to_string());
+    }
+}
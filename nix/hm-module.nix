@@ -5,7 +5,7 @@ self: {
   ...
 }: let
   inherit (builtins) toString;
-  inherit (lib.types) int str nullOr package listOf submodule bool float enum either;
+  inherit (lib.types) int str nullOr package listOf submodule bool float enum either attrsOf;
   inherit (lib.modules) mkIf mkMerge;
   inherit (lib.options) mkOption mkEnableOption;
   inherit (lib.meta) getExe;
@@ -14,7 +14,9 @@ self: {
   # Helper to convert transition type to TOML value
   transitionTypeToToml = type:
     if builtins.isList type
-    then "[${builtins.concatStringsSep ", " (map (t: "\"${t}\"") type)}]"
+    then "[${builtins.concatStringsSep ", " (map transitionTypeToToml type)}]"
+    else if builtins.isAttrs type
+    then "{ shader = \"${type.shader}\" }"
     else "\"${type}\"";
 
   # Slide direction enum
@@ -30,14 +32,47 @@ self: {
   ];
 
   # Transition type enum
-  transitionTypeEnum = enum ["crossfade" "slide"];
+  transitionTypeEnum = enum ["crossfade" "slide" "wipe" "circle" "iris" "dissolve" "zoom" "blur" "pixelate"];
+
+  # A WGSL file with a `transition` function
+  customTransition = submodule {
+    options.shader = mkOption {
+      description = "Path to the WGSL file of the transition";
+      type = str;
+    };
+  };
+
+  transitionType = either transitionTypeEnum customTransition;
+
+  # Transitions drawn by a shader, each with a table of parameters
+  shaderTransitions = ["wipe" "circle" "dissolve" "zoom" "blur" "pixelate"];
+
+  # Optional transition parameter, left out of the TOML when unset
+  paramOption = type: description:
+    mkOption {
+      inherit description;
+      type = nullOr type;
+      default = null;
+    };
+
+  # Helper to convert a parameter to a TOML value
+  paramToToml = value:
+    if builtins.isString value
+    then "\"${value}\""
+    else if builtins.isList value
+    then "[${builtins.concatStringsSep ", " (map paramToToml value)}]"
+    else toString value;
+
+  # Whether any parameter of a shader transition is set
+  hasShaderParams = transition:
+    builtins.any (name: builtins.any (value: value != null) (builtins.attrValues transition.${name})) shaderTransitions;
 
   # Transition configuration submodule
   transitionSubmodule = submodule {
     options = {
       type = mkOption {
         description = "Transition type (single value or list for random selection)";
-        type = nullOr (either transitionTypeEnum (listOf transitionTypeEnum));
+        type = nullOr (either transitionType (listOf transitionType));
         default = null;
       };
       duration_ms = mkOption {
@@ -50,6 +85,11 @@ self: {
         type = nullOr int;
         default = null;
       };
+      easing = mkOption {
+        description = "Transition easing, like \"ease-in-out-cubic\", \"cubic-bezier(0.25, 0.1, 0.25, 1)\" or \"spring(100, 10)\"";
+        type = nullOr str;
+        default = null;
+      };
       slide = {
         direction = mkOption {
           description = "Slide direction";
@@ -57,6 +97,33 @@ self: {
           default = null;
         };
       };
+      wipe = {
+        direction = paramOption slideDirections "Wipe direction";
+        softness = paramOption float "Width of the wipe edge, in fractions of the distance it travels";
+      };
+      circle = {
+        center = paramOption (listOf float) "Center of the circle in fractions of the output, random when unset";
+        softness = paramOption float "Width of the circle edge, in fractions of the output diagonal";
+      };
+      dissolve = {
+        seed = paramOption int "Noise seed, random when unset";
+        scale = paramOption float "Size of the noise in pixels";
+        softness = paramOption float "Width of the dissolving edge";
+      };
+      zoom = {
+        scale = paramOption float "How far the images zoom";
+      };
+      blur = {
+        radius = paramOption float "Blur radius halfway through, in pixels";
+      };
+      pixelate = {
+        size = paramOption float "Pixel size halfway through";
+      };
+      params = mkOption {
+        description = "Values handed to a custom transition shader as the fields of `params`";
+        type = attrsOf float;
+        default = {};
+      };
     };
   };
 
@@ -75,6 +142,10 @@ self: {
       if transition.fps != null
       then "fps = ${toString transition.fps}\n"
       else "";
+    easingStr =
+      if transition.easing != null
+      then "easing = \"${transition.easing}\"\n"
+      else "";
     slideStr =
       if transition.slide.direction != null
       then let
@@ -84,7 +155,18 @@ self: {
           else "";
       in "\n[${prefix}.slide]\n${dirStr}"
       else "";
-  in "${typeStr}${durationStr}${fpsStr}${slideStr}";
+    paramsStr = builtins.concatStringsSep "" (map (name: let
+        params = lib.filterAttrs (_: value: value != null) transition.${name};
+      in
+        if params == {}
+        then ""
+        else "\n[${prefix}.${name}]\n${builtins.concatStringsSep "" (lib.mapAttrsToList (key: value: "  ${key} = ${paramToToml value}\n") params)}")
+      shaderTransitions);
+    userParamsStr =
+      if transition.params != {}
+      then "\n[${prefix}.params]\n${builtins.concatStringsSep "" (lib.mapAttrsToList (key: value: "  ${key} = ${toString value}\n") transition.params)}"
+      else "";
+  in "${typeStr}${durationStr}${fpsStr}${easingStr}${slideStr}${paramsStr}${userParamsStr}";
 in {
   options.services.wayper = {
     enable = mkEnableOption "Wayper, the homebrewed wallpaper daemon";
@@ -174,7 +256,10 @@ in {
               cfg.config.transition.type != null
               || cfg.config.transition.duration_ms != null
               || cfg.config.transition.fps != null
-              || cfg.config.transition.slide.direction != null;
+              || cfg.config.transition.easing != null
+              || cfg.config.transition.slide.direction != null
+              || hasShaderParams cfg.config.transition
+              || cfg.config.transition.params != {};
           in
             if hasGlobalTransition
             then "[transition]\n${generateTransitionBlock "transition" cfg.config.transition}"
@@ -186,7 +271,10 @@ in {
               monitor.transition.type != null
               || monitor.transition.duration_ms != null
               || monitor.transition.fps != null
-              || monitor.transition.slide.direction != null;
+              || monitor.transition.easing != null
+              || monitor.transition.slide.direction != null
+              || hasShaderParams monitor.transition
+              || monitor.transition.params != {};
             transitionBlock =
               if hasMonitorTransition
               then "\n[${monitor.profile}.${monitor.name}.transition]\n${generateTransitionBlock "${monitor.profile}.${monitor.name}.transition" monitor.transition}"